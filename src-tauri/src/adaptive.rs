@@ -0,0 +1,113 @@
+//! Output scale that backs off while frames take longer to produce than the
+//! frame budget, keeping the app responsive on machines the full rate would
+//! saturate.
+//!
+//! Processing times are averaged over a second. The scale coarsens a step
+//! while they run over budget, and refines a step only when the finer scale
+//! would still leave headroom, so it doesn't oscillate between two.
+
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// Processing times are averaged over this long before the scale changes.
+const WINDOW: Duration = Duration::from_secs(1);
+/// Share of the frame budget above which processing counts as saturated.
+const OVER_BUDGET: f64 = 0.9;
+/// Share of the budget the finer scale is predicted to use below which
+/// quality is restored.
+const RESTORE_BELOW: f64 = 0.7;
+
+/// The range of scale factors adaptation may pick from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdaptiveBounds {
+    pub min_scale: u32,
+    pub max_scale: u32,
+}
+
+impl AdaptiveBounds {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_scale == 0 || self.min_scale > self.max_scale {
+            return Err("Scale bounds must be non-zero with min_scale at most max_scale".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Payload of `quality-adapted`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Adaptation {
+    scale_factor: u32,
+    /// Whether quality went down rather than back up.
+    reduced: bool,
+    /// Average time to produce a frame over the last second.
+    processing_ms: f32,
+    budget_ms: f32,
+}
+
+#[derive(Default)]
+pub struct AdaptiveScale {
+    bounds: Option<AdaptiveBounds>,
+    scale: u32,
+    window_start: Option<Instant>,
+    total: Duration,
+    frames: u32,
+}
+
+impl AdaptiveScale {
+    /// Adapts within `bounds`, starting from `base` clamped to them; `None`
+    /// stops adapting. Restarts only when the bounds change.
+    pub fn configure(&mut self, bounds: Option<AdaptiveBounds>, base: u32) {
+        if bounds != self.bounds {
+            *self = AdaptiveScale {
+                bounds,
+                scale: bounds.map_or(base, |bounds| base.clamp(bounds.min_scale, bounds.max_scale)),
+                ..Default::default()
+            };
+        }
+    }
+
+    /// The adapted scale factor, or `None` while not adapting.
+    pub fn scale_factor(&self) -> Option<u32> {
+        self.bounds.map(|_| self.scale)
+    }
+
+    /// Records how long a frame took against the time available for one,
+    /// returning the change when the scale moves.
+    pub fn record(&mut self, processing: Duration, budget: Duration) -> Option<Adaptation> {
+        let bounds = self.bounds?;
+        let now = Instant::now();
+        let started = *self.window_start.get_or_insert(now);
+        self.total += processing;
+        self.frames += 1;
+        if now.duration_since(started) < WINDOW || budget.is_zero() {
+            return None;
+        }
+        let average = self.total / self.frames;
+        self.window_start = Some(now);
+        self.total = Duration::ZERO;
+        self.frames = 0;
+
+        let load = average.as_secs_f64() / budget.as_secs_f64();
+        let scale = if load > OVER_BUDGET {
+            (self.scale + 1).min(bounds.max_scale)
+        } else if self.scale > bounds.min_scale {
+            // Work goes with the pixel count, i.e. the square of the scale
+            let finer = self.scale - 1;
+            let predicted = load * (self.scale as f64 / finer as f64).powi(2);
+            if predicted < RESTORE_BELOW { finer } else { self.scale }
+        } else {
+            self.scale
+        };
+        if scale == self.scale {
+            return None;
+        }
+        let reduced = scale > self.scale;
+        self.scale = scale;
+        Some(Adaptation {
+            scale_factor: scale,
+            reduced,
+            processing_ms: average.as_secs_f32() * 1000.0,
+            budget_ms: budget.as_secs_f32() * 1000.0,
+        })
+    }
+}
@@ -0,0 +1,81 @@
+//! Encoder quality that follows a target stream bitrate.
+//!
+//! Encoded sizes over the last second give the current rate; quality drops
+//! quickly while it is over the target and creeps back up while under, so a
+//! busy scene gets cheaper frames and a static one recovers detail.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Encoded sizes older than this no longer count towards the rate.
+const WINDOW: Duration = Duration::from_secs(1);
+/// Below this the quantizer leaves too little of the image to be useful.
+const MIN_QUALITY: u8 = 10;
+/// Quality when no target is set, and the starting point for a new one.
+const DEFAULT_QUALITY: u8 = 80;
+
+pub struct QualityController {
+    /// Target in bytes per second.
+    target: Option<u64>,
+    quality: u8,
+    /// When each recent frame was encoded, and its size in bytes.
+    recent: VecDeque<(Instant, usize)>,
+}
+
+impl Default for QualityController {
+    fn default() -> Self {
+        Self { target: None, quality: DEFAULT_QUALITY, recent: VecDeque::new() }
+    }
+}
+
+impl QualityController {
+    /// `None` stops adapting and restores the default quality.
+    pub fn set_target(&mut self, kbps: Option<u32>) {
+        self.target = kbps.map(|kbps| kbps as u64 * 1000 / 8);
+        self.quality = DEFAULT_QUALITY;
+        self.recent.clear();
+    }
+
+    /// The target in kilobits per second, as passed to `set_target`.
+    pub fn target_kbps(&self) -> Option<u32> {
+        self.target.map(|bytes| (bytes * 8 / 1000) as u32)
+    }
+
+    /// Quality for the next frame.
+    pub fn quality(&self) -> u8 {
+        self.quality
+    }
+
+    /// The adapted quality, or `None` while no target is set.
+    pub fn current(&self) -> Option<u8> {
+        self.target.map(|_| self.quality)
+    }
+
+    /// Records a frame's encoded size and adjusts quality for the next one.
+    pub fn record(&mut self, size: usize) {
+        let Some(target) = self.target else {
+            return;
+        };
+        let now = Instant::now();
+        self.recent.push_back((now, size));
+        while self.recent.front().is_some_and(|&(at, _)| now.duration_since(at) > WINDOW) {
+            self.recent.pop_front();
+        }
+        // The first frames say little about the rate yet
+        let span = now.duration_since(self.recent[0].0);
+        if span < WINDOW / 4 {
+            return;
+        }
+        let bytes: usize = self.recent.iter().map(|&(_, size)| size).sum();
+        let ratio = bytes as f64 / span.as_secs_f64() / target as f64;
+        let quality = if ratio > 1.1 {
+            // Proportional to the overshoot, so a sudden burst is reined in fast
+            self.quality.saturating_sub(((ratio - 1.0) * 20.0).clamp(1.0, 10.0) as u8)
+        } else if ratio < 0.8 {
+            self.quality.saturating_add(1)
+        } else {
+            self.quality
+        };
+        self.quality = quality.clamp(MIN_QUALITY, 100);
+    }
+}
@@ -0,0 +1,79 @@
+//! Burst capture of consecutive frames.
+//!
+//! While a burst is pending the capture loop feeds it instead of the preview,
+//! so frames arrive as fast as the duplication delivers them.
+
+use std::time::Instant;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+/// Most frames a single burst may request.
+pub const MAX_BURST_FRAMES: u32 = 120;
+/// Ceiling on the memory a burst may hold, checked against its first frame.
+const MAX_BURST_BYTES: usize = 1024 * 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct BurstFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Milliseconds since the first frame of the burst.
+    timestamp_ms: f64,
+    sequence: u32,
+}
+
+#[derive(Serialize)]
+pub struct Burst {
+    frames: Vec<BurstFrame>,
+    /// Milliseconds between consecutive frames.
+    intervals_ms: Vec<f64>,
+    /// Frames per second over the whole burst.
+    fps: f64,
+}
+
+/// A burst in progress, owned by the capture loop until it completes.
+pub struct BurstRequest {
+    pub divisor: u32,
+    count: u32,
+    started: Option<Instant>,
+    frames: Vec<BurstFrame>,
+    sender: oneshot::Sender<Result<Burst, String>>,
+}
+
+impl BurstRequest {
+    pub fn new(count: u32, divisor: u32, sender: oneshot::Sender<Result<Burst, String>>) -> Self {
+        Self { divisor, count, started: None, frames: Vec::with_capacity(count as usize), sender }
+    }
+
+    /// Adds a frame, returning `None` once the burst has been answered.
+    pub fn push(mut self, data: Vec<u8>, width: u32, height: u32, sequence: u32) -> Option<Self> {
+        if self.frames.is_empty() && data.len().saturating_mul(self.count as usize) > MAX_BURST_BYTES {
+            let _ = self.sender.send(Err(format!(
+                "A burst of {} {}x{} frames would exceed {} MB; use fewer frames or a larger scale factor",
+                self.count,
+                width,
+                height,
+                MAX_BURST_BYTES / (1024 * 1024)
+            )));
+            return None;
+        }
+
+        let now = Instant::now();
+        let started = *self.started.get_or_insert(now);
+        let timestamp_ms = now.duration_since(started).as_secs_f64() * 1000.0;
+        self.frames.push(BurstFrame { data, width, height, timestamp_ms, sequence });
+        if self.frames.len() < self.count as usize {
+            return Some(self);
+        }
+
+        let intervals_ms: Vec<f64> = self
+            .frames
+            .windows(2)
+            .map(|pair| pair[1].timestamp_ms - pair[0].timestamp_ms)
+            .collect();
+        let total_ms: f64 = intervals_ms.iter().sum();
+        let fps = if total_ms > 0.0 { intervals_ms.len() as f64 * 1000.0 / total_ms } else { 0.0 };
+        let _ = self.sender.send(Ok(Burst { frames: self.frames, intervals_ms, fps }));
+        None
+    }
+}
@@ -0,0 +1,138 @@
+//! Probing what this system supports, so the UI can offer only what works.
+
+use serde::Serialize;
+use windows::core::Interface;
+use windows::Wdk::System::SystemServices::RtlGetVersion;
+use windows::Win32::Foundation::HMODULE;
+use windows::Win32::Graphics::Direct3D::{
+    D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_9_3,
+};
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11VideoDevice, D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_SDK_VERSION,
+};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+use windows::Win32::Graphics::Dxgi::{IDXGIAdapter, IDXGIOutput6};
+use windows::Win32::System::SystemInformation::OSVERSIONINFOW;
+
+use crate::displays;
+
+/// First Windows 10 build (2004) honouring `WDA_EXCLUDEFROMCAPTURE`.
+const EXCLUDE_FROM_CAPTURE_BUILD: u32 = 19041;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Capabilities {
+    displays: u32,
+    adapters: Vec<String>,
+    /// Whether the OS can exclude our window from capture rather than
+    /// blacking it out.
+    exclude_from_capture: bool,
+    /// Whether any display is running in HDR.
+    hdr: bool,
+    /// Largest texture side the primary adapter supports.
+    max_texture_dimension: u32,
+    /// Whether the primary adapter has a video processor for GPU scaling.
+    gpu_scaling: bool,
+    /// Current refresh rate of each display, in index order; `None` where
+    /// it couldn't be read.
+    refresh_rates: Vec<Option<f64>>,
+    /// Whether any adapter supports variable refresh; see
+    /// `displays::variable_refresh`.
+    variable_refresh: bool,
+    /// Windows build number.
+    os_build: Option<u32>,
+}
+
+pub fn probe() -> Capabilities {
+    let all = displays::all();
+
+    let mut adapters: Vec<String> = Vec::new();
+    for (adapter, _) in &all {
+        let name = adapter.name();
+        if !adapters.contains(&name) {
+            adapters.push(name);
+        }
+    }
+
+    let hdr = all.iter().any(|(_, display)| {
+        let output: IDXGIOutput6 = unsafe { crate::gpu::rewrap(display.as_raw_ref()) };
+        unsafe { output.GetDesc1() }.is_ok_and(|desc| desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020)
+    });
+
+    let refresh_rates = all
+        .iter()
+        .map(|(_, display)| {
+            display
+                .get_current_display_mode()
+                .ok()
+                .filter(|mode| mode.refresh_num > 0 && mode.refresh_den > 0)
+                .map(|mode| mode.refresh_num as f64 / mode.refresh_den as f64)
+        })
+        .collect();
+
+    let variable_refresh = all.iter().any(|(adapter, _)| displays::variable_refresh(adapter));
+
+    let device = all.first().and_then(|(adapter, _)| {
+        let adapter: IDXGIAdapter = unsafe { crate::gpu::rewrap(adapter.as_raw_ref()) };
+        create_device(&adapter)
+    });
+    let (max_texture_dimension, gpu_scaling) = match &device {
+        Some((device, level)) => (max_texture_dimension(*level), device.cast::<ID3D11VideoDevice>().is_ok()),
+        None => (0, false),
+    };
+
+    let os_build = os_build();
+    Capabilities {
+        displays: all.len() as u32,
+        adapters,
+        exclude_from_capture: os_build.is_some_and(|build| build >= EXCLUDE_FROM_CAPTURE_BUILD),
+        hdr,
+        max_texture_dimension,
+        gpu_scaling,
+        refresh_rates,
+        variable_refresh,
+        os_build,
+    }
+}
+
+fn create_device(adapter: &IDXGIAdapter) -> Option<(ID3D11Device, D3D_FEATURE_LEVEL)> {
+    let mut device = None;
+    let mut level = D3D_FEATURE_LEVEL::default();
+    unsafe {
+        D3D11CreateDevice(
+            adapter,
+            D3D_DRIVER_TYPE_UNKNOWN,
+            HMODULE::default(),
+            D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+            None,
+            D3D11_SDK_VERSION,
+            Some(&mut device),
+            Some(&mut level),
+            None,
+        )
+    }
+    .ok()?;
+    device.map(|device| (device, level))
+}
+
+/// `D3D11_REQ_TEXTURE2D_U_OR_V_DIMENSION` and its down-level equivalents.
+fn max_texture_dimension(level: D3D_FEATURE_LEVEL) -> u32 {
+    if level.0 >= D3D_FEATURE_LEVEL_11_0.0 {
+        16384
+    } else if level.0 >= D3D_FEATURE_LEVEL_10_0.0 {
+        8192
+    } else if level.0 >= D3D_FEATURE_LEVEL_9_3.0 {
+        4096
+    } else {
+        2048
+    }
+}
+
+/// The real OS build number; unlike `GetVersionEx`, `RtlGetVersion` isn't
+/// subject to manifest-based version lies.
+fn os_build() -> Option<u32> {
+    let mut info = OSVERSIONINFOW {
+        dwOSVersionInfoSize: size_of::<OSVERSIONINFOW>() as u32,
+        ..Default::default()
+    };
+    unsafe { RtlGetVersion(&mut info) }.is_ok().then_some(info.dwBuildNumber)
+}
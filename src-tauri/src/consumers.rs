@@ -0,0 +1,134 @@
+//! Who is receiving frames, through which delivery path, and how fast.
+//!
+//! Paths with a connection register when it opens and unregister when it
+//! closes; polling and events have none, so they count as active while
+//! frames are going out and are forgotten once idle.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+/// Deliveries older than this no longer count towards the rate.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// Connectionless consumers are dropped after this long without a delivery.
+const IDLE_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum ConsumerId {
+    /// Calls to `get_frame_data`.
+    Polling,
+    /// `frame-ready` events.
+    FrameEvents,
+    /// The channel from `start_frame_channel`.
+    FrameChannel,
+    /// A stream from `open_stream`, by id.
+    Stream(u32),
+    /// The video recording.
+    Recording,
+}
+
+impl ConsumerId {
+    /// What a consumer without a connection receives; `None` for the rest.
+    fn implied_format(self) -> Option<&'static str> {
+        match self {
+            ConsumerId::Polling => Some("RGBA"),
+            ConsumerId::FrameEvents => Some("Sequence numbers"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConsumerInfo {
+    id: ConsumerId,
+    /// What the consumer receives, e.g. its negotiated codec and size.
+    format: String,
+    /// Deliveries over the last second.
+    fps: f32,
+    delivered: u64,
+    /// Frames it missed: skipped while it was busy, coalesced or refused.
+    dropped: u64,
+    /// Seconds since it was registered.
+    age_secs: f32,
+}
+
+struct Consumer {
+    format: String,
+    registered: Instant,
+    recent: VecDeque<Instant>,
+    delivered: u64,
+    dropped: u64,
+}
+
+#[derive(Default)]
+pub struct Consumers {
+    active: HashMap<ConsumerId, Consumer>,
+}
+
+impl Consumers {
+    /// Starts tracking `id`, resetting its counts if it was already tracked.
+    pub fn register(&mut self, id: ConsumerId, format: String) {
+        let consumer = Consumer {
+            format,
+            registered: Instant::now(),
+            recent: VecDeque::new(),
+            delivered: 0,
+            dropped: 0,
+        };
+        self.active.insert(id, consumer);
+    }
+
+    pub fn unregister(&mut self, id: ConsumerId) {
+        self.active.remove(&id);
+    }
+
+    /// Counts a frame delivered to `id`, registering connectionless
+    /// consumers on first use.
+    pub fn delivered(&mut self, id: ConsumerId) {
+        let Some(consumer) = self.get(id) else {
+            return;
+        };
+        let now = Instant::now();
+        consumer.delivered += 1;
+        consumer.recent.push_back(now);
+        while consumer.recent.front().is_some_and(|&at| now.duration_since(at) > RATE_WINDOW) {
+            consumer.recent.pop_front();
+        }
+    }
+
+    /// Counts a frame `id` missed.
+    pub fn dropped(&mut self, id: ConsumerId) {
+        if let Some(consumer) = self.get(id) {
+            consumer.dropped += 1;
+        }
+    }
+
+    fn get(&mut self, id: ConsumerId) -> Option<&mut Consumer> {
+        if let Some(format) = id.implied_format().filter(|_| !self.active.contains_key(&id)) {
+            self.register(id, format.to_string());
+        }
+        self.active.get_mut(&id)
+    }
+
+    pub fn list(&mut self) -> Vec<ConsumerInfo> {
+        let now = Instant::now();
+        self.active.retain(|id, consumer| {
+            let last = consumer.recent.back().copied().unwrap_or(consumer.registered);
+            id.implied_format().is_none() || now.duration_since(last) < IDLE_AFTER
+        });
+        let mut list: Vec<ConsumerInfo> = self
+            .active
+            .iter()
+            .map(|(&id, consumer)| ConsumerInfo {
+                id,
+                format: consumer.format.clone(),
+                fps: consumer.recent.iter().filter(|&&at| now.duration_since(at) <= RATE_WINDOW).count() as f32,
+                delivered: consumer.delivered,
+                dropped: consumer.dropped,
+                age_secs: now.duration_since(consumer.registered).as_secs_f32(),
+            })
+            .collect();
+        list.sort_by(|a, b| b.age_secs.total_cmp(&a.age_secs));
+        list
+    }
+}
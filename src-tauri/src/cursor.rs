@@ -0,0 +1,203 @@
+//! Software cursor compositing.
+//!
+//! The duplication's own cursor drawing is opaque and fixed, so the pointer
+//! is read from GDI instead and blended over the processed frame, or handed
+//! to consumers that draw it themselves.
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::Foundation::POINT;
+use windows::Win32::Graphics::Gdi::DeleteObject;
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetCursorInfo, GetCursorPos, GetIconInfo, CURSORINFO, CURSOR_SHOWING, HICON, ICONINFO,
+};
+
+use crate::gdi::read_bitmap;
+use crate::{scale_coord, Rect};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CursorMode {
+    #[default]
+    Hidden,
+    /// Blend the pointer over the frame, scaled by `opacity` in `0.0..=1.0`.
+    Shown { opacity: f32 },
+}
+
+/// A cursor shape as straight-alpha RGBA.
+struct CursorImage {
+    width: u32,
+    height: u32,
+    hotspot: (i32, i32),
+    rgba: Vec<u8>,
+}
+
+/// The visible cursor, for frontends drawing their own overlay.
+#[derive(Clone, Debug, Serialize)]
+pub struct CursorShape {
+    /// Changes whenever the shape does, so unchanged shapes can be skipped.
+    version: u32,
+    width: u32,
+    height: u32,
+    hotspot_x: i32,
+    hotspot_y: i32,
+    /// Straight-alpha RGBA.
+    rgba: Vec<u8>,
+    /// Pointer position on the virtual desktop.
+    x: i32,
+    y: i32,
+}
+
+/// Reads the current cursor, caching its bitmap until the shape changes.
+#[derive(Default)]
+pub struct CursorCapture {
+    /// Handle of the cached shape; kept as an integer so the loop stays `Send`.
+    handle: usize,
+    version: u32,
+    image: Option<CursorImage>,
+}
+
+impl CursorCapture {
+    /// Blends the cursor, if visible, over a `width`×`height` RGBA frame made
+    /// from `crop` of the display at `origin`.
+    pub fn composite(&mut self, data: &mut [u8], width: u32, height: u32, crop: Rect, origin: (i32, i32), opacity: f32) {
+        let Some(info) = self.refresh() else {
+            return;
+        };
+        let Some(image) = &self.image else {
+            return;
+        };
+        if crop.width == 0 || crop.height == 0 {
+            return;
+        }
+
+        // Cursor top-left in crop-relative source pixels
+        let left = info.0.ptScreenPos.x - origin.0 - image.hotspot.0 - crop.x as i32;
+        let top = info.0.ptScreenPos.y - origin.1 - image.hotspot.1 - crop.y as i32;
+        let opacity = (opacity.clamp(0.0, 1.0) * 256.0) as u32;
+
+        for y in 0..height {
+            let cy = scale_coord(y, crop.height, height) as i32 - top;
+            if cy < 0 || cy >= image.height as i32 {
+                continue;
+            }
+            for x in 0..width {
+                let cx = scale_coord(x, crop.width, width) as i32 - left;
+                if cx < 0 || cx >= image.width as i32 {
+                    continue;
+                }
+                let src = ((cy as u32 * image.width + cx as u32) * 4) as usize;
+                let alpha = image.rgba[src + 3] as u32 * opacity / 256;
+                if alpha == 0 {
+                    continue;
+                }
+                let dst = crate::pixel_offset(x, y, width);
+                let Some(pixel) = data.get_mut(dst..dst + 4) else {
+                    continue;
+                };
+                for (channel, &cursor) in pixel[..3].iter_mut().zip(&image.rgba[src..src + 3]) {
+                    *channel = ((cursor as u32 * alpha + *channel as u32 * (255 - alpha)) / 255) as u8;
+                }
+                pixel[3] = (alpha + pixel[3] as u32 * (255 - alpha) / 255) as u8;
+            }
+        }
+    }
+
+    /// The cursor's shape and position, or `None` while it is hidden.
+    pub fn shape(&mut self) -> Option<CursorShape> {
+        let info = self.refresh()?;
+        let image = self.image.as_ref()?;
+        Some(CursorShape {
+            version: self.version,
+            width: image.width,
+            height: image.height,
+            hotspot_x: image.hotspot.0,
+            hotspot_y: image.hotspot.1,
+            rgba: image.rgba.clone(),
+            x: info.0.ptScreenPos.x,
+            y: info.0.ptScreenPos.y,
+        })
+    }
+
+    /// Pointer position on the virtual desktop, or `None` while it is hidden.
+    pub fn position(&mut self) -> Option<(i32, i32)> {
+        self.refresh().map(|info| (info.0.ptScreenPos.x, info.0.ptScreenPos.y))
+    }
+
+    /// Queries the visible cursor, re-reading its bitmap only if the shape changed.
+    fn refresh(&mut self) -> Option<CursorInfo> {
+        let mut info = CursorInfo::default();
+        if unsafe { GetCursorInfo(&mut info.0) }.is_err() || info.0.flags.0 & CURSOR_SHOWING.0 == 0 {
+            return None;
+        }
+        let handle = info.0.hCursor.0 as usize;
+        if handle != self.handle {
+            self.handle = handle;
+            self.version = self.version.wrapping_add(1);
+            self.image = unsafe { read_cursor(HICON(info.0.hCursor.0)) };
+        }
+        Some(info)
+    }
+}
+
+/// Pointer position on the virtual desktop, even while the cursor is hidden.
+pub fn pointer_position() -> Option<(i32, i32)> {
+    let mut point = POINT::default();
+    unsafe { GetCursorPos(&mut point) }.ok()?;
+    Some((point.x, point.y))
+}
+
+struct CursorInfo(CURSORINFO);
+
+impl Default for CursorInfo {
+    fn default() -> Self {
+        Self(CURSORINFO { cbSize: size_of::<CURSORINFO>() as u32, ..Default::default() })
+    }
+}
+
+/// Converts a cursor to RGBA, honouring its alpha channel or, for cursors
+/// without one, its AND mask.
+unsafe fn read_cursor(icon: HICON) -> Option<CursorImage> {
+    let mut icon_info = ICONINFO::default();
+    GetIconInfo(icon, &mut icon_info).ok()?;
+    let hotspot = (icon_info.xHotspot as i32, icon_info.yHotspot as i32);
+
+    let mask = read_bitmap(icon_info.hbmMask);
+    let color = (!icon_info.hbmColor.is_invalid()).then(|| read_bitmap(icon_info.hbmColor)).flatten();
+    let _ = DeleteObject(icon_info.hbmMask.into());
+    if !icon_info.hbmColor.is_invalid() {
+        let _ = DeleteObject(icon_info.hbmColor.into());
+    }
+    let (mask_width, mask_height, mask) = mask?;
+
+    let (width, height, mut rgba) = match color {
+        Some((width, height, bgra)) => {
+            let has_alpha = bgra.chunks_exact(4).any(|p| p[3] != 0);
+            let mut rgba: Vec<u8> = bgra.chunks_exact(4).flat_map(|p| [p[2], p[1], p[0], p[3]]).collect();
+            if !has_alpha {
+                // Opaque wherever the AND mask is clear
+                for (pixel, and) in rgba.chunks_exact_mut(4).zip(mask.chunks_exact(4)) {
+                    pixel[3] = if and[0] == 0 { 255 } else { 0 };
+                }
+            }
+            (width, height, rgba)
+        }
+        None => {
+            // Monochrome: AND mask on top, XOR mask below
+            let height = mask_height / 2;
+            let (and, xor) = mask.split_at((mask_width * height * 4) as usize);
+            let rgba = and
+                .chunks_exact(4)
+                .zip(xor.chunks_exact(4))
+                .flat_map(|(and, xor)| match (and[0] != 0, xor[0] != 0) {
+                    (false, false) => [0, 0, 0, 255],
+                    (false, true) => [255, 255, 255, 255],
+                    // Screen inversion can't be reproduced; show it as a solid outline colour
+                    (true, true) => [0, 0, 0, 255],
+                    (true, false) => [0, 0, 0, 0],
+                })
+                .collect();
+            (mask_width, height, rgba)
+        }
+    };
+    rgba.truncate((width * height * 4) as usize);
+    Some(CursorImage { width, height, hotspot, rgba })
+}
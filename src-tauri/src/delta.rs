@@ -0,0 +1,231 @@
+//! Tile-based delta encoding of processed frames.
+//!
+//! After a keyframe carrying every tile, each frame only carries the tiles
+//! whose contents changed. Consumers apply tiles in order onto their copy of
+//! the previous frame.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use serde::{Deserialize, Serialize};
+
+use crate::{frame_len, pixel_offset, Rect};
+
+/// Side length of a delta tile in output pixels.
+pub const TILE_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaConfig {
+    /// Send a full keyframe after this many patches; zero never forces one.
+    pub keyframe_interval: u32,
+}
+
+/// A rectangle of changed RGBA pixels, tightly packed.
+#[derive(Clone, Debug, Serialize)]
+pub struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FramePatch {
+    sequence: u32,
+    keyframe: bool,
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+}
+
+impl FramePatch {
+    pub fn is_empty(&self) -> bool {
+        !self.keyframe && self.tiles.is_empty()
+    }
+}
+
+pub struct DeltaEncoder {
+    config: DeltaConfig,
+    width: u32,
+    height: u32,
+    hashes: Vec<u64>,
+    since_keyframe: u32,
+    force_keyframe: bool,
+}
+
+impl DeltaEncoder {
+    pub fn new(config: DeltaConfig) -> Self {
+        Self {
+            config,
+            width: 0,
+            height: 0,
+            hashes: Vec::new(),
+            since_keyframe: 0,
+            force_keyframe: true,
+        }
+    }
+
+    pub fn config(&self) -> DeltaConfig {
+        self.config
+    }
+
+    /// Makes the next patch a keyframe, e.g. when a new consumer attaches.
+    pub fn request_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    /// Encodes a `width`×`height` RGBA frame against the previously encoded one.
+    pub fn encode(&mut self, sequence: u32, data: &[u8], width: u32, height: u32) -> FramePatch {
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+
+        let keyframe = self.force_keyframe
+            || width != self.width
+            || height != self.height
+            || (self.config.keyframe_interval > 0 && self.since_keyframe >= self.config.keyframe_interval);
+
+        if keyframe {
+            self.width = width;
+            self.height = height;
+            self.hashes = vec![0; (tiles_x * tiles_y) as usize];
+            self.since_keyframe = 0;
+            self.force_keyframe = false;
+        } else {
+            self.since_keyframe += 1;
+        }
+
+        let mut tiles = Vec::new();
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let rect = tile_rect(tx, ty, width, height);
+                let hash = hash_tile(data, width, rect);
+                let slot = &mut self.hashes[(ty * tiles_x + tx) as usize];
+                if keyframe || *slot != hash {
+                    *slot = hash;
+                    tiles.push(copy_tile(data, width, rect));
+                }
+            }
+        }
+
+        FramePatch { sequence, keyframe, width, height, tiles }
+    }
+}
+
+fn tile_rows(data: &[u8], stride_width: u32, rect: Rect) -> impl Iterator<Item = &[u8]> {
+    (rect.y..rect.y + rect.height).filter_map(move |row| {
+        let start = pixel_offset(rect.x, row, stride_width);
+        data.get(start..start + rect.width as usize * 4)
+    })
+}
+
+fn hash_tile(data: &[u8], stride_width: u32, rect: Rect) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for row in tile_rows(data, stride_width, rect) {
+        row.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn copy_tile(data: &[u8], stride_width: u32, rect: Rect) -> Tile {
+    let mut pixels = Vec::with_capacity(frame_len(rect.width, rect.height).unwrap_or(0));
+    for row in tile_rows(data, stride_width, rect) {
+        pixels.extend_from_slice(row);
+    }
+    Tile { x: rect.x, y: rect.y, width: rect.width, height: rect.height, data: pixels }
+}
+
+/// When each tile of the published frame last changed, so a poller can be
+/// sent just the area that changed since the frame it has.
+#[derive(Default, Clone)]
+pub struct DirtyTiles {
+    width: u32,
+    height: u32,
+    hashes: Vec<u64>,
+    changed_at: Vec<u32>,
+    /// First sequence tracked at this size; older frames can't be diffed.
+    since: u32,
+}
+
+/// The part of a frame that changed since an earlier sequence.
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameDelta {
+    sequence: u32,
+    /// Set when `rect` is the whole frame because the earlier one isn't tracked.
+    full: bool,
+    width: u32,
+    height: u32,
+    /// Bounding box of the changed tiles; empty when nothing changed.
+    rect: Rect,
+    data: Vec<u8>,
+}
+
+impl DirtyTiles {
+    /// Records frame `sequence`, a `width`×`height` RGBA image.
+    pub fn update(&mut self, sequence: u32, data: &[u8], width: u32, height: u32) {
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        if (width, height) != (self.width, self.height) {
+            *self = DirtyTiles {
+                width,
+                height,
+                hashes: vec![0; (tiles_x * tiles_y) as usize],
+                changed_at: vec![sequence; (tiles_x * tiles_y) as usize],
+                since: sequence,
+            };
+        }
+
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let hash = hash_tile(data, width, tile_rect(tx, ty, width, height));
+                let slot = (ty * tiles_x + tx) as usize;
+                if self.hashes[slot] != hash {
+                    self.hashes[slot] = hash;
+                    self.changed_at[slot] = sequence;
+                }
+            }
+        }
+    }
+
+    /// Extracts from frame `sequence` everything changed after `since_sequence`.
+    pub fn delta(&self, since_sequence: u32, sequence: u32, data: &[u8]) -> FrameDelta {
+        let (width, height) = (self.width, self.height);
+        let full = since_sequence < self.since || since_sequence > sequence;
+        let rect = if full { Rect { x: 0, y: 0, width, height } } else { self.changed_since(since_sequence) };
+        let data = copy_tile(data, width, rect).data;
+        FrameDelta { sequence, full, width, height, rect, data }
+    }
+
+    /// Bounding box of the tiles changed after `since_sequence`; empty when
+    /// nothing changed. The whole frame if that sequence isn't tracked.
+    pub fn changed_since(&self, since_sequence: u32) -> Rect {
+        let (width, height) = (self.width, self.height);
+        if since_sequence < self.since {
+            return Rect { x: 0, y: 0, width, height };
+        }
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        self.changed_at
+            .iter()
+            .enumerate()
+            .filter(|(_, &changed)| changed > since_sequence)
+            .map(|(slot, _)| tile_rect(slot as u32 % tiles_x, slot as u32 / tiles_x, width, height))
+            .reduce(|a, b| {
+                let x = a.x.min(b.x);
+                let y = a.y.min(b.y);
+                Rect {
+                    x,
+                    y,
+                    width: (a.x + a.width).max(b.x + b.width) - x,
+                    height: (a.y + a.height).max(b.y + b.height) - y,
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn tile_rect(tx: u32, ty: u32, width: u32, height: u32) -> Rect {
+    Rect {
+        x: tx * TILE_SIZE,
+        y: ty * TILE_SIZE,
+        width: TILE_SIZE.min(width - tx * TILE_SIZE),
+        height: TILE_SIZE.min(height - ty * TILE_SIZE),
+    }
+}
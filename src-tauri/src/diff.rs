@@ -0,0 +1,57 @@
+//! Per-pixel difference between the current frame and a stored baseline.
+
+use serde::Serialize;
+
+/// A processed frame kept as the reference for `capture_diff`.
+pub struct Baseline {
+    pub sequence: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameDiff {
+    baseline_sequence: u32,
+    sequence: u32,
+    width: u32,
+    height: u32,
+    /// Pixels whose colour differs at all from the baseline.
+    changed_pixels: u32,
+    /// Largest per-channel RGB difference, as grayscale replicated to opaque RGBA.
+    data: Vec<u8>,
+}
+
+/// Compares a `width`×`height` RGBA frame against `baseline`.
+pub fn diff(baseline: &Baseline, sequence: u32, width: u32, height: u32, data: &[u8]) -> Result<FrameDiff, String> {
+    if (width, height) != (baseline.width, baseline.height) {
+        return Err(format!(
+            "Frame is {}x{} but the baseline is {}x{}; set a new baseline",
+            width, height, baseline.width, baseline.height
+        ));
+    }
+
+    let mut changed_pixels = 0;
+    let mut output = Vec::with_capacity(data.len());
+    for (current, reference) in data.chunks_exact(4).zip(baseline.data.chunks_exact(4)) {
+        let intensity = current[..3]
+            .iter()
+            .zip(&reference[..3])
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        if intensity > 0 {
+            changed_pixels += 1;
+        }
+        output.extend_from_slice(&[intensity, intensity, intensity, 255]);
+    }
+
+    Ok(FrameDiff {
+        baseline_sequence: baseline.sequence,
+        sequence,
+        width,
+        height,
+        changed_pixels,
+        data: output,
+    })
+}
@@ -0,0 +1,430 @@
+//! Display enumeration and lookup.
+//!
+//! Indices count outputs across all adapters in enumeration order, which can
+//! change across reboots and hotplugs; device names (`\\.\DISPLAY2`) are the
+//! stable way to refer to a display.
+//!
+//! In clone mode every mirrored output is enumerated with the same desktop
+//! rectangle; those after the first are reported as mirrors of it.
+
+use serde::Serialize;
+use win_desktop_duplication::{devices::*, outputs::Display};
+use windows::core::{Interface, PCWSTR};
+use windows::Win32::Devices::Display::{
+    DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig, DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+    DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_HEADER, DISPLAYCONFIG_MODE_INFO,
+    DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_SDR_WHITE_LEVEL, DISPLAYCONFIG_SOURCE_DEVICE_NAME, QDC_ONLY_ACTIVE_PATHS,
+};
+use windows::Win32::Foundation::{BOOL, ERROR_SUCCESS, RECT};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709, DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+    DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+};
+use windows::Win32::Graphics::Dxgi::{
+    IDXGIAdapter, IDXGIFactory5, IDXGIOutput, IDXGIOutput6, DXGI_FEATURE_PRESENT_ALLOW_TEARING, DXGI_OUTPUT_DESC,
+};
+use windows::Win32::Foundation::POINT;
+use windows::Win32::Graphics::Gdi::{
+    EnumDisplayDevicesW, GetMonitorInfoW, MonitorFromPoint, DISPLAY_DEVICEW, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+    MONITOR_DEFAULTTONULL,
+};
+use windows::Win32::UI::HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
+
+use crate::rotation::Rotation;
+use crate::Rect;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DisplayInfo {
+    index: u32,
+    /// Windows device name, e.g. `\\.\DISPLAY1`.
+    name: String,
+    /// Monitor name from its EDID, when Windows reports one.
+    friendly_name: Option<String>,
+    adapter: String,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    /// Index of the display this one mirrors, showing the same content.
+    mirror_of: Option<u32>,
+}
+
+/// How a display encodes colour, from DXGI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ColorSpace {
+    /// Gamma 2.2 with BT.709 primaries; what 8-bit BGRA capture assumes.
+    Srgb,
+    /// Linear BT.709, as used by FP16 desktops.
+    ScRgb,
+    /// PQ with BT.2020 primaries, i.e. HDR enabled.
+    Hdr10,
+    /// Reported, but none of the above.
+    Other,
+    /// The system doesn't report colour information for this display.
+    Unknown,
+}
+
+/// CIE 1931 xy chromaticities of a display's primaries and white point.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Primaries {
+    red: [f32; 2],
+    green: [f32; 2],
+    blue: [f32; 2],
+    white: [f32; 2],
+}
+
+/// Luminance range of a display in nits.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Luminance {
+    min: f32,
+    max: f32,
+    /// Highest luminance sustainable across the whole screen.
+    max_full_frame: f32,
+}
+
+/// Colour metadata of one display. Fields a display or system doesn't
+/// report are `None`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ColorInfo {
+    index: u32,
+    color_space: ColorSpace,
+    /// Whether the display is in HDR, where 8-bit BGRA frames lose range.
+    hdr: bool,
+    bits_per_color: Option<u32>,
+    /// Nits SDR content is shown at white, as set in Windows' HDR settings.
+    sdr_white_level: Option<f32>,
+    primaries: Option<Primaries>,
+    luminance: Option<Luminance>,
+}
+
+/// Every display on every adapter, in index order.
+pub fn all() -> Vec<(Adapter, Display)> {
+    AdapterFactory::new()
+        .flat_map(|adapter| {
+            adapter.iter_displays()
+                .map(|display| (adapter.clone(), display))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Every display that isn't a mirror of another, in index order.
+pub fn distinct() -> Vec<(Adapter, Display)> {
+    let all = all();
+    let mirrors = mirrors(&all);
+    all.into_iter().zip(mirrors).filter(|(_, mirror_of)| mirror_of.is_none()).map(|(display, _)| display).collect()
+}
+
+/// Describes every display, in index order.
+pub fn describe_all() -> Result<Vec<DisplayInfo>, String> {
+    let all = all();
+    let mirrors = mirrors(&all);
+    all.iter()
+        .zip(mirrors)
+        .enumerate()
+        .map(|(index, ((adapter, display), mirror_of))| describe(index as u32, adapter, display, mirror_of))
+        .collect()
+}
+
+fn describe(index: u32, adapter: &Adapter, display: &Display, mirror_of: Option<u32>) -> Result<DisplayInfo, String> {
+    let desc = output_desc(display)?;
+    let bounds = desc.DesktopCoordinates;
+    let name = display.name();
+    Ok(DisplayInfo {
+        index,
+        friendly_name: friendly_name(&name),
+        name,
+        adapter: adapter.name(),
+        x: bounds.left,
+        y: bounds.top,
+        width: (bounds.right - bounds.left) as u32,
+        height: (bounds.bottom - bounds.top) as u32,
+        mirror_of,
+    })
+}
+
+/// Finds a display by device name or monitor name, ignoring case. A mirror
+/// resolves to the display it mirrors, as both show the same content.
+pub fn find_by_name(name: &str) -> Result<(Adapter, Display), String> {
+    let mut all = all();
+    let index = all
+        .iter()
+        .position(|(_, display)| {
+            let device_name = display.name();
+            device_name.eq_ignore_ascii_case(name)
+                || friendly_name(&device_name).is_some_and(|friendly| friendly.eq_ignore_ascii_case(name))
+        })
+        .ok_or_else(|| format!("No display named {}", name))?;
+    let index = mirrors(&all)[index].map_or(index, |original| original as usize);
+    Ok(all.swap_remove(index))
+}
+
+/// For each display, the index of the first earlier one with the same
+/// desktop rectangle.
+fn mirrors(all: &[(Adapter, Display)]) -> Vec<Option<u32>> {
+    let rects: Vec<_> = all
+        .iter()
+        .map(|(_, display)| output_desc(display).ok().map(|desc| desc.DesktopCoordinates))
+        .collect();
+    rects
+        .iter()
+        .enumerate()
+        .map(|(index, rect)| {
+            let rect = rect.as_ref()?;
+            rects[..index].iter().position(|other| other.as_ref() == Some(rect)).map(|original| original as u32)
+        })
+        .collect()
+}
+
+/// Colour metadata of the display at `index`.
+pub fn color_info(index: u32) -> Result<ColorInfo, String> {
+    let (_, display) = all()
+        .into_iter()
+        .nth(index as usize)
+        .ok_or_else(|| format!("No display at index {}", index))?;
+    let output: IDXGIOutput = unsafe { crate::gpu::rewrap(display.as_raw_ref()) };
+    // `IDXGIOutput6` needs Windows 10 1803; earlier systems only get the white level
+    let desc = output.cast::<IDXGIOutput6>().ok().and_then(|output| unsafe { output.GetDesc1() }.ok());
+    let color_space = match desc.map(|desc| desc.ColorSpace) {
+        Some(DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709) => ColorSpace::Srgb,
+        Some(DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709) => ColorSpace::ScRgb,
+        Some(DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020) => ColorSpace::Hdr10,
+        Some(_) => ColorSpace::Other,
+        None => ColorSpace::Unknown,
+    };
+    // Drivers without the metadata leave it zeroed
+    let primaries = desc
+        .filter(|desc| desc.RedPrimary != [0.0; 2] && desc.WhitePoint != [0.0; 2])
+        .map(|desc| Primaries {
+            red: desc.RedPrimary,
+            green: desc.GreenPrimary,
+            blue: desc.BluePrimary,
+            white: desc.WhitePoint,
+        });
+    let luminance = desc.filter(|desc| desc.MaxLuminance > 0.0).map(|desc| Luminance {
+        min: desc.MinLuminance,
+        max: desc.MaxLuminance,
+        max_full_frame: desc.MaxFullFrameLuminance,
+    });
+    Ok(ColorInfo {
+        index,
+        color_space,
+        hdr: color_space == ColorSpace::Hdr10,
+        bits_per_color: desc.map(|desc| desc.BitsPerColor).filter(|&bits| bits > 0),
+        sdr_white_level: sdr_white_level(&display.name()),
+        primaries,
+        luminance,
+    })
+}
+
+/// SDR white level in nits of the active display path whose source is
+/// `device_name`.
+fn sdr_white_level(device_name: &str) -> Option<f32> {
+    let (mut path_count, mut mode_count) = (0, 0);
+    if unsafe { GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut path_count, &mut mode_count) } != ERROR_SUCCESS {
+        return None;
+    }
+    let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); path_count as usize];
+    let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); mode_count as usize];
+    let queried = unsafe {
+        QueryDisplayConfig(
+            QDC_ONLY_ACTIVE_PATHS,
+            &mut path_count,
+            paths.as_mut_ptr(),
+            &mut mode_count,
+            modes.as_mut_ptr(),
+            None,
+        )
+    };
+    if queried != ERROR_SUCCESS {
+        return None;
+    }
+    paths.truncate(path_count as usize);
+
+    let path = paths.iter().find(|path| {
+        let mut source = DISPLAYCONFIG_SOURCE_DEVICE_NAME {
+            header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+                r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
+                size: size_of::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>() as u32,
+                adapterId: path.sourceInfo.adapterId,
+                id: path.sourceInfo.id,
+            },
+            ..Default::default()
+        };
+        if unsafe { DisplayConfigGetDeviceInfo(&mut source.header) } != 0 {
+            return false;
+        }
+        let name = &source.viewGdiDeviceName;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        String::from_utf16_lossy(&name[..len]).eq_ignore_ascii_case(device_name)
+    })?;
+
+    let mut white = DISPLAYCONFIG_SDR_WHITE_LEVEL {
+        header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+            r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+            size: size_of::<DISPLAYCONFIG_SDR_WHITE_LEVEL>() as u32,
+            adapterId: path.targetInfo.adapterId,
+            id: path.targetInfo.id,
+        },
+        ..Default::default()
+    };
+    if unsafe { DisplayConfigGetDeviceInfo(&mut white.header) } != 0 {
+        return None;
+    }
+    // Reported in thousandths of the 80 nit scRGB reference white
+    Some(white.SDRWhiteLevel as f32 / 1000.0 * 80.0)
+}
+
+/// Top-left corner of `display` on the virtual desktop.
+pub fn desktop_origin(display: &Display) -> Result<(i32, i32), String> {
+    let bounds = output_desc(display)?.DesktopCoordinates;
+    Ok((bounds.left, bounds.top))
+}
+
+/// Area `display` covers on the virtual desktop.
+pub fn desktop_bounds(display: &Display) -> Result<RECT, String> {
+    Ok(output_desc(display)?.DesktopCoordinates)
+}
+
+/// Strips of the `width`×`height` display at desktop position `origin` that
+/// lie outside its work area, such as the taskbar, relative to the display.
+pub fn outside_work_area(origin: (i32, i32), width: u32, height: u32) -> Vec<Rect> {
+    let monitor = unsafe { MonitorFromPoint(POINT { x: origin.0, y: origin.1 }, MONITOR_DEFAULTTONULL) };
+    let mut info = MONITORINFO { cbSize: size_of::<MONITORINFO>() as u32, ..Default::default() };
+    if monitor.is_invalid() || !unsafe { GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+        return Vec::new();
+    }
+    let work = info.rcWork;
+    let (left, top) = ((work.left - origin.0).max(0) as u32, (work.top - origin.1).max(0) as u32);
+    let (right, bottom) = (((work.right - origin.0).max(0) as u32).min(width), ((work.bottom - origin.1).max(0) as u32).min(height));
+    [
+        Rect { x: 0, y: 0, width, height: top },
+        Rect { x: 0, y: bottom, width, height: height.saturating_sub(bottom) },
+        Rect { x: 0, y: top, width: left, height: bottom.saturating_sub(top) },
+        Rect { x: right, y: top, width: width.saturating_sub(right), height: bottom.saturating_sub(top) },
+    ]
+    .into_iter()
+    .filter(|rect| rect.width > 0 && rect.height > 0)
+    .collect()
+}
+
+/// Desktop bounds of the monitor containing `point`, or the nearest one.
+pub fn monitor_bounds(point: (i32, i32)) -> Option<RECT> {
+    let monitor = unsafe { MonitorFromPoint(POINT { x: point.0, y: point.1 }, MONITOR_DEFAULTTONEAREST) };
+    let mut info = MONITORINFO { cbSize: size_of::<MONITORINFO>() as u32, ..Default::default() };
+    if monitor.is_invalid() || !unsafe { GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+        return None;
+    }
+    Some(info.rcMonitor)
+}
+
+/// Effective DPI of the monitor at desktop position `origin`, i.e. 96 times
+/// its scale factor; 96 when Windows doesn't say.
+pub fn effective_dpi(origin: (i32, i32)) -> u32 {
+    let monitor = unsafe { MonitorFromPoint(POINT { x: origin.0, y: origin.1 }, MONITOR_DEFAULTTONULL) };
+    let (mut dpi_x, mut dpi_y) = (0, 0);
+    match unsafe { GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) } {
+        Ok(()) if dpi_x > 0 => dpi_x,
+        _ => 96,
+    }
+}
+
+/// Top-left corners for displays covering `bounds` on the desktop once each
+/// is shrunk by its `scale`. Displays that touched still touch, at the same
+/// relative offset along the shared edge; the one at the desktop origin
+/// stays there.
+pub fn scaled_layout(displays: &[(RECT, f64)]) -> Vec<(f64, f64)> {
+    let mut placed: Vec<Option<(f64, f64)>> = vec![None; displays.len()];
+    let anchor = displays
+        .iter()
+        .position(|(bounds, _)| bounds.left <= 0 && bounds.top <= 0 && bounds.right > 0 && bounds.bottom > 0)
+        .unwrap_or(0);
+    if let Some((bounds, scale)) = displays.get(anchor) {
+        placed[anchor] = Some((bounds.left as f64 * scale, bounds.top as f64 * scale));
+    }
+
+    // Place each display against one already placed until none are left touching
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for index in 0..displays.len() {
+            if placed[index].is_some() {
+                continue;
+            }
+            let (bounds, scale) = displays[index];
+            let (width, height) = ((bounds.right - bounds.left) as f64 * scale, (bounds.bottom - bounds.top) as f64 * scale);
+            let neighbour = displays.iter().zip(&placed).find_map(|(&(other, other_scale), &position)| {
+                let (x, y) = position?;
+                let (other_width, other_height) =
+                    ((other.right - other.left) as f64 * other_scale, (other.bottom - other.top) as f64 * other_scale);
+                let along_x = x + (bounds.left - other.left) as f64 * other_scale;
+                let along_y = y + (bounds.top - other.top) as f64 * other_scale;
+                let beside = bounds.top < other.bottom && bounds.bottom > other.top;
+                let stacked = bounds.left < other.right && bounds.right > other.left;
+                if beside && bounds.left == other.right {
+                    Some((x + other_width, along_y))
+                } else if beside && bounds.right == other.left {
+                    Some((x - width, along_y))
+                } else if stacked && bounds.top == other.bottom {
+                    Some((along_x, y + other_height))
+                } else if stacked && bounds.bottom == other.top {
+                    Some((along_x, y - height))
+                } else {
+                    None
+                }
+            });
+            if neighbour.is_some() {
+                placed[index] = neighbour;
+                progress = true;
+            }
+        }
+    }
+
+    placed
+        .into_iter()
+        .zip(displays)
+        .map(|(position, (bounds, scale))| position.unwrap_or((bounds.left as f64 * scale, bounds.top as f64 * scale)))
+        .collect()
+}
+
+/// Whether `adapter` can present with tearing allowed, which is how DXGI
+/// reports variable refresh (G-Sync, FreeSync) support. Not per display: a
+/// fixed-rate monitor on a capable adapter still reads as `true`.
+pub fn variable_refresh(adapter: &Adapter) -> bool {
+    let adapter: IDXGIAdapter = unsafe { crate::gpu::rewrap(adapter.as_raw_ref()) };
+    let Ok(factory) = (unsafe { adapter.GetParent::<IDXGIFactory5>() }) else {
+        return false;
+    };
+    let mut allowed = BOOL::default();
+    let supported = unsafe {
+        factory.CheckFeatureSupport(
+            DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+            &mut allowed as *mut BOOL as *mut core::ffi::c_void,
+            size_of::<BOOL>() as u32,
+        )
+    };
+    supported.is_ok() && allowed.as_bool()
+}
+
+/// How `display`'s duplicated texture is turned relative to the desktop.
+pub fn rotation(display: &Display) -> Result<Rotation, String> {
+    Ok(output_desc(display)?.Rotation.into())
+}
+
+fn output_desc(display: &Display) -> Result<DXGI_OUTPUT_DESC, String> {
+    let output: IDXGIOutput = unsafe { crate::gpu::rewrap(display.as_raw_ref()) };
+    unsafe { output.GetDesc() }.map_err(|e| e.to_string())
+}
+
+/// The monitor attached to a display device, as `EnumDisplayDevices` names it.
+fn friendly_name(device_name: &str) -> Option<String> {
+    let wide: Vec<u16> = device_name.encode_utf16().chain(Some(0)).collect();
+    let mut device = DISPLAY_DEVICEW {
+        cb: size_of::<DISPLAY_DEVICEW>() as u32,
+        ..Default::default()
+    };
+    let found = unsafe { EnumDisplayDevicesW(PCWSTR(wide.as_ptr()), 0, &mut device, 0) }.as_bool();
+    let len = device.DeviceString.iter().position(|&c| c == 0).unwrap_or(device.DeviceString.len());
+    let name = String::from_utf16_lossy(&device.DeviceString[..len]);
+    (found && !name.is_empty()).then_some(name)
+}
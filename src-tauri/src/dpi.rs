@@ -0,0 +1,70 @@
+//! Process DPI awareness.
+//!
+//! Awareness is process-global and Windows only lets it be chosen once, so
+//! it has to be settled before the first window or duplication is created.
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::Foundation::ERROR_ACCESS_DENIED;
+use windows::Win32::UI::HiDpi::{
+    AreDpiAwarenessContextsEqual, GetThreadDpiAwarenessContext, SetProcessDpiAwarenessContext,
+    DPI_AWARENESS_CONTEXT, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, DPI_AWARENESS_CONTEXT_UNAWARE, DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DpiAwareness {
+    Unaware,
+    UnawareGdiScaled,
+    System,
+    PerMonitor,
+    PerMonitorV2,
+}
+
+impl DpiAwareness {
+    const ALL: [DpiAwareness; 5] = [
+        DpiAwareness::Unaware,
+        DpiAwareness::UnawareGdiScaled,
+        DpiAwareness::System,
+        DpiAwareness::PerMonitor,
+        DpiAwareness::PerMonitorV2,
+    ];
+
+    fn context(self) -> DPI_AWARENESS_CONTEXT {
+        match self {
+            DpiAwareness::Unaware => DPI_AWARENESS_CONTEXT_UNAWARE,
+            DpiAwareness::UnawareGdiScaled => DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+            DpiAwareness::System => DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+            DpiAwareness::PerMonitor => DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
+            DpiAwareness::PerMonitorV2 => DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        }
+    }
+}
+
+/// The awareness currently in effect, or `None` if Windows reports a
+/// context we don't know about.
+pub fn current() -> Option<DpiAwareness> {
+    let context = unsafe { GetThreadDpiAwarenessContext() };
+    DpiAwareness::ALL
+        .into_iter()
+        .find(|awareness| unsafe { AreDpiAwarenessContextsEqual(context, awareness.context()) }.as_bool())
+}
+
+/// Sets the process awareness, failing if it has already been established.
+pub fn set(awareness: DpiAwareness) -> Result<(), String> {
+    match unsafe { SetProcessDpiAwarenessContext(awareness.context()) } {
+        Ok(()) => Ok(()),
+        Err(e) if e.code() == ERROR_ACCESS_DENIED.to_hresult() => Err(format!(
+            "DPI awareness is already established for this process ({}); \
+             set it in the application manifest or before any window is created",
+            current().map_or("unknown".to_string(), |current| format!("{:?}", current))
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Makes the process per-monitor aware unless an awareness was already chosen.
+pub fn ensure_aware() {
+    if current() == Some(DpiAwareness::Unaware) {
+        let _ = set(DpiAwareness::PerMonitorV2);
+    }
+}
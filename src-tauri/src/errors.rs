@@ -0,0 +1,108 @@
+//! Rate limiting of `capture-error` events for errors that repeat every frame.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum ErrorKind {
+    /// Copying the frame off the GPU failed.
+    Read,
+    /// No frame could be acquired from the duplication.
+    Acquire,
+    /// The window's position or size couldn't be queried.
+    Geometry,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorEvent {
+    kind: ErrorKind,
+    message: String,
+    /// Occurrences of this kind suppressed since the previous event.
+    count_since_last: u32,
+}
+
+#[derive(Default)]
+struct Slot {
+    last_emit: Option<Instant>,
+    suppressed: u32,
+}
+
+/// The most recent error, whether or not it was emitted.
+#[derive(Clone, Debug, Serialize)]
+pub struct LastError {
+    kind: ErrorKind,
+    message: String,
+    /// How long ago it happened.
+    age_ms: u64,
+}
+
+/// Lets through at most one event per kind per interval, counting the rest.
+pub struct ErrorLimiter {
+    interval: Duration,
+    slots: HashMap<ErrorKind, Slot>,
+    /// Occurrences of each kind since capture began, emitted or not.
+    totals: HashMap<ErrorKind, u64>,
+    last: Option<(ErrorKind, String, Instant)>,
+}
+
+impl Default for ErrorLimiter {
+    fn default() -> Self {
+        Self { interval: Duration::from_secs(1), slots: HashMap::new(), totals: HashMap::new(), last: None }
+    }
+}
+
+impl ErrorLimiter {
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Zero lets every error through.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Records an occurrence, returning the event to emit if the interval
+    /// since the last one of this kind has passed.
+    pub fn record(&mut self, kind: ErrorKind, message: String) -> Option<ErrorEvent> {
+        let now = Instant::now();
+        self.remember(kind, &message, now);
+        let slot = self.slots.entry(kind).or_default();
+        if slot.last_emit.is_some_and(|last| now.duration_since(last) < self.interval) {
+            slot.suppressed = slot.suppressed.saturating_add(1);
+            return None;
+        }
+        Some(Self::emit(slot, kind, message, now))
+    }
+
+    /// Records an occurrence that must be reported regardless of the limit.
+    pub fn force(&mut self, kind: ErrorKind, message: String) -> ErrorEvent {
+        let now = Instant::now();
+        self.remember(kind, &message, now);
+        let slot = self.slots.entry(kind).or_default();
+        Self::emit(slot, kind, message, now)
+    }
+
+    pub fn totals(&self) -> &HashMap<ErrorKind, u64> {
+        &self.totals
+    }
+
+    pub fn last(&self) -> Option<LastError> {
+        self.last.as_ref().map(|(kind, message, at)| LastError {
+            kind: *kind,
+            message: message.clone(),
+            age_ms: at.elapsed().as_millis() as u64,
+        })
+    }
+
+    fn remember(&mut self, kind: ErrorKind, message: &str, now: Instant) {
+        *self.totals.entry(kind).or_default() += 1;
+        self.last = Some((kind, message.to_string(), now));
+    }
+
+    fn emit(slot: &mut Slot, kind: ErrorKind, message: String, now: Instant) -> ErrorEvent {
+        let count_since_last = std::mem::take(&mut slot.suppressed);
+        slot.last_emit = Some(now);
+        ErrorEvent { kind, message, count_since_last }
+    }
+}
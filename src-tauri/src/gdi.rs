@@ -0,0 +1,69 @@
+//! GDI bitmap helpers for reads that don't go through the duplication.
+
+use std::ffi::c_void;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Gdi::{
+    CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC, GetDIBits, GetObjectW, ReleaseDC,
+    SelectObject, BITMAP, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP, HDC,
+};
+use windows::Win32::Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS};
+use windows::Win32::UI::WindowsAndMessaging::PW_RENDERFULLCONTENT;
+
+use crate::frame_len;
+
+/// Reads a bitmap as top-down 32-bit BGRA, or `None` if its size is
+/// negative or too large to address.
+pub unsafe fn read_bitmap(bitmap: HBITMAP) -> Option<(u32, u32, Vec<u8>)> {
+    let mut bm = BITMAP::default();
+    if GetObjectW(bitmap.into(), size_of::<BITMAP>() as i32, Some(&mut bm as *mut _ as *mut c_void)) == 0 {
+        return None;
+    }
+    let width = u32::try_from(bm.bmWidth).ok()?;
+    let height = u32::try_from(bm.bmHeight).ok()?;
+    let len = frame_len(width, height).ok()?;
+
+    let mut info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut pixels = vec![0u8; len];
+    let dc = GetDC(None);
+    let lines = GetDIBits(dc, bitmap, 0, height, Some(pixels.as_mut_ptr() as *mut c_void), &mut info, DIB_RGB_COLORS);
+    ReleaseDC(None, dc);
+    (lines > 0).then_some((width, height, pixels))
+}
+
+/// Draws into a `width`×`height` bitmap with `draw` and reads it back as BGRA.
+pub unsafe fn snapshot(width: i32, height: i32, draw: impl FnOnce(HDC) -> bool) -> Option<Vec<u8>> {
+    let screen_dc = GetDC(None);
+    let dc = CreateCompatibleDC(Some(screen_dc));
+    let bitmap: HBITMAP = CreateCompatibleBitmap(screen_dc, width, height);
+    ReleaseDC(None, screen_dc);
+
+    let previous = SelectObject(dc, bitmap.into());
+    let drawn = draw(dc);
+    SelectObject(dc, previous);
+    let _ = DeleteDC(dc);
+
+    let pixels = drawn.then(|| read_bitmap(bitmap)).flatten();
+    let _ = DeleteObject(bitmap.into());
+    pixels.map(|(_, _, pixels)| pixels)
+}
+
+/// The window's own composited content, `width`×`height` BGRA, including
+/// the alpha of layered windows.
+pub fn print_window(hwnd: HWND, width: u32, height: u32) -> Option<Vec<u8>> {
+    unsafe {
+        snapshot(width as i32, height as i32, |dc| {
+            PrintWindow(hwnd, dc, PRINT_WINDOW_FLAGS(PW_RENDERFULLCONTENT)).as_bool()
+        })
+    }
+}
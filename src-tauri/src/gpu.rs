@@ -0,0 +1,414 @@
+//! Direct3D helpers that work on the device and textures owned by
+//! `win_desktop_duplication`.
+
+use std::mem::ManuallyDrop;
+use std::ptr::copy_nonoverlapping;
+use serde::{Deserialize, Serialize};
+use windows::core::Interface;
+use windows::Win32::Foundation::RECT;
+use windows::Win32::Graphics::Direct3D11::{
+    ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, ID3D11VideoContext, ID3D11VideoDevice, ID3D11VideoProcessor,
+    ID3D11VideoProcessorEnumerator, ID3D11VideoProcessorOutputView,
+    D3D11_BIND_RENDER_TARGET, D3D11_BOX, D3D11_CPU_ACCESS_READ, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ,
+    D3D11_TEX2D_VPIV, D3D11_TEX2D_VPOV, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, D3D11_USAGE_STAGING,
+    D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE, D3D11_VIDEO_PROCESSOR_CONTENT_DESC,
+    D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_INPUT, D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_OUTPUT,
+    D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC, D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC_0,
+    D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC, D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC_0,
+    D3D11_VIDEO_PROCESSOR_STREAM, D3D11_VIDEO_USAGE_OPTIMAL_SPEED, D3D11_VPIV_DIMENSION_TEXTURE2D,
+    D3D11_VPOV_DIMENSION_TEXTURE2D,
+};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_RATIONAL, DXGI_SAMPLE_DESC,
+};
+use windows::Win32::Graphics::Dxgi::IDXGIDevice;
+
+use crate::Rect;
+
+/// Reinterprets a COM interface from the `windows` release used by
+/// `win_desktop_duplication` as the same interface from ours.
+///
+/// # Safety
+/// `F` and `T` must be the same COM interface (or `T` one of its bases).
+pub(crate) unsafe fn rewrap<F, T: Interface>(from: &F) -> T {
+    debug_assert_eq!(size_of::<F>(), size_of::<T>());
+    (*(from as *const F as *const T)).clone()
+}
+
+/// Whether `device` has been removed or reset, e.g. by a driver timeout (TDR).
+pub(crate) fn device_removed(device: &ID3D11Device) -> bool {
+    unsafe { device.GetDeviceRemovedReason() }.is_err()
+}
+
+/// How the GPU schedules the capture device's work against other processes
+/// on the adapter, such as a fullscreen game.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GpuPriority {
+    /// Yields to other work, at the cost of later readbacks.
+    Low,
+    #[default]
+    Normal,
+    /// Needs the base priority privilege, which most processes lack.
+    High,
+}
+
+impl GpuPriority {
+    /// The `SetGPUThreadPriority` level, from -7 to 7.
+    fn level(self) -> i32 {
+        match self {
+            GpuPriority::Low => -7,
+            GpuPriority::Normal => 0,
+            GpuPriority::High => 7,
+        }
+    }
+
+    fn from_level(level: i32) -> Self {
+        match level {
+            ..0 => GpuPriority::Low,
+            0 => GpuPriority::Normal,
+            _ => GpuPriority::High,
+        }
+    }
+}
+
+/// Sets the GPU scheduling priority of `device`, returning the priority it
+/// has afterwards, which differs from `priority` if the driver refused it.
+pub(crate) fn set_gpu_priority(device: &ID3D11Device, priority: GpuPriority) -> Result<GpuPriority, String> {
+    let device: IDXGIDevice = device.cast().map_err(|e| e.to_string())?;
+    unsafe {
+        // A refusal shows up in the level read back
+        let _ = device.SetGPUThreadPriority(priority.level());
+        device.GetGPUThreadPriority().map(GpuPriority::from_level).map_err(|e| e.to_string())
+    }
+}
+
+/// Scales `crop` of a BGRA `source` texture to exactly `out_width`×`out_height`
+/// with the D3D11 video processor, and reads the RGBA result back.
+pub(crate) fn scale_texture(
+    device: &ID3D11Device,
+    ctx: &ID3D11DeviceContext,
+    source: &ID3D11Texture2D,
+    crop: Rect,
+    out_width: u32,
+    out_height: u32,
+) -> Result<Vec<u8>, String> {
+    let mut source_desc = D3D11_TEXTURE2D_DESC::default();
+    unsafe { source.GetDesc(&mut source_desc) };
+    let converter = Converter::new(device, (source_desc.Width, source_desc.Height), (out_width, out_height))?;
+    let target = converter.convert(device, ctx, source, crop)?;
+    read_texture(device, ctx, target)
+}
+
+/// A video processor turning BGRA textures of one size into RGBA ones of
+/// another, kept so repeated frames don't rebuild it. The output is opaque.
+pub(crate) struct Converter {
+    input: (u32, u32),
+    output: (u32, u32),
+    enumerator: ID3D11VideoProcessorEnumerator,
+    processor: ID3D11VideoProcessor,
+    target: ID3D11Texture2D,
+    output_view: ID3D11VideoProcessorOutputView,
+}
+
+// Only used by the thread that owns the capture device, like `Readback`.
+unsafe impl Send for Converter {}
+
+impl Converter {
+    pub(crate) fn new(device: &ID3D11Device, input: (u32, u32), output: (u32, u32)) -> Result<Self, String> {
+        let video_device: ID3D11VideoDevice = device.cast().map_err(|e| e.to_string())?;
+        let content_desc = D3D11_VIDEO_PROCESSOR_CONTENT_DESC {
+            InputFrameFormat: D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            InputFrameRate: DXGI_RATIONAL { Numerator: 60, Denominator: 1 },
+            InputWidth: input.0,
+            InputHeight: input.1,
+            OutputFrameRate: DXGI_RATIONAL { Numerator: 60, Denominator: 1 },
+            OutputWidth: output.0,
+            OutputHeight: output.1,
+            Usage: D3D11_VIDEO_USAGE_OPTIMAL_SPEED,
+        };
+
+        unsafe {
+            let enumerator = video_device
+                .CreateVideoProcessorEnumerator(&content_desc)
+                .map_err(|e| format!("Video processor unavailable: {}", e))?;
+
+            let input_support = enumerator.CheckVideoProcessorFormat(DXGI_FORMAT_B8G8R8A8_UNORM).map_err(|e| e.to_string())?;
+            let output_support = enumerator.CheckVideoProcessorFormat(DXGI_FORMAT_R8G8B8A8_UNORM).map_err(|e| e.to_string())?;
+            if input_support & D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_INPUT.0 as u32 == 0
+                || output_support & D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_OUTPUT.0 as u32 == 0
+            {
+                return Err("Video processor doesn't support the desktop format".to_string());
+            }
+
+            let processor = video_device
+                .CreateVideoProcessor(&enumerator, 0)
+                .map_err(|e| e.to_string())?;
+
+            let target = create_texture(device, output.0, output.1, DXGI_FORMAT_R8G8B8A8_UNORM)?;
+
+            let mut output_view = None;
+            video_device
+                .CreateVideoProcessorOutputView(
+                    &target,
+                    &enumerator,
+                    &D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC {
+                        ViewDimension: D3D11_VPOV_DIMENSION_TEXTURE2D,
+                        Anonymous: D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC_0 {
+                            Texture2D: D3D11_TEX2D_VPOV { MipSlice: 0 },
+                        },
+                    },
+                    Some(&mut output_view),
+                )
+                .map_err(|e| e.to_string())?;
+            let output_view = output_view.ok_or("Failed to create video processor output view")?;
+
+            Ok(Converter { input, output, enumerator, processor, target, output_view })
+        }
+    }
+
+    /// Whether this converter takes `input`-sized frames to `output`-sized ones.
+    pub(crate) fn fits(&self, input: (u32, u32), output: (u32, u32)) -> bool {
+        self.input == input && self.output == output
+    }
+
+    /// Scales `crop` of the BGRA `source` onto the RGBA target and returns it.
+    pub(crate) fn convert(
+        &self,
+        device: &ID3D11Device,
+        ctx: &ID3D11DeviceContext,
+        source: &ID3D11Texture2D,
+        crop: Rect,
+    ) -> Result<&ID3D11Texture2D, String> {
+        let mut source_desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { source.GetDesc(&mut source_desc) };
+        if source_desc.Format != DXGI_FORMAT_B8G8R8A8_UNORM {
+            return Err("Video processor doesn't support the desktop format".to_string());
+        }
+        if (source_desc.Width, source_desc.Height) != self.input {
+            return Err("Frame size changed since the converter was created".to_string());
+        }
+        let video_device: ID3D11VideoDevice = device.cast().map_err(|e| e.to_string())?;
+        let video_ctx: ID3D11VideoContext = ctx.cast().map_err(|e| e.to_string())?;
+
+        unsafe {
+            let mut input_view = None;
+            video_device
+                .CreateVideoProcessorInputView(
+                    source,
+                    &self.enumerator,
+                    &D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC {
+                        FourCC: 0,
+                        ViewDimension: D3D11_VPIV_DIMENSION_TEXTURE2D,
+                        Anonymous: D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC_0 {
+                            Texture2D: D3D11_TEX2D_VPIV { MipSlice: 0, ArraySlice: 0 },
+                        },
+                    },
+                    Some(&mut input_view),
+                )
+                .map_err(|e| e.to_string())?;
+
+            let source_rect = RECT {
+                left: crop.x as i32,
+                top: crop.y as i32,
+                right: (crop.x + crop.width) as i32,
+                bottom: (crop.y + crop.height) as i32,
+            };
+            video_ctx.VideoProcessorSetStreamSourceRect(&self.processor, 0, true, Some(&source_rect));
+
+            let mut stream = D3D11_VIDEO_PROCESSOR_STREAM {
+                Enable: true.into(),
+                pInputSurface: ManuallyDrop::new(input_view),
+                ..Default::default()
+            };
+            let result = video_ctx.VideoProcessorBlt(&self.processor, &self.output_view, 0, std::slice::from_ref(&stream));
+            ManuallyDrop::drop(&mut stream.pInputSurface);
+            result.map_err(|e| format!("Video processor blit failed: {}", e))?;
+        }
+        Ok(&self.target)
+    }
+}
+
+fn create_texture(device: &ID3D11Device, width: u32, height: u32, format: DXGI_FORMAT) -> Result<ID3D11Texture2D, String> {
+    let desc = D3D11_TEXTURE2D_DESC {
+        Width: width,
+        Height: height,
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: format,
+        SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: D3D11_BIND_RENDER_TARGET.0 as u32,
+        ..Default::default()
+    };
+    let mut texture = None;
+    unsafe { device.CreateTexture2D(&desc, None, Some(&mut texture)) }.map_err(|e| e.to_string())?;
+    texture.ok_or_else(|| "Failed to create texture".to_string())
+}
+
+/// Copies a 4-byte-per-pixel texture into a staging texture and returns its
+/// rows tightly packed.
+fn read_texture(device: &ID3D11Device, ctx: &ID3D11DeviceContext, texture: &ID3D11Texture2D) -> Result<Vec<u8>, String> {
+    let desc = staging_desc(texture);
+    let staging = create_staging(device, &desc)?;
+    let mut data = Vec::new();
+    unsafe { ctx.CopyResource(&staging, texture) };
+    map_rows(ctx, &staging, &desc, &mut data)?;
+    Ok(data)
+}
+
+/// Round-robin staging textures for reading frames back from the GPU.
+///
+/// With one buffer every read waits for its own copy. With more, each read
+/// issues the copy of the new frame and maps the oldest pending one, so the
+/// GPU copy overlaps CPU processing at the cost of `buffers - 1` frames of
+/// latency.
+pub(crate) struct Readback {
+    device: ID3D11Device,
+    ctx: ID3D11DeviceContext,
+    buffers: usize,
+    staging: Vec<ID3D11Texture2D>,
+    desc: D3D11_TEXTURE2D_DESC,
+    next: usize,
+    pending: usize,
+}
+
+// The capture task owns its readback and only ever uses it from one thread at a time.
+unsafe impl Send for Readback {}
+
+impl Readback {
+    pub(crate) fn new(device: ID3D11Device, ctx: ID3D11DeviceContext, buffers: usize) -> Self {
+        Self {
+            device,
+            ctx,
+            buffers: buffers.max(1),
+            staging: Vec::new(),
+            desc: D3D11_TEXTURE2D_DESC::default(),
+            next: 0,
+            pending: 0,
+        }
+    }
+
+    pub(crate) fn buffers(&self) -> usize {
+        self.buffers
+    }
+
+    /// Changes the number of staging textures, dropping frames still in flight.
+    pub(crate) fn set_buffers(&mut self, buffers: usize) {
+        let buffers = buffers.max(1);
+        if buffers != self.buffers {
+            self.buffers = buffers;
+            self.staging.clear();
+        }
+    }
+
+    /// Forgets queued copies so the next frame read is one queued after this call.
+    pub(crate) fn flush(&mut self) {
+        self.pending = 0;
+    }
+
+    /// Queues a copy of the BGRA or RGBA `texture`, or only its `region`, and reads
+    /// the oldest queued frame into `data`. Returns `false` while the pipeline
+    /// is still filling.
+    ///
+    /// Queued copies keep the region they were made with, so callers moving
+    /// the region with more than one buffer get back frames of an old one.
+    pub(crate) fn read(&mut self, texture: &ID3D11Texture2D, region: Option<Rect>, data: &mut Vec<u8>) -> Result<bool, String> {
+        let mut desc = staging_desc(texture);
+        if desc.Format != DXGI_FORMAT_B8G8R8A8_UNORM && desc.Format != DXGI_FORMAT_R8G8B8A8_UNORM {
+            return Err(format!("Unsupported desktop format {:?}", desc.Format));
+        }
+        let copy_box = match region {
+            Some(region) => {
+                if region.width == 0
+                    || region.height == 0
+                    || region.x + region.width > desc.Width
+                    || region.y + region.height > desc.Height
+                {
+                    return Err(format!("Readback region {:?} outside the {}x{} desktop", region, desc.Width, desc.Height));
+                }
+                desc.Width = region.width;
+                desc.Height = region.height;
+                Some(D3D11_BOX {
+                    left: region.x,
+                    top: region.y,
+                    front: 0,
+                    right: region.x + region.width,
+                    bottom: region.y + region.height,
+                    back: 1,
+                })
+            }
+            None => None,
+        };
+        if self.staging.is_empty()
+            || desc.Width != self.desc.Width
+            || desc.Height != self.desc.Height
+            || desc.Format != self.desc.Format
+        {
+            self.staging = (0..self.buffers)
+                .map(|_| create_staging(&self.device, &desc))
+                .collect::<Result<_, _>>()?;
+            self.desc = desc;
+            self.next = 0;
+            self.pending = 0;
+        }
+
+        let staging = &self.staging[self.next];
+        match copy_box {
+            Some(copy_box) => unsafe {
+                self.ctx.CopySubresourceRegion(staging, 0, 0, 0, 0, texture, 0, Some(&copy_box));
+            },
+            None => unsafe { self.ctx.CopyResource(staging, texture) },
+        }
+        self.next = (self.next + 1) % self.buffers;
+        self.pending = (self.pending + 1).min(self.buffers);
+        if self.pending < self.buffers {
+            return Ok(false);
+        }
+
+        // `next` now points at the oldest copy, which the next call will overwrite
+        map_rows(&self.ctx, &self.staging[self.next], &self.desc, data)?;
+        Ok(true)
+    }
+}
+
+/// CPU-readable description matching `texture`.
+fn staging_desc(texture: &ID3D11Texture2D) -> D3D11_TEXTURE2D_DESC {
+    let mut desc = D3D11_TEXTURE2D_DESC::default();
+    unsafe { texture.GetDesc(&mut desc) };
+    desc.Usage = D3D11_USAGE_STAGING;
+    desc.BindFlags = 0;
+    desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+    desc.MiscFlags = 0;
+    desc
+}
+
+fn create_staging(device: &ID3D11Device, desc: &D3D11_TEXTURE2D_DESC) -> Result<ID3D11Texture2D, String> {
+    let mut staging = None;
+    unsafe { device.CreateTexture2D(desc, None, Some(&mut staging)) }.map_err(|e| e.to_string())?;
+    staging.ok_or_else(|| "Failed to create staging texture".to_string())
+}
+
+/// Maps a 4-byte-per-pixel staging texture and copies its rows tightly packed into `data`.
+fn map_rows(
+    ctx: &ID3D11DeviceContext,
+    staging: &ID3D11Texture2D,
+    desc: &D3D11_TEXTURE2D_DESC,
+    data: &mut Vec<u8>,
+) -> Result<(), String> {
+    let row_bytes = desc.Width as usize * 4;
+    data.clear();
+    data.resize(row_bytes * desc.Height as usize, 0);
+    unsafe {
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        ctx.Map(staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped)).map_err(|e| e.to_string())?;
+        for row in 0..desc.Height as usize {
+            copy_nonoverlapping(
+                (mapped.pData as *const u8).add(row * mapped.RowPitch as usize),
+                data.as_mut_ptr().add(row * row_bytes),
+                row_bytes,
+            );
+        }
+        ctx.Unmap(staging, 0);
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! Intermediate frames blended from the two latest captures, so a capture
+//! running well below the display rate can still be played back smoothly.
+
+/// The frame the latest one replaced in the frame buffer.
+pub struct PreviousFrame {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Linear blend of two equally sized frames; `t` of 0 gives `previous` and 1
+/// gives `latest`.
+pub fn blend(previous: &[u8], latest: &[u8], t: f32) -> Vec<u8> {
+    let weight = (t.clamp(0.0, 1.0) * 256.0) as u32;
+    previous
+        .iter()
+        .zip(latest)
+        .map(|(&a, &b)| ((a as u32 * (256 - weight) + b as u32 * weight + 128) >> 8) as u8)
+        .collect()
+}
@@ -0,0 +1,78 @@
+//! Measurement of present-to-available capture latency.
+//!
+//! DWM stamps each composition with a performance counter value; comparing
+//! the composition a frame was acquired after with the moment that frame is
+//! published to the frame buffer gives the pipeline's latency.
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Dwm::{DwmGetCompositionTimingInfo, DWM_TIMING_INFO};
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+
+/// Most frames a single measurement may sample.
+pub const MAX_LATENCY_SAMPLES: u32 = 600;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LatencyReport {
+    samples: usize,
+    min_ms: f64,
+    avg_ms: f64,
+    max_ms: f64,
+}
+
+/// A measurement in progress, owned by the capture loop until it completes.
+pub struct LatencyProbe {
+    count: u32,
+    samples: Vec<f64>,
+    sender: oneshot::Sender<LatencyReport>,
+}
+
+impl LatencyProbe {
+    pub fn new(count: u32, sender: oneshot::Sender<LatencyReport>) -> Self {
+        Self { count, samples: Vec::with_capacity(count as usize), sender }
+    }
+
+    /// Records a frame composed at `composed_at` and published just now,
+    /// returning `None` once the report has been sent.
+    pub fn push(mut self, composed_at: u64) -> Option<Self> {
+        let (Some(now), Some(frequency)) = (counter(), frequency()) else {
+            return Some(self);
+        };
+        self.samples.push(now.saturating_sub(composed_at) as f64 * 1000.0 / frequency as f64);
+        if self.samples.len() < self.count as usize {
+            return Some(self);
+        }
+
+        let samples = &self.samples;
+        let _ = self.sender.send(LatencyReport {
+            samples: samples.len(),
+            min_ms: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            avg_ms: samples.iter().sum::<f64>() / samples.len() as f64,
+            max_ms: samples.iter().copied().fold(0.0, f64::max),
+        });
+        None
+    }
+}
+
+/// DWM's timing for the most recent composition.
+pub fn composition_timing() -> Option<DWM_TIMING_INFO> {
+    let mut info = DWM_TIMING_INFO {
+        cbSize: size_of::<DWM_TIMING_INFO>() as u32,
+        ..Default::default()
+    };
+    unsafe { DwmGetCompositionTimingInfo(HWND::default(), &mut info) }.ok()?;
+    Some(info)
+}
+
+fn counter() -> Option<u64> {
+    let mut count = 0;
+    unsafe { QueryPerformanceCounter(&mut count) }.ok()?;
+    Some(count as u64)
+}
+
+fn frequency() -> Option<u64> {
+    let mut frequency = 0;
+    unsafe { QueryPerformanceFrequency(&mut frequency) }.ok()?;
+    (frequency > 0).then_some(frequency as u64)
+}
@@ -0,0 +1,70 @@
+//! Detection of letterbox and pillarbox bars around fullscreen content.
+
+use crate::{pixel_offset, Rect};
+
+/// Frames a new content rectangle must persist for before it is applied,
+/// so fades and dark scenes don't make the crop jump around.
+const STABLE_FRAMES: u32 = 15;
+
+/// Left, top, right and bottom bar thickness in pixels.
+type Insets = [u32; 4];
+
+/// Tracks the black bars inside a crop, debouncing changes.
+#[derive(Default)]
+pub struct Letterbox {
+    /// Size of the crop the insets were measured in.
+    crop_size: (u32, u32),
+    applied: Insets,
+    candidate: Insets,
+    stable: u32,
+}
+
+impl Letterbox {
+    /// Measures the bars of `crop` in a BGRA frame `width` pixels wide and
+    /// returns the crop with the currently applied bars removed.
+    ///
+    /// A pixel is black when none of its colour channels exceed `threshold`.
+    pub fn update(&mut self, data: &[u8], width: u32, crop: Rect, threshold: u8) -> Rect {
+        if (crop.width, crop.height) != self.crop_size {
+            *self = Letterbox { crop_size: (crop.width, crop.height), ..Default::default() };
+        }
+
+        // An entirely black frame says nothing about where the content is
+        if let Some(insets) = detect(data, width, crop, threshold) {
+            if insets == self.candidate {
+                self.stable = self.stable.saturating_add(1);
+            } else {
+                self.candidate = insets;
+                self.stable = 1;
+            }
+            if self.stable >= STABLE_FRAMES {
+                self.applied = self.candidate;
+            }
+        }
+
+        let [left, top, right, bottom] = self.applied;
+        Rect {
+            x: crop.x + left,
+            y: crop.y + top,
+            width: crop.width - left - right,
+            height: crop.height - top - bottom,
+        }
+    }
+}
+
+fn detect(data: &[u8], width: u32, crop: Rect, threshold: u8) -> Option<Insets> {
+    let dark = |x: u32, y: u32| {
+        let idx = pixel_offset(x, y, width);
+        data.get(idx..idx + 3).is_none_or(|bgr| bgr.iter().all(|&c| c <= threshold))
+    };
+    let dark_row = |y: u32| (crop.x..crop.x + crop.width).all(|x| dark(x, y));
+
+    let top = (0..crop.height).find(|&dy| !dark_row(crop.y + dy))?;
+    let bottom = (0..crop.height).rev().find(|&dy| !dark_row(crop.y + dy)).unwrap_or(top);
+    // Only rows with content need checking for side bars
+    let dark_column = |x: u32| (crop.y + top..=crop.y + bottom).all(|y| dark(x, y));
+    let left = (0..crop.width).find(|&dx| !dark_column(crop.x + dx)).unwrap_or(0);
+    let right = (0..crop.width).rev().find(|&dx| !dark_column(crop.x + dx)).unwrap_or(left);
+
+    Some([left, top, crop.width - 1 - right, crop.height - 1 - bottom])
+}
@@ -1,235 +1,4481 @@
+mod adaptive;
+mod bitrate;
+mod burst;
+mod capabilities;
+mod consumers;
+mod cursor;
+mod delta;
+mod diff;
+mod displays;
+mod dpi;
+mod errors;
+mod gdi;
+mod gpu;
+mod interpolate;
+mod latency;
+mod letterbox;
+mod mock;
+mod ocr;
+mod overlay;
+mod palette;
+mod process;
+mod profiles;
+mod protection;
+mod replay;
+mod resample;
+mod rotation;
+mod smoothing;
+mod source;
+mod stream;
+mod tensor;
+mod thread;
+mod video;
+mod visualize;
+mod watermark;
+mod webp;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tauri::{Emitter, Manager, Runtime, State};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::ipc;
+use tauri::{Emitter, Manager, RunEvent, Runtime, State};
+use tokio::sync::{oneshot, watch};
+use log::{debug, error, info, warn, LevelFilter};
 use windows::Win32::UI::WindowsAndMessaging::{
+    GetWindowDisplayAffinity,
+    IsWindow,
     SetWindowDisplayAffinity,
     WINDOW_DISPLAY_AFFINITY,
 };
-use windows::Win32::Foundation::HWND;
-use win_desktop_duplication::{devices::*, tex_reader::*, co_init, set_process_dpi_awareness, DesktopDuplicationApi, DuplicationApiOptions};
-use parking_lot::RwLock;
-use win_desktop_duplication::errors::DDApiError;
+use windows::Win32::Foundation::{HWND, RECT};
+use win_desktop_duplication::{devices::*, outputs::Display, tex_reader::*, texture::Texture, co_init, DesktopDuplicationApi, DuplicationApiOptions};
+use parking_lot::{Mutex, RwLock};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+
+use adaptive::{AdaptiveBounds, AdaptiveScale};
+use bitrate::QualityController;
+use burst::{Burst, BurstRequest, MAX_BURST_FRAMES};
+use capabilities::Capabilities;
+use consumers::{ConsumerId, ConsumerInfo, Consumers};
+use cursor::{CursorCapture, CursorMode, CursorShape};
+use delta::{DeltaConfig, DeltaEncoder, DirtyTiles, FrameDelta};
+use diff::{Baseline, FrameDiff};
+use displays::{ColorInfo, DisplayInfo};
+use dpi::DpiAwareness;
+use errors::{ErrorKind, ErrorLimiter, LastError};
+use gpu::GpuPriority;
+use interpolate::PreviousFrame;
+use latency::{LatencyProbe, LatencyReport, MAX_LATENCY_SAMPLES};
+use letterbox::Letterbox;
+use mock::{MockConfig, MockSource};
+use ocr::{OcrFrame, Threshold};
+use overlay::OverlayStats;
+use palette::PaletteMode;
+use profiles::Profile;
+use protection::ProtectionCheck;
+use replay::ReplayBuffer;
+use resample::{Downscale, Interpolation};
+use rotation::{Rotation, ViewerOrientation};
+use smoothing::TemporalFilter;
+use source::{CaptureSource, DuplicationSource, Failure, SourceOptions};
+use stream::{StreamConfig, StreamFrame, StreamHandshake, Streams};
+use tensor::{Normalization, TensorLayout};
+use thread::{ThreadPriority, ThreadTuning};
+use video::{RecordingSummary, VideoCodec, VideoRecording};
+use visualize::{Visualization, Visualizer};
+use watermark::{Corner, Watermark};
+
+/// How the alpha channel of the output is treated.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    /// Alpha is passed through untouched.
+    Straight,
+    /// RGB is multiplied by alpha.
+    Premultiplied,
+    /// Alpha is always 255, hiding transparent-desktop artifacts.
+    #[default]
+    ForceOpaque,
+}
+
+/// Byte order of the pixels a source hands over.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelOrder {
+    /// `DXGI_FORMAT_B8G8R8A8_UNORM`, what desktop duplication normally produces.
+    #[default]
+    Bgra,
+    Rgba,
+}
+
+impl ChannelOrder {
+    /// Reorders one source pixel to RGBA.
+    #[inline]
+    fn to_rgba(self, p: &[u8]) -> [u8; 4] {
+        match self {
+            ChannelOrder::Bgra => [p[2], p[1], p[0], p[3]],
+            ChannelOrder::Rgba => [p[0], p[1], p[2], p[3]],
+        }
+    }
+}
+
+/// A single colour channel, in RGBA order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Channel {
+    Red = 0,
+    Green = 1,
+    Blue = 2,
+    Alpha = 3,
+}
+
+/// A rectangle in desktop texture pixels.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// How a fixed region repositions itself when the window is resized.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegionAnchor {
+    /// Keep the same offset from the window's top-left corner.
+    #[default]
+    TopLeft,
+    /// Keep the same offset from the window's centre.
+    Center,
+    /// Scale the offset with the window size.
+    Proportional,
+}
+
+/// A capture region relative to the window, remembered alongside the
+/// window size it was chosen for.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CaptureRegion {
+    rect: Rect,
+    reference_width: u32,
+    reference_height: u32,
+}
+
+impl CaptureRegion {
+    /// Returns the region in window-local coordinates for the current window size.
+    fn resolve(&self, anchor: RegionAnchor, window_width: u32, window_height: u32) -> Rect {
+        let width = self.rect.width.min(window_width);
+        let height = self.rect.height.min(window_height);
+
+        let (x, y) = match anchor {
+            RegionAnchor::TopLeft => (self.rect.x as i64, self.rect.y as i64),
+            RegionAnchor::Center => (
+                self.rect.x as i64 + (window_width as i64 - self.reference_width as i64) / 2,
+                self.rect.y as i64 + (window_height as i64 - self.reference_height as i64) / 2,
+            ),
+            RegionAnchor::Proportional => (
+                self.rect.x as i64 * window_width as i64 / self.reference_width.max(1) as i64,
+                self.rect.y as i64 * window_height as i64 / self.reference_height.max(1) as i64,
+            ),
+        };
+
+        Rect {
+            x: x.clamp(0, (window_width - width) as i64) as u32,
+            y: y.clamp(0, (window_height - height) as i64) as u32,
+            width,
+            height,
+        }
+    }
+}
+
+/// A display-relative crop rectangle updated without locking, packed as four
+/// `u16`s so the loop never sees a torn update. Zero width means unset.
+#[derive(Default)]
+pub struct DynamicRegion(AtomicU64);
+
+impl DynamicRegion {
+    fn load(&self) -> Option<Rect> {
+        let packed = self.0.load(Ordering::Acquire);
+        let field = |shift: u32| (packed >> shift) as u16 as u32;
+        let rect = Rect { x: field(48), y: field(32), width: field(16), height: field(0) };
+        (rect.width > 0 && rect.height > 0).then_some(rect)
+    }
+
+    fn store(&self, rect: Option<Rect>) -> Result<(), String> {
+        let packed = match rect {
+            Some(Rect { x, y, width, height }) => {
+                let field = |value: u32| {
+                    u16::try_from(value)
+                        .map(u64::from)
+                        .map_err(|_| "Region coordinates must fit in 16 bits".to_string())
+                };
+                field(x)? << 48 | field(y)? << 32 | field(width)? << 16 | field(height)?
+            }
+            None => 0,
+        };
+        self.0.store(packed, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Metadata describing how the current frame was produced.
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct FrameMeta {
+    /// Matches the `frame-ready` payload for this frame.
+    sequence: u32,
+    alpha_mode: AlphaMode,
+    /// When set, the frame is this channel as grayscale, replicated to opaque RGBA.
+    channel: Option<Channel>,
+    /// When set, the frame shows this visualization rather than the capture alone.
+    visualization: Option<Visualization>,
+    /// Desktop presents since the previous captured frame.
+    accumulated_frames: u32,
+    /// Whether the app window had to be masked out because the OS didn't
+    /// exclude it from capture.
+    self_masked: bool,
+    /// Source rectangle of this frame, relative to the captured display, or
+    /// to the window when it is rendered with `FrameSource::PrintWindow`.
+    crop: Rect,
+    /// Top-left of that display or window on the virtual desktop; add it to
+    /// `crop` for screen coordinates.
+    display_origin: (i32, i32),
+    /// CRC32 of the frame bytes, when checksums are enabled.
+    checksum: Option<u32>,
+    /// Whether the frame was turned a quarter clockwise for the viewer
+    /// orientation, so `crop` runs along its height.
+    turned: bool,
+    /// Bytes per row, including any padding `get_frame_data` adds for the
+    /// output alignment; `width` stays the logical width.
+    stride: u32,
+    /// Where the crop lands in the frame while the output size is locked;
+    /// the rest is fill colour.
+    content: Option<Rect>,
+    /// Wall-clock time the frame was acquired, in microseconds since the Unix epoch.
+    timestamp_us: u64,
+    /// Clockwise turn applied by `set_rotation_angle`, in degrees; the frame
+    /// is the turned crop on a canvas grown to fit it.
+    angle: f32,
+}
+
+/// A message sent through a frame channel. Every frame sends a small
+/// `Meta`; a `Data` with its pixels follows when the subscriber wants them.
+#[derive(Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum FrameMessage {
+    Meta {
+        sequence: u32,
+        width: u32,
+        height: u32,
+        meta: FrameMeta,
+        /// Area of the frame that changed since the previous one; empty when
+        /// the pixels are identical.
+        dirty: Rect,
+        /// Pointer position on the virtual desktop; `None` while it is hidden.
+        cursor: Option<(i32, i32)>,
+    },
+    Data {
+        sequence: u32,
+        /// RGBA bytes.
+        data: Vec<u8>,
+    },
+}
+
+/// A subscriber registered with `start_frame_channel`.
+struct FrameChannel {
+    channel: ipc::Channel<FrameMessage>,
+    with_data: bool,
+    /// Only send pixels that differ from the last ones sent.
+    skip_unchanged: bool,
+    /// Sequence of the last frame whose pixels were sent.
+    last_data: Option<u32>,
+}
+
+/// Rolling statistics, published once per second as `capture-stats`.
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct CaptureStats {
+    fps: f32,
+    /// Desktop presents over the last second. Well above `fps` means capture
+    /// is the bottleneck; close to `fps` means the desktop is simply static.
+    desktop_presents: u32,
+    /// Average time per frame spent reading the desktop back from the GPU.
+    readback_ms: f32,
+    readback_buffers: usize,
+    /// Bytes held by the live frame, thumbnail and replay buffer.
+    memory_bytes: usize,
+    /// Quality `get_frame_webp` adapted to the stream bitrate, if one is set.
+    stream_quality: Option<u8>,
+}
 
 #[derive(Default, Clone)]
 pub struct FrameBuffer {
     data: Vec<u8>,
     width: u32,
     height: u32,
-    fps: u32
+    fps: f32,
+    meta: FrameMeta,
+    stats: CaptureStats,
+    dirty: DirtyTiles,
+    /// When the frame was acquired, for freshness checks.
+    captured_at: Option<Instant>,
+    /// Size before `set_rotation_angle` turned it.
+    unrotated: (u32, u32),
+}
+
+/// Payload of `capture-ready`.
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameSize {
+    width: u32,
+    height: u32,
+}
+
+/// Lifecycle of the capture loop, published as `capture-status`.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum CaptureStatus {
+    /// No frame has been produced yet.
+    #[default]
+    Starting,
+    Running,
+    /// The GPU device was lost and is being rebuilt from a fresh adapter.
+    Recovering,
+    /// The captured window is entirely off the display; no frames are
+    /// produced until it returns.
+    Offscreen,
+    /// `safe_mode` stopped capture; it stays stopped until the app restarts.
+    SafeMode,
+}
+
+/// When the capture loop acquires frames.
+///
+/// Either way frames are complete: `DesktopDuplicationApi` copies each
+/// acquired desktop image into its own texture and releases the duplication
+/// frame before returning it, and our readback is queued after that copy on
+/// the same device context, so a present landing mid-readback can't tear it.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncMode {
+    /// Poll with `acquire_next_frame_now`, which never blocks and returns the
+    /// last frame when nothing new was presented. Lowest latency.
+    #[default]
+    Immediate,
+    /// Wait for the display's vertical blank before acquiring, so each frame
+    /// is the one being scanned out. Adds up to a refresh interval of latency
+    /// and caps capture at the refresh rate.
+    Vsync,
+}
+
+/// How often the capture loop runs.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pacing {
+    /// As fast as frames can be acquired and processed.
+    #[default]
+    Unlimited,
+    /// Once per refresh of the captured display, using its exact period.
+    MatchRefresh,
+    FixedFps(u32),
+    /// On a variable refresh display, once per frame DWM composes, so the
+    /// loop follows the present rate as it varies; elsewhere `MatchRefresh`.
+    Adaptive,
+}
+
+impl Pacing {
+    /// Time between iterations, given the display's refresh rate if known.
+    fn period(self, refresh_rate: Option<f64>) -> Option<Duration> {
+        match self {
+            Pacing::Unlimited => None,
+            // Without a reported mode, 60Hz is the safest guess
+            Pacing::MatchRefresh | Pacing::Adaptive => Some(Duration::from_secs_f64(1.0 / refresh_rate.unwrap_or(60.0))),
+            Pacing::FixedFps(fps) => Some(Duration::from_secs_f64(1.0 / fps.max(1) as f64)),
+        }
+    }
+}
+
+/// Where frame pixels come from.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FrameSource {
+    /// The duplicated desktop under the window, as composited.
+    #[default]
+    DesktopDuplication,
+    /// The window's own rendering via `PrintWindow`, keeping the per-pixel
+    /// alpha of layered windows instead of the desktop showing through.
+    PrintWindow,
+    /// Generated test frames, needing no display or GPU.
+    Mock(MockConfig),
+    /// The duplicated desktop under another process's main window instead
+    /// of ours.
+    ProcessWindow { pid: u32 },
+    /// A `2 * radius` square centred on the pointer, following it, scaled up
+    /// `zoom` times with bilinear filtering.
+    CursorMagnifier { radius: u32, zoom: f32 },
+}
+
+/// What gives when stored frames exceed the memory budget.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryPolicy {
+    /// Evict the oldest replay frames.
+    #[default]
+    DropOldest,
+    /// Stop adding frames to the replay buffer until there is room.
+    DropNewest,
+    /// Halve the output resolution until usage fits, evicting replay frames meanwhile.
+    ReduceQuality,
+}
+
+/// The display currently being captured, as reported by `get_capture_info`.
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct CaptureInfo {
+    /// Device name; `None` until capture has started.
+    display: Option<String>,
+    origin: (i32, i32),
+    /// Detected refresh rate in Hz.
+    refresh_rate: Option<f64>,
+    rotation: Rotation,
+    /// Priority the capture thread is actually running at.
+    thread_priority: Option<ThreadPriority>,
+    /// Byte order of the duplicated frames, from their texture format.
+    channel_order: Option<ChannelOrder>,
+    /// Whether the GPU is converting frames to RGBA as they are read back;
+    /// see `set_gpu_format_convert`.
+    gpu_format_convert: bool,
+    /// GPU priority the capture device actually has; `None` if it couldn't be set.
+    gpu_priority: Option<GpuPriority>,
+    /// Whether the display may refresh at a variable rate, which
+    /// `Pacing::Adaptive` follows.
+    variable_refresh: bool,
+}
+
+/// Settings the capture loop picks up on its next iteration.
+///
+/// Saved whole as a named profile; fields missing from older profiles keep
+/// their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Output is the crop divided by this, unless `output_size` is set.
+    scale_factor: u32,
+    alpha_mode: AlphaMode,
+    region: Option<CaptureRegion>,
+    region_anchor: RegionAnchor,
+    /// Minimum time between `frame-ready` events; zero emits every frame.
+    emit_interval: Duration,
+    /// When set, changed tiles are pushed as `frame-delta` events.
+    delta: Option<DeltaConfig>,
+    /// Fixed output size; `None` divides the crop by the scale factor.
+    output_size: Option<(u32, u32)>,
+    /// Output size no crop, memory policy or orientation may change; the
+    /// crop is fitted inside it.
+    locked_size: Option<(u32, u32)>,
+    /// Size of the thumbnail output; `None` disables it.
+    thumbnail_size: Option<(u32, u32)>,
+    /// Filter used along axes where the output is larger than the crop.
+    upscale_filter: Interpolation,
+    /// Filter used along axes where the output is smaller than the crop.
+    downscale_filter: Downscale,
+    channel: Option<Channel>,
+    cursor: CursorMode,
+    /// RGBA used wherever output pixels have no desktop source or are masked.
+    fill_color: [u8; 4],
+    /// Width in output pixels of the alpha fade at each edge; zero disables it.
+    edge_fade: u32,
+    /// Weight of the previous frame when smoothing out noise; zero disables it.
+    temporal_smoothing: f32,
+    /// Rendered in place of the captured image; `None` shows it as captured.
+    visualization: Option<Visualization>,
+    /// How much of the visualization shows over the capture, `0.0..=1.0`.
+    visualization_opacity: f32,
+    /// Burn fps, size, drops and latency into the top-left corner.
+    debug_overlay: bool,
+    /// Device name of the display to capture; `None` captures the primary one.
+    /// Not saved in profiles, as names change with the hardware attached.
+    #[serde(skip)]
+    display: Option<String>,
+    sync_mode: SyncMode,
+    pacing: Pacing,
+    /// Compute a CRC32 of every frame for integrity testing.
+    checksum: bool,
+    /// Staging textures used for readback; more than one pipelines the GPU
+    /// copy with processing at the cost of latency. Zero behaves like one.
+    readback_buffers: usize,
+    /// Copy only the crop off the GPU rather than the whole display, where
+    /// nothing needs the rest.
+    crop_readback: bool,
+    capture_thread: ThreadTuning,
+    /// GPU scheduling priority of the capture device.
+    gpu_priority: GpuPriority,
+    /// Have the GPU convert frames to RGBA during readback where it can.
+    gpu_format_convert: bool,
+    /// Scale factors to move between while processing can't keep up;
+    /// overrides `scale_factor` when set.
+    adaptive_quality: Option<AdaptiveBounds>,
+    /// Crop away black bars darker than this threshold.
+    autocrop_bars: Option<u8>,
+    /// Width, height and RGBA colour served before the first frame arrives.
+    placeholder: Option<(u32, u32, [u8; 4])>,
+    /// Minimum time between successful `get_frame_data` calls; zero disables.
+    min_fetch_interval: Duration,
+    /// Pixels to over-crop each edge by while the window is moving.
+    drag_compensation: u32,
+    /// Ceiling on bytes held across all stored frames; `None` is unlimited.
+    frame_memory_budget: Option<usize>,
+    memory_policy: MemoryPolicy,
+    source: FrameSource,
+    parallel_processing: bool,
+    /// Row alignment in bytes for frames returned by `get_frame_data`.
+    output_alignment: u32,
+    /// Orientation to turn output frames to; `None` keeps the source's.
+    viewer_orientation: Option<ViewerOrientation>,
+    /// Clockwise turn of output frames in degrees, any angle; zero disables it.
+    rotation_angle: f32,
+    /// Keep the previous frame for `get_interpolated_frame`.
+    frame_interpolation: bool,
+    /// How `get_frame_indexed` quantizes; `None` disables it.
+    output_palette: Option<PaletteMode>,
+    /// Display-relative rectangles filled with the fill colour in every output.
+    exclusion_rects: Vec<Rect>,
+    /// Also fill the parts of the display outside its work area, i.e. the taskbar.
+    exclude_taskbar: bool,
+    /// Scale each display to a common DPI in whole-desktop captures.
+    dpi_normalize: bool,
+    /// Window whose pixels alone are kept, the rest made transparent. Not
+    /// saved in profiles, as handles only live as long as the window.
+    #[serde(skip)]
+    mask_to_window: Option<usize>,
+    /// Byte order captured pixels are converted from.
+    source_order: ChannelOrder,
+    /// Take `source_order` from each frame's texture format instead.
+    autodetect_channels: bool,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            scale_factor: DEFAULT_SCALE_FACTOR,
+            alpha_mode: AlphaMode::default(),
+            region: None,
+            region_anchor: RegionAnchor::default(),
+            emit_interval: Duration::ZERO,
+            delta: None,
+            output_size: None,
+            locked_size: None,
+            thumbnail_size: None,
+            upscale_filter: Interpolation::default(),
+            downscale_filter: Downscale::default(),
+            channel: None,
+            cursor: CursorMode::default(),
+            fill_color: [0, 0, 0, 255],
+            edge_fade: 0,
+            temporal_smoothing: 0.0,
+            visualization: None,
+            visualization_opacity: 1.0,
+            debug_overlay: false,
+            display: None,
+            sync_mode: SyncMode::default(),
+            pacing: Pacing::default(),
+            checksum: false,
+            readback_buffers: 1,
+            crop_readback: true,
+            capture_thread: ThreadTuning::default(),
+            gpu_priority: GpuPriority::default(),
+            gpu_format_convert: false,
+            adaptive_quality: None,
+            autocrop_bars: None,
+            placeholder: None,
+            min_fetch_interval: Duration::ZERO,
+            drag_compensation: 0,
+            frame_memory_budget: None,
+            memory_policy: MemoryPolicy::default(),
+            source: FrameSource::default(),
+            parallel_processing: false,
+            output_alignment: 1,
+            viewer_orientation: None,
+            rotation_angle: 0.0,
+            frame_interpolation: false,
+            output_palette: None,
+            exclusion_rects: Vec::new(),
+            exclude_taskbar: false,
+            dpi_normalize: false,
+            mask_to_window: None,
+            source_order: ChannelOrder::default(),
+            autodetect_channels: false,
+        }
+    }
+}
+
+/// The part of `CaptureSettings` remembered per display.
+#[derive(Clone, Debug)]
+struct DisplayProfile {
+    scale_factor: u32,
+    region: Option<CaptureRegion>,
+    region_anchor: RegionAnchor,
+    output_size: Option<(u32, u32)>,
+    thumbnail_size: Option<(u32, u32)>,
+}
+
+impl CaptureSettings {
+    fn profile(&self) -> DisplayProfile {
+        DisplayProfile {
+            scale_factor: self.scale_factor,
+            region: self.region,
+            region_anchor: self.region_anchor,
+            output_size: self.output_size,
+            thumbnail_size: self.thumbnail_size,
+        }
+    }
+
+    fn apply_profile(&mut self, profile: DisplayProfile) {
+        self.scale_factor = profile.scale_factor;
+        self.region = profile.region;
+        self.region_anchor = profile.region_anchor;
+        self.output_size = profile.output_size;
+        self.thumbnail_size = profile.thumbnail_size;
+    }
+
+    /// Runs the checks of each setter, for settings that didn't come through
+    /// them, such as a loaded profile.
+    fn validate(&self) -> Result<(), String> {
+        validate_scale_factor(self.scale_factor)?;
+        if let Some(region) = self.region {
+            validate_region(region.rect)?;
+        }
+        for (size, what) in [
+            (self.output_size, "Output"),
+            (self.locked_size, "Output"),
+            (self.thumbnail_size, "Thumbnail"),
+            (self.placeholder.map(|(width, height, _)| (width, height)), "Placeholder"),
+        ] {
+            if let Some((width, height)) = size {
+                validate_size(width, height, what)?;
+            }
+        }
+        validate_cursor_mode(self.cursor)?;
+        validate_smoothing(self.temporal_smoothing)?;
+        validate_opacity(self.visualization_opacity)?;
+        validate_pacing(self.pacing)?;
+        validate_readback_buffers(self.readback_buffers)?;
+        self.capture_thread.validate()?;
+        if let Some(bounds) = self.adaptive_quality {
+            bounds.validate()?;
+        }
+        validate_source(self.source)?;
+        validate_output_alignment(self.output_alignment)?;
+        if !(0.0..360.0).contains(&self.rotation_angle) {
+            return Err("Angle must be between 0 and 360 degrees".to_string());
+        }
+        if let Some(mode) = self.output_palette {
+            mode.validate()?;
+        }
+        Ok(())
+    }
+
+    fn source_options(&self) -> SourceOptions {
+        SourceOptions { readback_buffers: self.readback_buffers, source: self.source }
+    }
+
+    fn process_options(&self) -> ProcessOptions {
+        ProcessOptions {
+            alpha_mode: self.alpha_mode,
+            upscale_filter: self.upscale_filter,
+            downscale_filter: self.downscale_filter,
+            channel: self.channel,
+            fill: self.fill_color,
+            parallel: self.parallel_processing,
+            source_order: self.source_order,
+        }
+    }
+}
+
+/// Per-pixel options for `process_image`.
+#[derive(Default, Clone, Copy, Debug)]
+struct ProcessOptions {
+    alpha_mode: AlphaMode,
+    upscale_filter: Interpolation,
+    downscale_filter: Downscale,
+    channel: Option<Channel>,
+    /// Output colour, as RGBA, for pixels with no source.
+    fill: [u8; 4],
+    /// Split the output into row tiles processed across threads.
+    parallel: bool,
+    source_order: ChannelOrder,
+}
+
+impl ProcessOptions {
+    /// Converts one source pixel, already in RGBA order, to its output value.
+    #[inline]
+    fn apply(&self, rgba: [u8; 4]) -> [u8; 4] {
+        match self.channel {
+            Some(channel) => {
+                let value = rgba[channel as usize];
+                [value, value, value, 255]
+            }
+            None => apply_alpha_mode(rgba, self.alpha_mode),
+        }
+    }
 }
 
+const DEFAULT_SCALE_FACTOR: u32 = 4;
+/// Most verbose level logged until `set_log_level` changes it.
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+/// How long shutdown waits for the capture task before giving up on it.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long `try_get_frame_data` waits for the read lock before reporting `Busy`.
+const FRAME_READ_TIMEOUT: Duration = Duration::from_millis(50);
+/// How long to wait between attempts to rebuild a lost GPU device.
+const DEVICE_RECOVERY_INTERVAL: Duration = Duration::from_millis(500);
+/// How often a minimized window is checked for being restored.
+const MINIMIZED_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Consecutive window geometry failures tolerated before capture gives up.
+const MAX_GEOMETRY_FAILURES: u32 = 30;
+/// How long commands that ask the loop for its next frame wait for it.
+const FRAME_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long one-shot captures wait for a new duplication to deliver its first frame.
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long a newly opened source is polled for its first frame before the
+/// loop starts regardless.
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(1);
+const WARM_UP_POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How often `Pacing::Adaptive` checks for a new composition, and how long
+/// it waits for one before running the loop regardless.
+const FRAME_ARRIVAL_POLL_INTERVAL: Duration = Duration::from_millis(1);
+const FRAME_ARRIVAL_TIMEOUT: Duration = Duration::from_millis(100);
+/// Largest zoom `FrameSource::CursorMagnifier` and `capture_around_cursor` accept.
+const MAX_MAGNIFIER_ZOOM: f32 = 16.0;
+/// Largest row alignment `set_output_alignment` accepts.
+const MAX_OUTPUT_ALIGNMENT: u32 = 4096;
+/// Most `MemoryPolicy::ReduceQuality` will divide the output by.
+const MAX_MEMORY_DIVISOR: u32 = 8;
+/// How long `capture_burst` waits for all of its frames.
+const BURST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `capture_behind_window` leaves DWM to recompose after hiding
+/// the window.
+const HIDE_SETTLE_TIME: Duration = Duration::from_millis(50);
+
 #[derive(Default, Clone)]
 pub struct CaptureState {
     frame_buffer: Arc<RwLock<FrameBuffer>>,
+    settings: Arc<RwLock<CaptureSettings>>,
+    shutdown: Arc<AtomicBool>,
+    /// Capture threads that haven't exited yet, however they end.
+    capture_threads: Arc<watch::Sender<usize>>,
+    /// Whether the OS honours `WDA_EXCLUDEFROMCAPTURE` for our window.
+    self_excluded: Arc<AtomicBool>,
+    /// Set by consumers that need the next `frame-delta` to be a keyframe.
+    keyframe_requested: Arc<AtomicBool>,
+    /// Set by `flush` to drop buffered frames and restart sequences.
+    flush_requested: Arc<AtomicBool>,
+    replay: Arc<Mutex<ReplayBuffer>>,
+    status: Arc<RwLock<CaptureStatus>>,
+    info: Arc<RwLock<CaptureInfo>>,
+    dynamic_region: Arc<DynamicRegion>,
+    /// Second, smaller output of the same frames, when enabled.
+    thumbnail: Arc<RwLock<FrameBuffer>>,
+    hires_request: Arc<Mutex<Option<HiresRequest>>>,
+    raw_request: Arc<Mutex<Option<oneshot::Sender<RawFrame>>>>,
+    grab_request: Arc<Mutex<Option<GrabRequest>>>,
+    trigger: Arc<Mutex<Option<TriggerRequest>>>,
+    burst_request: Arc<Mutex<Option<BurstRequest>>>,
+    latency_probe: Arc<Mutex<Option<LatencyProbe>>>,
+    /// Settings to restore per display device name; `None` is the primary display.
+    display_profiles: Arc<Mutex<HashMap<Option<String>, DisplayProfile>>>,
+    /// Reference frame for `capture_diff`.
+    baseline: Arc<Mutex<Option<Baseline>>>,
+    /// When `get_frame_data` last returned a frame.
+    last_fetch: Arc<Mutex<Option<Instant>>>,
+    errors: Arc<Mutex<ErrorLimiter>>,
+    frame_channel: Arc<Mutex<Option<FrameChannel>>>,
+    /// Consumers fed frames in the format each negotiated.
+    streams: Arc<Mutex<Streams>>,
+    consumers: Arc<Mutex<Consumers>>,
+    video: Arc<Mutex<Option<VideoRecording>>>,
+    /// Kept out of `CaptureSettings` so profiles don't carry the image.
+    watermark: Arc<Mutex<Option<Watermark>>>,
+    /// Shared with the capture loop so both reuse one cached cursor bitmap.
+    cursor: Arc<Mutex<CursorCapture>>,
+    /// Sequence of the latest frame written to the frame buffer.
+    frame_published: Arc<watch::Sender<u32>>,
+    stream_quality: Arc<Mutex<QualityController>>,
+    /// The frame before the current one, while interpolation is enabled.
+    previous_frame: Arc<Mutex<Option<PreviousFrame>>>,
 }
 
 impl CaptureState {
     fn new() -> Self {
         Self {
             frame_buffer: Arc::new(RwLock::new(FrameBuffer::default())),
+            settings: Arc::new(RwLock::new(CaptureSettings::default())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            capture_threads: Arc::new(watch::Sender::new(0)),
+            self_excluded: Arc::new(AtomicBool::new(false)),
+            keyframe_requested: Arc::new(AtomicBool::new(false)),
+            flush_requested: Arc::new(AtomicBool::new(false)),
+            replay: Arc::new(Mutex::new(ReplayBuffer::default())),
+            status: Arc::new(RwLock::new(CaptureStatus::default())),
+            info: Arc::new(RwLock::new(CaptureInfo::default())),
+            dynamic_region: Arc::new(DynamicRegion::default()),
+            thumbnail: Arc::new(RwLock::new(FrameBuffer::default())),
+            hires_request: Arc::new(Mutex::new(None)),
+            raw_request: Arc::new(Mutex::new(None)),
+            grab_request: Arc::new(Mutex::new(None)),
+            trigger: Arc::new(Mutex::new(None)),
+            burst_request: Arc::new(Mutex::new(None)),
+            latency_probe: Arc::new(Mutex::new(None)),
+            display_profiles: Arc::new(Mutex::new(HashMap::new())),
+            baseline: Arc::new(Mutex::new(None)),
+            last_fetch: Arc::new(Mutex::new(None)),
+            errors: Arc::new(Mutex::new(ErrorLimiter::default())),
+            frame_channel: Arc::new(Mutex::new(None)),
+            streams: Arc::new(Mutex::new(Streams::default())),
+            consumers: Arc::new(Mutex::new(Consumers::default())),
+            video: Arc::new(Mutex::new(None)),
+            watermark: Arc::new(Mutex::new(None)),
+            cursor: Arc::new(Mutex::new(CursorCapture::default())),
+            frame_published: Arc::new(watch::Sender::new(0)),
+            stream_quality: Arc::new(Mutex::new(QualityController::default())),
+            previous_frame: Arc::new(Mutex::new(None)),
         }
     }
+
+    /// Whether the capture loop is running and hasn't been asked to stop.
+    fn is_capturing(&self) -> bool {
+        !self.shutdown.load(Ordering::Acquire) && *self.capture_threads.borrow() > 0
+    }
+
+    /// Signals the capture loop to exit and waits a bounded time for its
+    /// thread to finish.
+    ///
+    /// Blocking work can't be cancelled, so on timeout the thread is left to
+    /// exit after its current frame.
+    /// Never touches the frame buffer lock, so it cannot hang on a reader or writer.
+    async fn shutdown(&self) -> Result<(), String> {
+        self.shutdown.store(true, Ordering::Release);
+
+        let mut threads = self.capture_threads.subscribe();
+        let stopped = tokio::time::timeout(SHUTDOWN_TIMEOUT, threads.wait_for(|&threads| threads == 0)).await;
+        stopped
+            .map(|_| ())
+            .map_err(|_| "Timed out waiting for the capture task to stop".to_string())
+    }
 }
 
-fn enable_capture_protection<R: Runtime>(window: &tauri::Window<R>) -> Result<(), String> {
+/// Enables capture protection and reports whether the window is actually
+/// excluded from capture, rather than just blacked out.
+fn enable_capture_protection<R: Runtime>(window: &tauri::Window<R>) -> Result<bool, String> {
     #[cfg(target_os = "windows")]
     {
         let hwnd = HWND(window.hwnd().unwrap().0);
         let affinity = WINDOW_DISPLAY_AFFINITY(0x00000011);
+        let mut applied = 0u32;
         unsafe {
             SetWindowDisplayAffinity(hwnd, affinity).map_err(|e| e.to_string())?;
+            GetWindowDisplayAffinity(hwnd, &mut applied).map_err(|e| e.to_string())?;
         }
+        Ok(applied == affinity.0)
+    }
+    #[cfg(not(target_os = "windows"))]
+    Ok(false)
+}
+
+/// Lets the window be seen and captured again, e.g. for screen sharing.
+fn disable_capture_protection<R: Runtime>(window: &tauri::Window<R>) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        let hwnd = HWND(window.hwnd().map_err(|e| e.to_string())?.0);
+        unsafe { SetWindowDisplayAffinity(hwnd, WINDOW_DISPLAY_AFFINITY(0)) }.map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
-fn configure_window<R: Runtime>(window: &tauri::Window<R>) -> Result<(), String> {
+/// What this system supports: displays, adapters, window exclusion, HDR,
+/// texture limits, GPU scaling and refresh rates.
+#[tauri::command]
+async fn get_capabilities() -> Result<Capabilities, String> {
+    tauri::async_runtime::spawn_blocking(|| {
+        dpi::ensure_aware();
+        co_init();
+        capabilities::probe()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Re-applies capture protection, then checks whether the window's content
+/// actually shows up when its screen area is captured.
+#[tauri::command]
+async fn verify_protection(window: tauri::Window, state: State<'_, CaptureState>) -> Result<ProtectionCheck, String> {
+    let excluded = enable_capture_protection(&window)?;
+    state.self_excluded.store(excluded, Ordering::Release);
+    // Handles aren't `Send`; pass the raw value to the blocking task
+    let hwnd = window.hwnd().map_err(|e| e.to_string())?.0 as usize;
+    tauri::async_runtime::spawn_blocking(move || protection::verify(HWND(hwnd as *mut _)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn configure_window<R: Runtime>(window: &tauri::Window<R>) -> Result<bool, String> {
     // Enable capture protection
-    enable_capture_protection(window)?;
-    Ok(())
+    enable_capture_protection(window)
+}
+
+
+/// Byte length of a `width`×`height` frame at 4 bytes per pixel, or an error
+/// if it can't be addressed (e.g. several 4K displays on a 32-bit index).
+fn frame_len(width: u32, height: u32) -> Result<usize, String> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or_else(|| format!("A {}x{} frame is too large to address", width, height))
+}
+
+/// Byte offset of pixel (`x`, `y`) in a 4-byte-per-pixel image `width` pixels wide.
+#[inline]
+fn pixel_offset(x: u32, y: u32, width: u32) -> usize {
+    (y as usize * width as usize + x as usize) * 4
 }
 
+/// Maps output coordinate `i` of `out_len` onto a source axis of `len`, without
+/// overflowing the intermediate product.
+#[inline]
+fn scale_coord(i: u32, len: u32, out_len: u32) -> u32 {
+    (i as u64 * len as u64 / out_len as u64) as u32
+}
 
 fn process_image(
     original: &[u8],
     orig_width: u32,
-    crop_x: u32,
-    crop_y: u32,
-    crop_width: u32,
-    crop_height: u32,
-    scale_factor: u32
+    crop: Rect,
+    new_width: u32,
+    new_height: u32,
+    options: &ProcessOptions
 ) -> Vec<u8> {
-    let new_width = crop_width / scale_factor;
-    let new_height = crop_height / scale_factor;
-
-    let mut downsampled = Vec::with_capacity((new_width * new_height * 4) as usize);
-
-    let x_scale = (crop_width as f32 / new_width as f32).ceil() as u32;
-    let y_scale = (crop_height as f32 / new_height as f32).ceil() as u32;
-
-    for y in 0..new_height {
-        for x in 0..new_width {
-            // Calculate the source pixel in the original image based on the crop and scale factor
-            let src_x = crop_x + x * x_scale;
-            let src_y = crop_y + y * y_scale;
-
-            if src_x < crop_x + crop_width && src_y < crop_y + crop_height {
-                let src_idx = ((src_y * orig_width + src_x) * 4) as usize;
-                if src_idx + 3 < original.len() {
-                    // Fix colour channels
-                    downsampled.push(original[src_idx + 2]); // R => B
-                    downsampled.push(original[src_idx + 1]); // G => G
-                    downsampled.push(original[src_idx]);     // B => R
-                    downsampled.push(original[src_idx + 3]); // A => A
-                }
+    let upscaling = new_width > crop.width || new_height > crop.height;
+    let ratio = (crop.width as f32 / new_width as f32).max(crop.height as f32 / new_height as f32);
+    let downscale = options.downscale_filter.resolve(ratio);
+    if (upscaling && options.upscale_filter != Interpolation::Nearest) || downscale != Downscale::Nearest {
+        let mut output: Vec<u8> = resample::resample(original, orig_width, crop, new_width, new_height, options.upscale_filter, downscale)
+            .chunks_exact(4)
+            .flat_map(|pixel| options.apply(options.source_order.to_rgba(pixel)))
+            .collect();
+        let orig_height = (original.len() / (orig_width.max(1) as usize * 4)) as u32;
+        let bounds = Rect { x: 0, y: 0, width: orig_width, height: orig_height };
+        fill_outside(&mut output, new_width, new_height, crop, bounds, options.fill);
+        return output;
+    }
+
+    let Rect { x: crop_x, y: crop_y, width: crop_width, height: crop_height } = crop;
+
+    let mut downsampled = vec![0; frame_len(new_width, new_height).unwrap_or(0)];
+    let row_len = new_width as usize * 4;
+    if row_len == 0 {
+        return downsampled;
+    }
+
+    let fill_row = |y: u32, row: &mut [u8]| {
+        // Calculate the source row in the original image based on the crop and output size
+        let src_y = crop_y + scale_coord(y, crop_height, new_height);
+        for (x, pixel) in (0..new_width).zip(row.chunks_exact_mut(4)) {
+            let src_x = crop_x + scale_coord(x, crop_width, new_width);
+
+            let src_idx = pixel_offset(src_x, src_y, orig_width);
+            if src_x < orig_width && src_idx + 3 < original.len() {
+                // Fix colour channels
+                let rgba = options.source_order.to_rgba(&original[src_idx..src_idx + 4]);
+                pixel.copy_from_slice(&options.apply(rgba));
+            } else {
+                // Off the edge of the desktop
+                pixel.copy_from_slice(&options.fill);
             }
         }
+    };
+
+    if options.parallel && new_height > 1 {
+        // One tile of whole rows per thread, each writing its own slice of the output
+        let tile_rows = new_height.div_ceil(rayon::current_num_threads() as u32) as usize;
+        downsampled.par_chunks_mut(tile_rows * row_len).enumerate().for_each(|(tile, rows)| {
+            for (i, row) in rows.chunks_exact_mut(row_len).enumerate() {
+                fill_row((tile * tile_rows + i) as u32, row);
+            }
+        });
+    } else {
+        for (y, row) in downsampled.chunks_exact_mut(row_len).enumerate() {
+            fill_row(y as u32, row);
+        }
     }
 
     downsampled
 }
 
+/// Largest size with the aspect ratio of `width`×`height` that fits in `bounds`.
+fn fit_size(width: u32, height: u32, bounds: (u32, u32)) -> (u32, u32) {
+    if width == 0 || height == 0 {
+        return bounds;
+    }
+    let scale = (bounds.0 as f64 / width as f64).min(bounds.1 as f64 / height as f64);
+    let fitted = |len: u32, max: u32| ((len as f64 * scale).round() as u32).clamp(1, max);
+    (fitted(width, bounds.0), fitted(height, bounds.1))
+}
 
-async fn start_capture(window: tauri::Window, frame_buffer: Arc<RwLock<FrameBuffer>>) -> Result<(), String> {
-    set_process_dpi_awareness();
-    co_init();
+/// Places a `width`×`height` frame centred on a `canvas_width`×`canvas_height`
+/// one filled with `fill`, returning it and where the frame went.
+fn center_in(
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    canvas_width: u32,
+    canvas_height: u32,
+    fill: [u8; 4]
+) -> (Vec<u8>, Rect) {
+    let placed = Rect {
+        x: canvas_width.saturating_sub(width) / 2,
+        y: canvas_height.saturating_sub(height) / 2,
+        width: width.min(canvas_width),
+        height: height.min(canvas_height),
+    };
+    if (width, height) == (canvas_width, canvas_height) {
+        return (data, placed);
+    }
+    let mut canvas = fill.repeat(canvas_width as usize * canvas_height as usize);
+    let row_len = placed.width as usize * 4;
+    for (row, line) in data.chunks_exact(width as usize * 4).take(placed.height as usize).enumerate() {
+        let start = pixel_offset(placed.x, placed.y + row as u32, canvas_width);
+        canvas[start..start + row_len].copy_from_slice(&line[..row_len]);
+    }
+    (canvas, placed)
+}
+
+/// Fills output pixels whose source lies inside any of `regions`, e.g. to
+/// keep the app's own window out of its capture.
+fn mask_regions(data: &mut [u8], out_width: u32, out_height: u32, crop: Rect, regions: &[Rect], fill: [u8; 4]) {
+    for &region in regions {
+        fill_sources(data, out_width, out_height, crop, region, false, fill);
+    }
+}
 
-    let mut adapters = AdapterFactory::new();
-    let adapter = adapters.find(|adapter| {
-        adapter.get_display_by_idx(0).is_some()
+/// Makes output pixels whose source lies outside `keep` fully transparent,
+/// leaving only that window's content; an empty `keep` clears everything.
+fn mask_to_window(data: &mut [u8], out_width: u32, out_height: u32, crop: Rect, keep: Option<Rect>) {
+    if let Some(keep) = keep {
+        fill_outside(data, out_width, out_height, crop, keep, [0, 0, 0, 0]);
+    }
+}
+
+/// Fills output pixels whose source lies outside `bounds`, e.g. where the crop
+/// runs off the edge of the desktop texture.
+fn fill_outside(data: &mut [u8], out_width: u32, out_height: u32, crop: Rect, bounds: Rect, fill: [u8; 4]) {
+    fill_sources(data, out_width, out_height, crop, bounds, true, fill);
+}
+
+/// The part of `rect` inside a `width`×`height` image, or `None` if it's all outside.
+fn clip_rect(rect: Rect, width: u32, height: u32) -> Option<Rect> {
+    let (right, bottom) = ((rect.x + rect.width).min(width), (rect.y + rect.height).min(height));
+    (rect.x < right && rect.y < bottom).then(|| Rect {
+        x: rect.x,
+        y: rect.y,
+        width: right - rect.x,
+        height: bottom - rect.y,
     })
-        .ok_or("No suitable display adapters found")?;
+}
 
-    let output = adapter.get_display_by_idx(0)
-        .ok_or("No displays found for the selected adapter")?;
+/// A `size` rectangle at desktop position `pos` relative to a display at
+/// `origin`, dropping any part left of or above the display.
+fn on_display(pos: (i32, i32), size: (u32, u32), origin: (i32, i32)) -> Rect {
+    let clip = |start: i64, len: u32| {
+        let end = (start + len as i64).clamp(0, u32::MAX as i64) as u32;
+        let start = start.clamp(0, u32::MAX as i64) as u32;
+        (start, end - start)
+    };
+    let (x, width) = clip(pos.0 as i64 - origin.0 as i64, size.0);
+    let (y, height) = clip(pos.1 as i64 - origin.1 as i64, size.1);
+    Rect { x, y, width, height }
+}
 
-    // Configure for fastest frame acquisition
-    let mut dupl = DesktopDuplicationApi::new(adapter, output.clone())
-        .map_err(|e| format!("Failed to initialize desktop duplication: {:?}", e))?;
+/// `rect` relative to an image starting at (`x`, `y`), dropping any part before it.
+fn offset_rect(rect: Rect, x: u32, y: u32) -> Rect {
+    Rect {
+        x: rect.x.saturating_sub(x),
+        y: rect.y.saturating_sub(y),
+        width: (rect.x + rect.width).saturating_sub(x.max(rect.x)),
+        height: (rect.y + rect.height).saturating_sub(y.max(rect.y)),
+    }
+}
 
-    let mut options = DuplicationApiOptions::default();
-    options.skip_cursor = true;
-    dupl.configure(options);
+fn fill_sources(
+    data: &mut [u8],
+    out_width: u32,
+    out_height: u32,
+    crop: Rect,
+    region: Rect,
+    outside: bool,
+    fill: [u8; 4]
+) {
+    if out_width == 0 || out_height == 0 {
+        return;
+    }
+    let inside = |src: u32, start: u32, len: u32| src >= start && src - start < len;
 
-    let (device, ctx) = dupl.get_device_and_ctx();
-    let mut texture_reader = TextureReader::new(device, ctx);
+    let columns: Vec<bool> = (0..out_width)
+        .map(|x| inside(crop.x + scale_coord(x, crop.width, out_width), region.x, region.width))
+        .collect();
 
-    let mut frame_counter = 0u32;
-    let mut fps_counter = 0u32;
-    let mut last_second = std::time::Instant::now();
+    for (y, row) in data.chunks_exact_mut(out_width as usize * 4).enumerate() {
+        let row_inside = inside(crop.y + scale_coord(y as u32, crop.height, out_height), region.y, region.height);
+        if !row_inside && !outside {
+            continue;
+        }
+        for (pixel, &column_inside) in row.chunks_exact_mut(4).zip(&columns) {
+            if (row_inside && column_inside) != outside {
+                pixel.copy_from_slice(&fill);
+            }
+        }
+    }
+}
+
+/// Fades alpha to zero over the outermost `fade` pixels of each edge.
+///
+/// Premultiplied output has its colour faded along with alpha so it stays
+/// premultiplied; otherwise only alpha changes.
+fn apply_edge_fade(data: &mut [u8], width: u32, height: u32, fade: u32, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    // Opacity out of 256 by distance to the nearest edge along one axis
+    let ramp = |i: u32, len: u32| {
+        let distance = i.min(len - 1 - i);
+        if distance >= fade { 256 } else { ((distance * 2 + 1) * 128 / fade) as u16 }
+    };
+    let columns: Vec<u16> = (0..width).map(|x| ramp(x, width)).collect();
+
+    for (y, row) in data.chunks_exact_mut(width as usize * 4).enumerate() {
+        let row_ramp = ramp(y as u32, height);
+        for (pixel, &column_ramp) in row.chunks_exact_mut(4).zip(&columns) {
+            let factor = row_ramp.min(column_ramp);
+            if factor == 256 {
+                continue;
+            }
+            let channels = if premultiplied { &mut pixel[..] } else { &mut pixel[3..] };
+            for channel in channels {
+                *channel = ((*channel as u16 * factor) >> 8) as u8;
+            }
+        }
+    }
+}
+
+#[inline]
+fn apply_alpha_mode([r, g, b, a]: [u8; 4], alpha_mode: AlphaMode) -> [u8; 4] {
+    match alpha_mode {
+        AlphaMode::Straight => [r, g, b, a],
+        AlphaMode::Premultiplied => [premultiply(r, a), premultiply(g, a), premultiply(b, a), a],
+        AlphaMode::ForceOpaque => [r, g, b, 255],
+    }
+}
+
+/// Finds the `index`-th display, counting across all adapters.
+fn find_display(index: u32) -> Result<(Adapter, Display), String> {
+    displays::all()
+        .into_iter()
+        .nth(index as usize)
+        .ok_or_else(|| format!("No display with index {}", index))
+}
 
+/// Waits for a freshly created duplication to produce its first frame.
+fn acquire_first_frame(dupl: &mut DesktopDuplicationApi) -> Result<Texture, String> {
+    let deadline = Instant::now() + FIRST_FRAME_TIMEOUT;
     loop {
-        // Use acquire_next_frame_now for immediate frame capture
         match dupl.acquire_next_frame_now() {
-            Ok(tex) => {
-                let desc = tex.desc();
+            Ok(tex) => return Ok(tex),
+            Err(e) if Instant::now() >= deadline => {
+                return Err(format!("Failed to acquire frame: {:?}", e));
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(5)),
+        }
+    }
+}
 
-                let scale_factor = 4;
+/// Total number of frames composed by DWM, used as the desktop present count.
+///
+/// `DesktopDuplicationApi` doesn't surface `AccumulatedFrames`, but DWM's
+/// composition counter advances on the same desktop updates.
+fn desktop_present_count() -> Option<u64> {
+    latency::composition_timing().map(|info| info.cFrame)
+}
 
-                // Prepare a new buffer for the frame
-                let mut frame_data = Vec::with_capacity((desc.width * desc.height * 4) as usize);
+/// Waits until DWM has composed past `last`, polling its counter, and
+/// returns the new count. Gives up after `FRAME_ARRIVAL_TIMEOUT` so a still
+/// desktop doesn't stall the loop.
+async fn wait_for_composition(last: Option<u64>) -> Option<u64> {
+    let deadline = Instant::now() + FRAME_ARRIVAL_TIMEOUT;
+    loop {
+        let count = desktop_present_count();
+        if count.is_none() || count != last || Instant::now() >= deadline {
+            return count;
+        }
+        tokio::time::sleep(FRAME_ARRIVAL_POLL_INTERVAL).await;
+    }
+}
 
-                // Read texture data using texture_reader
-                match texture_reader.get_data(&mut frame_data, &tex) {
-                    Ok(_) => {
-                        // Get window position and size directly
-                        let window_pos = window.outer_position().map_err(|e| e.to_string())?;
-                        let window_size = window.outer_size().map_err(|e| e.to_string())?;
+fn set_status(window: &tauri::Window, status: &RwLock<CaptureStatus>, value: CaptureStatus) {
+    *status.write() = value;
+    if let Err(e) = window.emit("capture-status", value) {
+        warn!("Failed to emit capture-status event: {:?}", e);
+    }
+}
 
-                        // Crop the image to the window's area
-                        let processed_data = process_image(
-                            &frame_data,
-                            desc.width,
-                            window_pos.x as u32,
-                            window_pos.y as u32,
-                            window_size.width,
-                            window_size.height,
-                            scale_factor
-                        );
+/// Emits `capture-error` unless one of the same kind went out within the
+/// limiter's interval; `force` reports it regardless.
+fn report_error(window: &tauri::Window, errors: &Mutex<ErrorLimiter>, kind: ErrorKind, message: String, force: bool) {
+    let event = {
+        let mut errors = errors.lock();
+        if force { Some(errors.force(kind, message)) } else { errors.record(kind, message) }
+    };
+    if let Some(event) = event {
+        if let Err(e) = window.emit("capture-error", event) {
+            warn!("Failed to emit capture-error event: {:?}", e);
+        }
+    }
+}
 
-                        // Notify frontend about new frame
-                        frame_counter = frame_counter.wrapping_add(1);
-                        fps_counter += 1;
+/// Initialises the source again after the GPU was removed or reset.
+///
+/// Retries until it succeeds or shutdown is requested, returning `None` in the latter case.
+async fn recover_device<S: CaptureSource>(
+    window: &tauri::Window,
+    status: &RwLock<CaptureStatus>,
+    shutdown: &AtomicBool,
+    display_name: Option<&str>,
+    options: &SourceOptions,
+    cause: String
+) -> Option<S> {
+    warn!("GPU device lost, rebuilding: {}", cause);
+    set_status(window, status, CaptureStatus::Recovering);
+    if let Err(e) = window.emit("gpu-reset", cause) {
+        warn!("Failed to emit gpu-reset event: {:?}", e);
+    }
 
-                        // Update the shared state
-                        {
-                            let current_time = std::time::Instant::now();
-                            let mut buffer = frame_buffer.write();
-                            buffer.data = processed_data;
-                            buffer.width = window_size.width as u32 / scale_factor;
-                            buffer.height = window_size.height as u32 / scale_factor;
-                            if current_time.duration_since(last_second).as_secs() >= 1 {
-                                // println!("FPS: {}", fps_counter);
-                                buffer.fps = fps_counter.clone();
-                                fps_counter = 0;
-                                last_second = current_time;
-                            }
-                        }
+    while !shutdown.load(Ordering::Acquire) {
+        tokio::time::sleep(DEVICE_RECOVERY_INTERVAL).await;
+        match S::init(display_name, options) {
+            Ok(opened) => {
+                info!("GPU device rebuilt, capturing {}", opened.display().name);
+                set_status(window, status, CaptureStatus::Running);
+                return Some(opened);
+            }
+            Err(e) => debug!("GPU device not ready yet: {}", e),
+        }
+    }
+    None
+}
 
-                        if let Err(e) = window.emit("frame-ready", frame_counter) {
-                            eprintln!("Failed to emit frame-ready event: {:?}", e);
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("Failed to get texture data: {:?}", e);
-                        continue;
-                    }
-                }
-            },
-            Err(e) => {
-                eprintln!("Failed to acquire frame: {:?}", e);
-                // Handle potential recovery scenarios
-                if matches!(e, DDApiError::AccessLost | DDApiError::AccessDenied) {
-                    // Potentially reinitialize duplication API
-                    break;
-                }
+/// Polls a newly opened source until it delivers a frame, which a fresh
+/// duplication only does once the desktop next presents, so the loop starts
+/// on frames that are flowing. Returns whether one arrived in time.
+async fn warm_up_source<S: CaptureSource>(backend: &mut S, shutdown: &AtomicBool) -> bool {
+    let deadline = Instant::now() + WARM_UP_TIMEOUT;
+    while !shutdown.load(Ordering::Acquire) && Instant::now() < deadline {
+        match backend.acquire_frame(SyncMode::Immediate).await {
+            // Later acquires return this frame again until the desktop changes
+            Ok(_) => return true,
+            Err(e) if matches!(backend.failure(&e), Failure::Transient) => {
+                tokio::time::sleep(WARM_UP_POLL_INTERVAL).await;
             }
+            // Left for the loop to recover from
+            Err(_) => return false,
         }
     }
+    false
+}
 
-    Ok(())
+#[inline]
+fn premultiply(channel: u8, alpha: u8) -> u8 {
+    ((channel as u16 * alpha as u16 + 127) / 255) as u8
 }
 
-#[tauri::command]
-fn get_frame_data(state: State<'_, CaptureState>) -> Result<(Vec<u8>, u32, u32, u32), String> {
-    let frame_buffer = state.frame_buffer.read();
 
-    if !frame_buffer.data.is_empty() && frame_buffer.width > 0 && frame_buffer.height > 0 {
-        Ok((
-            frame_buffer.data.clone(),
-            frame_buffer.width,
-            frame_buffer.height,
-            frame_buffer.fps
-        ))
-    } else {
-        Err("No valid frame data available.".to_string())
+/// Counts a capture thread out of `CaptureState::capture_threads` when it
+/// exits, even by panicking.
+struct CaptureThread(Arc<watch::Sender<usize>>);
+
+impl Drop for CaptureThread {
+    fn drop(&mut self) {
+        self.0.send_modify(|threads| *threads -= 1);
     }
 }
 
+/// Runs capture on a dedicated thread, so its priority and affinity can be
+/// tuned and end with it rather than carrying over to a pooled worker.
+fn spawn_capture(window: tauri::Window, state: CaptureState) {
+    // Counted before spawning, so a shutdown right away still waits for it
+    state.capture_threads.send_modify(|threads| *threads += 1);
+    let exited = CaptureThread(state.capture_threads.clone());
+    let spawned = std::thread::Builder::new().name("capture".to_string()).spawn(move || {
+        let _exited = exited;
+        if let Err(e) = tauri::async_runtime::block_on(run_capture(window, state)) {
+            error!("Capture error: {}", e);
+        }
+    });
+    // A thread that failed to start took its count with it
+    if let Err(e) = spawned {
+        error!("Failed to start the capture thread: {}", e);
+    }
+}
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .manage(CaptureState::new())
-        .setup(|app| {
-            let capture_state = app.state::<CaptureState>();
+/// Runs capture from whichever source the settings select, handing over
+/// between the duplication and generated frames as they change.
+async fn run_capture(window: tauri::Window, state: CaptureState) -> Result<(), String> {
+    loop {
+        let mock = MockSource::serves(&state.settings.read().source);
+        if mock {
+            start_capture::<MockSource>(window.clone(), state.clone()).await?;
+        } else {
+            start_capture::<DuplicationSource>(window.clone(), state.clone()).await?;
+        }
+        // Stopped for good unless it was to hand over to the other source
+        if state.shutdown.load(Ordering::Acquire) || MockSource::serves(&state.settings.read().source) == mock {
+            return Ok(());
+        }
+    }
+}
 
-            if let Some(window) = app.get_window("main") {
-                if let Err(e) = configure_window(&window) {
-                    eprintln!("Failed to configure window: {}", e);
-                }
+async fn start_capture<S: CaptureSource>(window: tauri::Window, state: CaptureState) -> Result<(), String> {
+    let frame_buffer = state.frame_buffer;
+    let settings = state.settings;
+    let shutdown = state.shutdown;
+    let self_excluded = state.self_excluded;
+    let keyframe_requested = state.keyframe_requested;
+    let flush_requested = state.flush_requested;
+    let replay = state.replay;
+    let status = state.status;
+    let thumbnail = state.thumbnail;
+    let hires_request = state.hires_request;
+    let raw_request = state.raw_request;
+    let grab_request = state.grab_request;
+    let trigger = state.trigger;
+    let burst_request = state.burst_request;
+    let latency_probe = state.latency_probe;
+    let info = state.info;
+    let dynamic_region = state.dynamic_region;
+    let errors = state.errors;
+    let frame_channel = state.frame_channel;
+    let streams = state.streams;
+    let consumers = state.consumers;
+    let video = state.video;
+    let watermark = state.watermark;
+    let cursor = state.cursor;
+    let frame_published = state.frame_published;
+    let stream_quality = state.stream_quality;
+    let previous_frame = state.previous_frame;
 
-                let state_clone = capture_state.frame_buffer.clone();
+    dpi::ensure_aware();
+    co_init();
 
-                tauri::async_runtime::spawn(async move {
-                    if let Err(e) = start_capture(window, state_clone).await {
-                        eprintln!("Capture error: {}", e);
-                    }
-                });
+    let mut frame_counter = 0u32;
+    let mut selected_display = settings.read().display.clone();
+    let mut backend = S::init(selected_display.as_deref(), &settings.read().source_options())?;
+    backend.display().publish(&info);
+    info!("Capturing {} ({:?} Hz)", backend.display().name, backend.display().refresh_rate);
+    if !warm_up_source(&mut backend, &shutdown).await {
+        warn!("No frame from {} within {:?} of opening it", backend.display().name, WARM_UP_TIMEOUT);
+    }
+
+    let mut fps_counter = 0u32;
+    let mut presents_counter = 0u32;
+    let mut readback_time = Duration::ZERO;
+    let mut last_present_count = desktop_present_count();
+    let mut last_second = std::time::Instant::now();
+    let mut last_emit: Option<Instant> = None;
+    let mut delta_encoder: Option<DeltaEncoder> = None;
+    let mut ready = false;
+    let mut window_minimized = false;
+    let mut geometry_failures = 0u32;
+    let mut last_tick = Instant::now();
+    let mut last_arrival = desktop_present_count();
+    let mut thread_tuning: Option<ThreadTuning> = None;
+    // Reset whenever the backend is replaced, as a new device starts at normal
+    let mut gpu_priority: Option<GpuPriority> = None;
+    // Set once the device turns out not to convert formats, until it's replaced
+    let mut gpu_convert_failed = false;
+    let mut letterbox = Letterbox::default();
+    let mut smoothing = TemporalFilter::default();
+    let mut visualizer = Visualizer::default();
+    let mut offscreen = false;
+    // Last second's fps and drops, for the debug overlay
+    let mut overlay_stats = OverlayStats::default();
+    let mut last_window_pos = None;
+    // Raw handle, as `HWND` can't be held across the loop's awaits
+    let hwnd = window.hwnd().ok().map(|hwnd| hwnd.0 as usize);
+    // Output divisor applied by `MemoryPolicy::ReduceQuality`
+    let mut memory_divisor = 1u32;
+    let mut adaptive_scale = AdaptiveScale::default();
+
+    while !shutdown.load(Ordering::Acquire) {
+        let wanted_tuning = settings.read().capture_thread.clone();
+        if thread_tuning.as_ref() != Some(&wanted_tuning) {
+            if let Err(e) = thread::apply(&wanted_tuning) {
+                warn!("Failed to tune capture thread: {}", e);
+            }
+            info.write().thread_priority = thread::current_priority();
+            thread_tuning = Some(wanted_tuning);
+        }
+
+        let wanted_gpu_priority = settings.read().gpu_priority;
+        if gpu_priority != Some(wanted_gpu_priority) {
+            let applied = backend.set_gpu_priority(wanted_gpu_priority);
+            match &applied {
+                Ok(applied) if *applied != wanted_gpu_priority => {
+                    warn!("GPU priority {:?} refused, running at {:?}", wanted_gpu_priority, applied)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to set GPU priority: {}", e),
+            }
+            info.write().gpu_priority = applied.ok();
+            gpu_priority = Some(wanted_gpu_priority);
+        }
+
+        if flush_requested.swap(false, Ordering::AcqRel) {
+            backend.flush();
+            // Consumers see the sequence go back to 1 and know the stream restarted
+            frame_counter = 0;
+            // Tiles stamped with the old sequences would read as changed until it caught up
+            frame_buffer.write().dirty = DirtyTiles::default();
+            keyframe_requested.store(true, Ordering::Release);
+            debug!("Flushed buffered frames");
+        }
+
+        if !S::serves(&settings.read().source) {
+            info!("Handing capture over to another source");
+            return Ok(());
+        }
+
+        let wanted_display = settings.read().display.clone();
+        if wanted_display != selected_display {
+            let options = settings.read().source_options();
+            match S::init(wanted_display.as_deref(), &options) {
+                Ok(opened) => {
+                    backend = opened;
+                    backend.display().publish(&info);
+                    info!("Switched capture to {}", backend.display().name);
+                    gpu_priority = None;
+                    gpu_convert_failed = false;
+                    warm_up_source(&mut backend, &shutdown).await;
+                    delta_encoder = None;
+                }
+                Err(e) => error!("Failed to switch display: {}", e),
+            }
+            // Don't retry a failed switch every frame
+            selected_display = wanted_display;
+        }
+
+        let (sync_mode, pacing) = {
+            let settings = settings.read();
+            (settings.sync_mode, settings.pacing)
+        };
+
+        // Bursts run as fast as frames arrive
+        let bursting = burst_request.lock().is_some();
+        if pacing == Pacing::Adaptive && backend.display().variable_refresh && !bursting {
+            last_arrival = wait_for_composition(last_arrival).await;
+        } else if let Some(period) = pacing.period(backend.display().refresh_rate).filter(|_| !bursting) {
+            // Tick on a fixed grid so the cadence doesn't drift, unless we've fallen a whole period behind
+            let target = last_tick + period;
+            let now = Instant::now();
+            if target > now {
+                tokio::time::sleep(target - now).await;
+            }
+            last_tick = if now.saturating_duration_since(target) > period { now } else { target };
+        }
+
+        let acquire_started = Instant::now();
+        match backend.acquire_frame(sync_mode).await {
+            Ok(tex) => {
+                let acquired_at = Instant::now();
+                let desc = backend.describe(&tex);
+                if info.read().channel_order != desc.order {
+                    info.write().channel_order = desc.order;
+                }
+                // The composition this frame came out of, while a latency measurement wants it
+                let composed_at = latency_probe
+                    .lock()
+                    .is_some()
+                    .then(latency::composition_timing)
+                    .flatten()
+                    .map(|timing| timing.qpcCompose);
+
+                let mut current_settings = settings.read().clone();
+                adaptive_scale.configure(current_settings.adaptive_quality, current_settings.scale_factor);
+                let scale_factor = adaptive_scale.scale_factor().unwrap_or(current_settings.scale_factor);
+
+                // Desktop coordinates only line up with an upright frame
+                let (display_width, display_height) = if backend.display().rotation.is_quarter_turn() {
+                    (desc.height, desc.width)
+                } else {
+                    (desc.width, desc.height)
+                };
+
+                // Get window position and size directly; failures are usually transient
+                let (window_pos, window_size) = match window.outer_position().and_then(|pos| {
+                    window.outer_size().map(|size| (pos, size))
+                }) {
+                    Ok(geometry) => {
+                        geometry_failures = 0;
+                        geometry
+                    }
+                    Err(e) => {
+                        geometry_failures += 1;
+                        warn!(
+                            "Failed to query window geometry ({}/{}): {}",
+                            geometry_failures, MAX_GEOMETRY_FAILURES, e
+                        );
+                        let message = format!("Window geometry unavailable: {}", e);
+                        let fatal = geometry_failures >= MAX_GEOMETRY_FAILURES;
+                        report_error(&window, &errors, ErrorKind::Geometry, message.clone(), fatal);
+                        if !fatal {
+                            continue;
+                        }
+                        return Err(message);
+                    }
+                };
+                // Our own window, masked out when the OS doesn't exclude it from capture
+                let window_rect = on_display(
+                    (window_pos.x, window_pos.y),
+                    (window_size.width, window_size.height),
+                    backend.display().origin,
+                );
+                // A targeted process's window stands in for ours from here on
+                let (window_pos, window_size) = match current_settings.source {
+                    FrameSource::ProcessWindow { pid } => match process::window_geometry(pid) {
+                        Some(geometry) => geometry,
+                        None => {
+                            let message = format!("No visible window for process {}", pid);
+                            report_error(&window, &errors, ErrorKind::Geometry, message, false);
+                            continue;
+                        }
+                    },
+                    // Generated frames stand in for the window's contents
+                    FrameSource::Mock(_) => {
+                        let origin = backend.display().origin;
+                        (
+                            tauri::PhysicalPosition::new(origin.0, origin.1),
+                            tauri::PhysicalSize::new(display_width, display_height),
+                        )
+                    }
+                    FrameSource::CursorMagnifier { radius, zoom } => {
+                        let Some(pointer) = cursor::pointer_position() else {
+                            continue;
+                        };
+                        let origin = backend.display().origin;
+                        let bounds = RECT {
+                            left: origin.0,
+                            top: origin.1,
+                            right: origin.0 + display_width as i32,
+                            bottom: origin.1 + display_height as i32,
+                        };
+                        let [left, top, right, bottom] = magnifier_area(pointer, radius, bounds);
+                        let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+                        current_settings.output_size = Some(zoomed_size(width, height, zoom));
+                        current_settings.upscale_filter = Interpolation::Bilinear;
+                        (tauri::PhysicalPosition::new(left, top), tauri::PhysicalSize::new(width, height))
+                    }
+                    _ => (window_pos, window_size),
+                };
+
+                // A window wholly off this display would only give blank, fully clamped frames.
+                // Windows render themselves and streamed regions are display-relative already.
+                let origin = backend.display().origin;
+                let now_offscreen = current_settings.source != FrameSource::PrintWindow
+                    && dynamic_region.load().is_none()
+                    && (window_pos.x >= origin.0 + display_width as i32
+                        || window_pos.y >= origin.1 + display_height as i32
+                        || window_pos.x + window_size.width as i32 <= origin.0
+                        || window_pos.y + window_size.height as i32 <= origin.1);
+                if now_offscreen != offscreen {
+                    offscreen = now_offscreen;
+                    info!("Capture region {} the display", if offscreen { "left" } else { "returned to" });
+                    let status_now = if offscreen { CaptureStatus::Offscreen } else { CaptureStatus::Running };
+                    set_status(&window, &status, status_now);
+                    if let Err(e) = window.emit("region-offscreen", offscreen) {
+                        warn!("Failed to emit region-offscreen event: {:?}", e);
+                    }
+                }
+                if offscreen {
+                    continue;
+                }
+
+                // Window position relative to the captured display
+                let window_x = (window_pos.x - backend.display().origin.0).max(0) as u32;
+                let window_y = (window_pos.y - backend.display().origin.1).max(0) as u32;
+
+                // Crop to the configured region, or the whole window
+                let local = match current_settings.region {
+                    Some(region) => region.resolve(
+                        current_settings.region_anchor,
+                        window_size.width,
+                        window_size.height,
+                    ),
+                    None => Rect { x: 0, y: 0, width: window_size.width, height: window_size.height },
+                };
+
+                // Only the crop crosses the bus when nothing needs the rest of the display. A
+                // pipelined read would return a region chosen frames ago, and a turned display
+                // would need the region turned with it.
+                let read_region = if current_settings.crop_readback
+                    && current_settings.readback_buffers <= 1
+                    && backend.display().rotation == Rotation::Identity
+                    && current_settings.source != FrameSource::PrintWindow
+                    && raw_request.lock().is_none()
+                {
+                    let wanted = dynamic_region.load().unwrap_or(Rect {
+                        x: window_x + local.x,
+                        y: window_y + local.y,
+                        width: local.width,
+                        height: local.height,
+                    });
+                    clip_rect(wanted, display_width, display_height)
+                } else {
+                    None
+                };
+                let (read_x, read_y) = read_region.map_or((0, 0), |region| (region.x, region.y));
+                let (read_width, read_height) =
+                    read_region.map_or((desc.width, desc.height), |region| (region.width, region.height));
+
+                // Prepare a new buffer for the frame
+                let frame_len = match frame_len(read_width, read_height) {
+                    Ok(len) => len,
+                    Err(e) => {
+                        warn!("Skipping frame: {}", e);
+                        continue;
+                    }
+                };
+                let mut frame_data = Vec::with_capacity(frame_len);
+
+                backend.configure(&current_settings.source_options());
+                // The GPU swaps channels during the copy where it can; alpha doesn't survive it
+                let mut gpu_convert = current_settings.gpu_format_convert
+                    && !gpu_convert_failed
+                    && current_settings.alpha_mode == AlphaMode::ForceOpaque
+                    && current_settings.source != FrameSource::PrintWindow
+                    && desc.order == Some(ChannelOrder::Bgra)
+                    && raw_request.lock().is_none();
+                let readback_start = Instant::now();
+                let read = match gpu_convert.then(|| backend.read_rgba_into(&tex, read_region, &mut frame_data)) {
+                    Some(Err(e)) => {
+                        warn!("GPU format conversion unavailable, converting on the CPU: {}", e);
+                        gpu_convert_failed = true;
+                        gpu_convert = false;
+                        backend.read_into(&tex, read_region, &mut frame_data)
+                    }
+                    Some(read) => read,
+                    None => backend.read_into(&tex, read_region, &mut frame_data),
+                };
+                readback_time += readback_start.elapsed();
+                if info.read().gpu_format_convert != gpu_convert {
+                    info.write().gpu_format_convert = gpu_convert;
+                }
+
+                match read {
+                    // Pipeline still filling
+                    Ok(false) => continue,
+                    Ok(true) => {
+                        if let Some(sender) = raw_request.lock().take_if(|_| read_region.is_none()) {
+                            let _ = sender.send(RawFrame {
+                                data: frame_data.clone(),
+                                width: desc.width,
+                                height: desc.height,
+                                format: desc.format,
+                                stride: desc.width * 4,
+                            });
+                        }
+
+                        let (frame_data, frame_width, _) =
+                            backend.display().rotation.upright(frame_data, read_width, read_height);
+                        // From here on, coordinates are relative to the part of the display read
+                        let window_rect = offset_rect(window_rect, read_x, read_y);
+
+
+                        let printed = match (current_settings.source, hwnd) {
+                            (FrameSource::PrintWindow, Some(hwnd)) => {
+                                let printed = gdi::print_window(HWND(hwnd as *mut _), window_size.width, window_size.height);
+                                if printed.is_none() {
+                                    debug!("PrintWindow failed, using the duplicated desktop");
+                                }
+                                printed
+                            }
+                            _ => None,
+                        };
+                        // Crops below are relative to whichever image is the source
+                        let from_window = printed.is_some();
+                        // PrintWindow renders BGRA whatever the duplication's format
+                        if gpu_convert {
+                            current_settings.source_order = ChannelOrder::Rgba;
+                        } else if current_settings.autodetect_channels && !from_window {
+                            if let Some(order) = desc.order {
+                                current_settings.source_order = order;
+                            }
+                        }
+                        let (frame_data, frame_width, source_origin, (source_x, source_y)) = match printed {
+                            Some(data) => (data, window_size.width, (window_pos.x, window_pos.y), (0, 0)),
+                            None => {
+                                let source_origin = (origin.0 + read_x as i32, origin.1 + read_y as i32);
+                                (frame_data, frame_width, source_origin, (window_x, window_y))
+                            }
+                        };
+
+                        // A region streamed from the frontend overrides the window entirely
+                        let dynamic_crop = dynamic_region
+                            .load()
+                            .filter(|_| !from_window)
+                            .map(|region| offset_rect(region, read_x, read_y));
+                        let crop = dynamic_crop.unwrap_or(Rect {
+                            x: (source_x + local.x).saturating_sub(read_x),
+                            y: (source_y + local.y).saturating_sub(read_y),
+                            width: local.width,
+                            height: local.height,
+                        });
+                        let crop = match current_settings.autocrop_bars {
+                            Some(threshold) => letterbox.update(&frame_data, frame_width, crop, threshold),
+                            None => crop,
+                        };
+
+                        let (out_width, out_height) = match current_settings.locked_size {
+                            Some(locked) => fit_size(crop.width, crop.height, locked),
+                            None => current_settings
+                                .output_size
+                                .unwrap_or((crop.width / scale_factor, crop.height / scale_factor)),
+                        };
+                        let (out_width, out_height) = match memory_divisor {
+                            divisor if divisor > 1 && current_settings.locked_size.is_none() => {
+                                ((out_width / divisor).max(1), (out_height / divisor).max(1))
+                            }
+                            _ => (out_width, out_height),
+                        };
+
+                        // Mid-drag the reported position can trail the composited window by a
+                        // frame; over-crop so the edge doesn't show a sliver of desktop. The
+                        // output keeps its size, zooming in slightly until the window settles.
+                        let moving = last_window_pos
+                            .replace((window_pos.x, window_pos.y))
+                            .is_some_and(|last| last != (window_pos.x, window_pos.y));
+                        let inset = current_settings.drag_compensation;
+                        let crop = if moving && inset > 0 && dynamic_crop.is_none() && !from_window {
+                            let inset = inset.min(crop.width.saturating_sub(1) / 2).min(crop.height.saturating_sub(1) / 2);
+                            Rect {
+                                x: crop.x + inset,
+                                y: crop.y + inset,
+                                width: crop.width - 2 * inset,
+                                height: crop.height - 2 * inset,
+                            }
+                        } else {
+                            crop
+                        };
+
+                        // Keep the last good frame rather than publishing empty ones while minimized
+                        let minimized = out_width == 0 || out_height == 0 || window.is_minimized().unwrap_or(false);
+                        if minimized != window_minimized {
+                            window_minimized = minimized;
+                            let event = if minimized { "window-minimized" } else { "window-restored" };
+                            if let Err(e) = window.emit(event, ()) {
+                                warn!("Failed to emit {} event: {:?}", event, e);
+                            }
+                        }
+                        if minimized {
+                            tokio::time::sleep(MINIMIZED_POLL_INTERVAL).await;
+                            continue;
+                        }
+
+                        // Generated frames show no desktop, so nothing on it needs hiding
+                        let generated = matches!(current_settings.source, FrameSource::Mock(_));
+                        // Without OS-level exclusion our own window would show up in the capture
+                        let self_masked = !from_window && !generated && !self_excluded.load(Ordering::Acquire);
+                        // Display-relative exclusions, moved to wherever the source image starts
+                        let mut exclusions = current_settings.exclusion_rects.clone();
+                        if current_settings.exclude_taskbar && !generated {
+                            exclusions.extend(displays::outside_work_area(origin, display_width, display_height));
+                        }
+                        let (image_x, image_y) = if from_window { (window_x, window_y) } else { (read_x, read_y) };
+                        let masked: Vec<Rect> = self_masked
+                            .then_some(window_rect)
+                            .into_iter()
+                            .chain(exclusions.into_iter().map(|rect| offset_rect(rect, image_x, image_y)))
+                            .collect();
+                        // The window to cut out, found afresh each frame as it moves
+                        let window_mask = current_settings.mask_to_window.filter(|_| !from_window && !generated).map(|hwnd| {
+                            let Some(bounds) = process::window_bounds(HWND(hwnd as *mut _)) else {
+                                return Rect::default();
+                            };
+                            let (left, top) = ((bounds.left - origin.0).max(0) as u32, (bounds.top - origin.1).max(0) as u32);
+                            let (right, bottom) = ((bounds.right - origin.0).max(0) as u32, (bounds.bottom - origin.1).max(0) as u32);
+                            let rect = Rect { x: left, y: top, width: right.saturating_sub(left), height: bottom.saturating_sub(top) };
+                            offset_rect(rect, read_x, read_y)
+                        });
+
+                        // A pending burst takes every frame, leaving the preview as it was
+                        let burst = burst_request.lock().take();
+                        if let Some(burst) = burst {
+                            let (burst_width, burst_height) = (crop.width / burst.divisor, crop.height / burst.divisor);
+                            let options = current_settings.process_options();
+                            let mut burst_data = process_image(
+                                &frame_data,
+                                frame_width,
+                                crop,
+                                burst_width,
+                                burst_height,
+                                &options
+                            );
+                            mask_regions(&mut burst_data, burst_width, burst_height, crop, &masked, options.fill);
+                            mask_to_window(&mut burst_data, burst_width, burst_height, crop, window_mask);
+                            frame_counter = frame_counter.wrapping_add(1);
+                            let mut pending = burst_request.lock();
+                            // Unless a newer burst has replaced this one
+                            if pending.is_none() {
+                                *pending = burst.push(burst_data, burst_width, burst_height, frame_counter);
+                            }
+                            continue;
+                        }
+                        let mut processed_data = process_image(
+                            &frame_data,
+                            frame_width,
+                            crop,
+                            out_width,
+                            out_height,
+                            &current_settings.process_options()
+                        );
+
+                        // Before any overlays, so the cursor doesn't leave a trail
+                        if current_settings.temporal_smoothing > 0.0 {
+                            smoothing.apply(&mut processed_data, out_width, out_height, current_settings.temporal_smoothing);
+                        } else {
+                            smoothing.reset();
+                        }
+
+                        if let Some(mode) = current_settings.visualization {
+                            let opacity = current_settings.visualization_opacity;
+                            visualizer.apply(&mut processed_data, out_width, out_height, mode, opacity);
+                        } else {
+                            visualizer.reset();
+                        }
+
+                        mask_regions(&mut processed_data, out_width, out_height, crop, &masked, current_settings.fill_color);
+
+                        if let (CursorMode::Shown { opacity }, false) = (current_settings.cursor, generated) {
+                            cursor.lock().composite(&mut processed_data, out_width, out_height, crop, source_origin, opacity);
+                        }
+                        mask_to_window(&mut processed_data, out_width, out_height, crop, window_mask);
+
+                        let premultiplied = current_settings.alpha_mode == AlphaMode::Premultiplied
+                            && current_settings.channel.is_none();
+                        // Before the fade, so a watermark near the edge fades with the frame
+                        if let Some(watermark) = watermark.lock().as_mut() {
+                            watermark.draw(&mut processed_data, out_width, out_height, premultiplied);
+                        }
+
+                        if current_settings.edge_fade > 0 {
+                            apply_edge_fade(
+                                &mut processed_data,
+                                out_width,
+                                out_height,
+                                current_settings.edge_fade,
+                                premultiplied,
+                            );
+                        }
+
+                        let unrotated = (out_width, out_height);
+                        let angle = current_settings.rotation_angle;
+                        let (processed_data, out_width, out_height) = if angle != 0.0 {
+                            rotation::rotate_by(&processed_data, out_width, out_height, angle, current_settings.fill_color)
+                        } else {
+                            (processed_data, out_width, out_height)
+                        };
+
+                        let mut content = None;
+                        let (mut processed_data, out_width, out_height, turned) =
+                            match (current_settings.locked_size, current_settings.viewer_orientation) {
+                                (Some((width, height)), _) => {
+                                    let (data, placed) = center_in(
+                                        processed_data,
+                                        out_width,
+                                        out_height,
+                                        width,
+                                        height,
+                                        current_settings.fill_color,
+                                    );
+                                    content = Some(placed);
+                                    (data, width, height, false)
+                                }
+                                (None, Some(viewer)) => viewer.orient(processed_data, out_width, out_height),
+                                (None, None) => (processed_data, out_width, out_height, false),
+                            };
+
+                        // Last, so it sits in the corner of the final frame
+                        if current_settings.debug_overlay {
+                            overlay_stats.width = out_width;
+                            overlay_stats.height = out_height;
+                            overlay_stats.latency_ms = acquire_started.elapsed().as_secs_f32() * 1000.0;
+                            overlay::draw(&mut processed_data, out_width, out_height, &overlay_stats);
+                        }
+
+                        // Notify frontend about new frame
+                        frame_counter = frame_counter.wrapping_add(1);
+                        fps_counter += 1;
+
+                        let present_count = desktop_present_count();
+                        let accumulated_frames = match (last_present_count, present_count) {
+                            (Some(last), Some(now)) => now.saturating_sub(last) as u32,
+                            _ => 0,
+                        };
+                        last_present_count = present_count;
+                        presents_counter = presents_counter.saturating_add(accumulated_frames);
+
+                        let memory_bytes = {
+                            let mut replay = replay.lock();
+                            let live_bytes = processed_data.len() + thumbnail.read().data.len();
+                            let budget = current_settings.frame_memory_budget;
+                            let room = budget.map_or(usize::MAX, |budget| budget.saturating_sub(live_bytes));
+                            let fits = replay.bytes() + processed_data.len() <= room;
+                            if replay.is_enabled() && (fits || current_settings.memory_policy != MemoryPolicy::DropNewest) {
+                                replay.push(out_width, out_height, processed_data.clone());
+                            }
+                            replay.evict_to(room);
+
+                            if let (Some(budget), MemoryPolicy::ReduceQuality) = (budget, current_settings.memory_policy) {
+                                // Step back up only with plenty of headroom, so it doesn't oscillate
+                                if live_bytes > budget {
+                                    memory_divisor = (memory_divisor * 2).min(MAX_MEMORY_DIVISOR);
+                                } else if memory_divisor > 1 && live_bytes * 8 < budget {
+                                    memory_divisor /= 2;
+                                }
+                            } else {
+                                memory_divisor = 1;
+                            }
+                            live_bytes + replay.bytes()
+                        };
+
+                        if let Some(recording) = video.lock().as_mut() {
+                            if recording.push(processed_data.clone(), out_width, out_height, acquired_at) {
+                                consumers.lock().delivered(ConsumerId::Recording);
+                            } else {
+                                consumers.lock().dropped(ConsumerId::Recording);
+                            }
+                        }
+
+                        let checksum = current_settings.checksum.then(|| crc32fast::hash(&processed_data));
+
+                        // Update the shared state
+                        let mut stats = None;
+                        {
+                            let current_time = std::time::Instant::now();
+                            let mut buffer = frame_buffer.write();
+                            let previous = std::mem::replace(&mut buffer.data, processed_data);
+                            *previous_frame.lock() = (current_settings.frame_interpolation && !previous.is_empty())
+                                .then(|| PreviousFrame { data: previous, width: buffer.width, height: buffer.height });
+                            buffer.width = out_width;
+                            buffer.height = out_height;
+                            buffer.meta.sequence = frame_counter;
+                            buffer.meta.alpha_mode = current_settings.alpha_mode;
+                            buffer.meta.channel = current_settings.channel;
+                            buffer.meta.visualization = current_settings.visualization;
+                            buffer.meta.accumulated_frames = accumulated_frames;
+                            buffer.meta.self_masked = self_masked;
+                            // Published relative to the whole display, not just the part read back
+                            buffer.meta.crop = Rect { x: crop.x + read_x, y: crop.y + read_y, ..crop };
+                            buffer.meta.display_origin = (source_origin.0 - read_x as i32, source_origin.1 - read_y as i32);
+                            buffer.meta.checksum = checksum;
+                            buffer.meta.turned = turned;
+                            buffer.meta.content = content;
+                            buffer.meta.timestamp_us = unix_micros(acquired_at);
+                            buffer.meta.angle = angle;
+                            buffer.unrotated = unrotated;
+                            buffer.captured_at = Some(acquired_at);
+                            buffer.meta.stride = out_width * 4;
+                            let FrameBuffer { data, dirty, .. } = &mut *buffer;
+                            dirty.update(frame_counter, data, out_width, out_height);
+                            let elapsed = current_time.duration_since(last_second);
+                            if elapsed.as_secs() >= 1 {
+                                // Frames over the actual elapsed time keeps fractional and sub-1fps rates
+                                let fps = fps_counter as f32 / elapsed.as_secs_f32();
+                                buffer.fps = fps;
+                                buffer.stats = CaptureStats {
+                                    fps,
+                                    desktop_presents: presents_counter,
+                                    readback_ms: readback_time.as_secs_f32() * 1000.0 / fps_counter.max(1) as f32,
+                                    readback_buffers: backend.pipeline_depth(),
+                                    memory_bytes,
+                                    stream_quality: stream_quality.lock().current(),
+                                };
+                                overlay_stats.fps = fps;
+                                overlay_stats.dropped = presents_counter.saturating_sub(fps_counter);
+                                fps_counter = 0;
+                                presents_counter = 0;
+                                readback_time = Duration::ZERO;
+                                last_second = current_time;
+                                debug!("{:?}", buffer.stats);
+                                stats = Some(buffer.stats.clone());
+                            }
+                        }
+
+                        // The thumbnail reuses this frame's readback instead of capturing again
+                        if let Some((thumb_width, thumb_height)) = current_settings.thumbnail_size {
+                            let mut thumb_data = process_image(
+                                &frame_data,
+                                frame_width,
+                                crop,
+                                thumb_width,
+                                thumb_height,
+                                &current_settings.process_options()
+                            );
+                            mask_regions(&mut thumb_data, thumb_width, thumb_height, crop, &masked, current_settings.fill_color);
+                            mask_to_window(&mut thumb_data, thumb_width, thumb_height, crop, window_mask);
+                            let buffer = frame_buffer.read();
+                            let mut thumb = thumbnail.write();
+                            thumb.data = thumb_data;
+                            thumb.width = thumb_width;
+                            thumb.height = thumb_height;
+                            thumb.fps = buffer.fps;
+                            thumb.meta = buffer.meta.clone();
+                        }
+
+                        // One-off detail frame from the same readback; the preview keeps its size
+                        let hires = hires_request.lock().take();
+                        if let Some((divisor, options, sender)) = hires {
+                            let options = options.unwrap_or_else(|| current_settings.process_options());
+                            let (hires_width, hires_height) = (crop.width / divisor, crop.height / divisor);
+                            let mut hires_data = process_image(
+                                &frame_data,
+                                frame_width,
+                                crop,
+                                hires_width,
+                                hires_height,
+                                &options
+                            );
+                            mask_regions(&mut hires_data, hires_width, hires_height, crop, &masked, options.fill);
+                            mask_to_window(&mut hires_data, hires_width, hires_height, crop, window_mask);
+                            let buffer = frame_buffer.read();
+                            let _ = sender.send((hires_data, hires_width, hires_height, buffer.fps, buffer.meta.clone()));
+                        }
+
+                        // Pipelined readback lags by `buffers - 1` frames, so count that many
+                        // acquires begun after the request before the data is fresh
+                        {
+                            let mut grab = grab_request.lock();
+                            let fresh = grab.as_mut().is_some_and(|request| {
+                                if acquire_started >= request.requested_at {
+                                    request.frames += 1;
+                                }
+                                request.frames >= backend.pipeline_depth()
+                            });
+                            if let Some(request) = grab.take_if(|_| fresh) {
+                                let buffer = frame_buffer.read();
+                                let _ = request.sender.send(crate::frame_data(&buffer));
+                            }
+                        }
+
+                        // Same for a fired trigger, timed from the first frame acquired after it
+                        {
+                            let mut armed = trigger.lock();
+                            let fresh = armed.as_mut().is_some_and(|request| {
+                                let Some(fired_at) = request.fired_at else {
+                                    return false;
+                                };
+                                if acquire_started >= fired_at {
+                                    request.captured_at.get_or_insert(acquired_at);
+                                    request.frames += 1;
+                                }
+                                request.frames >= backend.pipeline_depth()
+                            });
+                            if let Some(request) = armed.take_if(|_| fresh) {
+                                let buffer = frame_buffer.read();
+                                let frame = request.frame(&buffer);
+                                let _ = request.sender.send(frame);
+                            }
+                        }
+
+                        if let Some(composed_at) = composed_at {
+                            let mut probe = latency_probe.lock();
+                            *probe = probe.take().and_then(|probe| probe.push(composed_at));
+                        }
+
+                        frame_published.send_replace(frame_counter);
+
+                        // Uncapped pacing still has to keep up with the display
+                        let refresh_rate = backend.display().refresh_rate;
+                        let budget = pacing.period(refresh_rate).or(Pacing::MatchRefresh.period(refresh_rate));
+                        if let Some(adaptation) = adaptive_scale.record(acquired_at.elapsed(), budget.unwrap_or_default()) {
+                            info!("Adapted output quality: {:?}", adaptation);
+                            if let Err(e) = window.emit("quality-adapted", adaptation) {
+                                warn!("Failed to emit quality-adapted event: {:?}", e);
+                            }
+                        }
+
+                        // Frontends gate their UI on this instead of polling into "No valid frame data"
+                        if !ready {
+                            ready = true;
+                            set_status(&window, &status, CaptureStatus::Running);
+                            let size = FrameSize { width: out_width, height: out_height };
+                            if let Err(e) = window.emit("capture-ready", size) {
+                                warn!("Failed to emit capture-ready event: {:?}", e);
+                            }
+                        }
+
+                        if let Some(stats) = stats {
+                            if let Err(e) = window.emit("capture-stats", stats) {
+                                warn!("Failed to emit capture-stats event: {:?}", e);
+                            }
+                        }
+
+                        // Channel subscribers get every frame, in order
+                        let mut subscriber = frame_channel.lock();
+                        if let Some(FrameChannel { channel, with_data, skip_unchanged, last_data }) = subscriber.as_mut() {
+                            let (meta, data) = {
+                                let buffer = frame_buffer.read();
+                                // A sequence that went backwards means the stream restarted
+                                let changed = !*skip_unchanged
+                                    || last_data.is_none_or(|last| {
+                                        last >= frame_counter || buffer.dirty.changed_since(last).width > 0
+                                    });
+                                let meta = FrameMessage::Meta {
+                                    sequence: frame_counter,
+                                    width: buffer.width,
+                                    height: buffer.height,
+                                    meta: buffer.meta.clone(),
+                                    dirty: buffer.dirty.changed_since(frame_counter.wrapping_sub(1)),
+                                    cursor: cursor.lock().position(),
+                                };
+                                let data = (*with_data && changed)
+                                    .then(|| FrameMessage::Data { sequence: frame_counter, data: buffer.data.clone() });
+                                (meta, data)
+                            };
+                            let sent_data = data.is_some();
+                            let sent = channel.send(meta).and_then(|()| data.map_or(Ok(()), |data| channel.send(data)));
+                            match sent {
+                                Ok(()) => {
+                                    if sent_data {
+                                        *last_data = Some(frame_counter);
+                                    }
+                                    consumers.lock().delivered(ConsumerId::FrameChannel)
+                                }
+                                Err(e) => {
+                                    // The consumer is gone
+                                    debug!("Frame channel closed: {:?}", e);
+                                    *subscriber = None;
+                                    consumers.lock().unregister(ConsumerId::FrameChannel);
+                                }
+                            }
+                        }
+                        drop(subscriber);
+                        streams.lock().feed(&frame_buffer.read(), &consumers);
+
+                        // Coalesce notifications; the buffer above is always current
+                        let now = Instant::now();
+                        let emit_due = last_emit
+                            .is_none_or(|last| now.duration_since(last) >= current_settings.emit_interval);
+                        if emit_due {
+                            last_emit = Some(now);
+                            if let Err(e) = window.emit("frame-ready", frame_counter) {
+                                warn!("Failed to emit frame-ready event: {:?}", e);
+                            }
+                            consumers.lock().delivered(ConsumerId::FrameEvents);
+
+                            // Patches are only cut when emitted so consumers never miss a change
+                            if delta_encoder.as_ref().map(|encoder| encoder.config()) != current_settings.delta {
+                                delta_encoder = current_settings.delta.map(DeltaEncoder::new);
+                            }
+                            if let Some(encoder) = delta_encoder.as_mut() {
+                                if keyframe_requested.swap(false, Ordering::AcqRel) {
+                                    encoder.request_keyframe();
+                                }
+                                let buffer = frame_buffer.read();
+                                let patch = encoder.encode(frame_counter, &buffer.data, buffer.width, buffer.height);
+                                drop(buffer);
+                                if !patch.is_empty() {
+                                    if let Err(e) = window.emit("frame-delta", patch) {
+                                        warn!("Failed to emit frame-delta event: {:?}", e);
+                                    }
+                                }
+                            }
+                        } else {
+                            consumers.lock().dropped(ConsumerId::FrameEvents);
+                        }
+                    },
+                    Err(e) => {
+                        error!("Failed to get texture data: {:?}", e);
+                        let message = format!("Failed to read frame: {:?}", e);
+                        report_error(&window, &errors, ErrorKind::Read, message, false);
+                        continue;
+                    }
+                }
+            },
+            Err(e) => {
+                warn!("Failed to acquire frame: {:?}", e);
+                report_error(&window, &errors, ErrorKind::Acquire, format!("Failed to acquire frame: {:?}", e), false);
+                match backend.failure(&e) {
+                    Failure::DeviceLost => {
+                        drop(backend);
+                        let cause = format!("{:?}", e);
+                        let options = settings.read().source_options();
+                        match recover_device(&window, &status, &shutdown, selected_display.as_deref(), &options, cause).await {
+                            Some(opened) => {
+                                backend = opened;
+                                backend.display().publish(&info);
+                                gpu_priority = None;
+                                gpu_convert_failed = false;
+                                warm_up_source(&mut backend, &shutdown).await;
+                                delta_encoder = None;
+                            }
+                            None => break,
+                        }
+                    }
+                    // Potentially reinitialize duplication API
+                    Failure::AccessLost => break,
+                    Failure::Transient => {}
+                }
+            }
+        }
+    }
+
+    info!("Capture stopped");
+    Ok(())
+}
+
+/// Bytes, width, height, fps and metadata as returned to the frontend.
+type FrameData = (Vec<u8>, u32, u32, f32, FrameMeta);
+
+/// Pending `grab_now`, fulfilled by the first frame acquired after it was made.
+struct GrabRequest {
+    requested_at: Instant,
+    /// Frames acquired since `requested_at`.
+    frames: usize,
+    sender: oneshot::Sender<Result<FrameData, String>>,
+}
+
+/// Pending `arm_trigger`, fulfilled by the first frame acquired after
+/// `fire_trigger`.
+struct TriggerRequest {
+    fired_at: Option<Instant>,
+    /// When the first frame acquired after firing arrived.
+    captured_at: Option<Instant>,
+    /// Frames acquired since `fired_at`.
+    frames: usize,
+    sender: oneshot::Sender<Result<TriggeredFrame, String>>,
+}
+
+impl TriggerRequest {
+    fn frame(&self, frame_buffer: &FrameBuffer) -> Result<TriggeredFrame, String> {
+        let (data, width, height, _, meta) = frame_data(frame_buffer)?;
+        let (Some(fired_at), Some(captured_at)) = (self.fired_at, self.captured_at) else {
+            return Err("Trigger completed before firing".to_string());
+        };
+        Ok(TriggeredFrame {
+            data,
+            width,
+            height,
+            meta,
+            delay_ms: captured_at.duration_since(fired_at).as_secs_f64() * 1000.0,
+            timestamp_us: unix_micros(captured_at),
+        })
+    }
+}
+
+/// Wall-clock time of `at`, in microseconds since the Unix epoch.
+fn unix_micros(at: Instant) -> u64 {
+    let time = SystemTime::now() - at.elapsed();
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_micros() as u64)
+}
+
+/// The frame captured for a trigger.
+#[derive(Serialize)]
+pub struct TriggeredFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    meta: FrameMeta,
+    /// From `fire_trigger` to the frame being acquired.
+    delay_ms: f64,
+    /// Wall-clock time the frame was acquired, in microseconds since the Unix epoch.
+    timestamp_us: u64,
+}
+
+/// Unprocessed desktop texture contents.
+#[derive(Clone, Debug, Serialize)]
+pub struct RawFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Texture format as reported by the duplication API.
+    format: String,
+    /// Bytes per row; rows are tightly packed.
+    stride: u32,
+}
+
+/// Pending one-off request for a finer frame: crop divisor, processing to use
+/// instead of the preview's, and where to send it.
+type HiresRequest = (u32, Option<ProcessOptions>, oneshot::Sender<FrameData>);
+
+fn frame_data(frame_buffer: &FrameBuffer) -> Result<FrameData, String> {
+    if !frame_buffer.data.is_empty() && frame_buffer.width > 0 && frame_buffer.height > 0 {
+        Ok((
+            frame_buffer.data.clone(),
+            frame_buffer.width,
+            frame_buffer.height,
+            frame_buffer.fps,
+            frame_buffer.meta.clone()
+        ))
+    } else {
+        Err("No valid frame data available.".to_string())
+    }
+}
+
+/// The frame, or the configured placeholder while none has been captured
+/// yet, with rows padded to the output alignment.
+fn frame_or_placeholder(state: &CaptureState, frame_buffer: &FrameBuffer) -> Result<FrameData, String> {
+    let (placeholder, alignment) = {
+        let settings = state.settings.read();
+        (settings.placeholder, settings.output_alignment)
+    };
+    let (data, width, height, fps, mut meta) = match (frame_data(frame_buffer), placeholder) {
+        (Err(_), Some((width, height, color))) if frame_buffer.meta.sequence == 0 => {
+            let data = color.repeat(frame_len(width, height)? / 4);
+            (data, width, height, 0.0, FrameMeta::default())
+        }
+        (frame, _) => frame?,
+    };
+    let (data, stride) = align_rows(data, width, height, alignment);
+    meta.stride = stride;
+    Ok((data, width, height, fps, meta))
+}
+
+/// Pads each row of a packed `width`×`height` frame to a multiple of
+/// `alignment` bytes, returning it with its stride. Processing works on
+/// packed rows throughout, so padding is only added on the way out.
+fn align_rows(data: Vec<u8>, width: u32, height: u32, alignment: u32) -> (Vec<u8>, u32) {
+    let row_len = width * 4;
+    let stride = row_len.next_multiple_of(alignment.max(1));
+    if stride == row_len || row_len == 0 {
+        return (data, row_len);
+    }
+    let mut padded = vec![0; stride as usize * height as usize];
+    for (src, dst) in data.chunks_exact(row_len as usize).zip(padded.chunks_exact_mut(stride as usize)) {
+        dst[..src.len()].copy_from_slice(src);
+    }
+    (padded, stride)
+}
+
+/// Pads rows returned by `get_frame_data` to a multiple of `bytes`, a power
+/// of two, so they can be uploaded as aligned textures without a repack.
+/// `meta.stride` reports the padded row length; 1 packs rows tightly.
+#[tauri::command]
+fn set_output_alignment(state: State<'_, CaptureState>, bytes: u32) -> Result<(), String> {
+    validate_output_alignment(bytes)?;
+    state.settings.write().output_alignment = bytes;
+    Ok(())
+}
+
+fn validate_output_alignment(bytes: u32) -> Result<(), String> {
+    if !bytes.is_power_of_two() || bytes > MAX_OUTPUT_ALIGNMENT {
+        return Err(format!("Alignment must be a power of two up to {}", MAX_OUTPUT_ALIGNMENT));
+    }
+    Ok(())
+}
+
+/// Resolves with the frame size once capture is delivering frames, so
+/// startup can wait for it rather than polling into "No valid frame data".
+/// Rejects with `Timeout` after `timeout_ms`, 2 seconds by default.
+#[tauri::command]
+async fn warm_up(state: State<'_, CaptureState>, timeout_ms: Option<u64>) -> Result<FrameSize, String> {
+    let mut published = state.frame_published.subscribe();
+    let timeout = timeout_ms.map_or(FRAME_REQUEST_TIMEOUT, Duration::from_millis);
+    tokio::time::timeout(timeout, published.wait_for(|&sequence| sequence != 0))
+        .await
+        .map_err(|_| "Timeout".to_string())?
+        .map_err(|e| e.to_string())?;
+    let buffer = state.frame_buffer.read();
+    Ok(FrameSize { width: buffer.width, height: buffer.height })
+}
+
+/// Waits for a frame other than `since_sequence`, the last one the caller
+/// saw, and returns it; rejects with `Timeout` after `timeout_ms`.
+#[tauri::command]
+async fn next_frame(state: State<'_, CaptureState>, since_sequence: u32, timeout_ms: u64) -> Result<FrameData, String> {
+    let mut published = state.frame_published.subscribe();
+    let newer = published.wait_for(|&sequence| sequence != 0 && sequence != since_sequence);
+    tokio::time::timeout(Duration::from_millis(timeout_ms), newer)
+        .await
+        .map_err(|_| "Timeout".to_string())?
+        .map_err(|e| e.to_string())?;
+    frame_or_placeholder(&state, &state.frame_buffer.read())
+}
+
+/// Returns a `TooSoon` error, without touching the frame buffer, when called
+/// again within the minimum fetch interval.
+#[tauri::command]
+fn get_frame_data(state: State<'_, CaptureState>) -> Result<FrameData, String> {
+    let min_interval = state.settings.read().min_fetch_interval;
+    let mut last_fetch = state.last_fetch.lock();
+    if last_fetch.is_some_and(|last| last.elapsed() < min_interval) {
+        state.consumers.lock().dropped(ConsumerId::Polling);
+        return Err("TooSoon".to_string());
+    }
+    let frame = frame_or_placeholder(&state, &state.frame_buffer.read())?;
+    *last_fetch = Some(Instant::now());
+    state.consumers.lock().delivered(ConsumerId::Polling);
+    Ok(frame)
+}
+
+/// The current frame, but only if it was acquired within `max_age_ms`;
+/// otherwise fails with `TooStale`, so a late frame is never shown as live.
+#[tauri::command]
+fn get_fresh_frame(state: State<'_, CaptureState>, max_age_ms: u64) -> Result<FrameData, String> {
+    let buffer = state.frame_buffer.read();
+    let fresh = buffer
+        .captured_at
+        .is_some_and(|at| at.elapsed() <= Duration::from_millis(max_age_ms));
+    if !fresh {
+        return Err("TooStale".to_string());
+    }
+    let (data, width, height, fps, mut meta) = frame_data(&buffer)?;
+    let (data, stride) = align_rows(data, width, height, state.settings.read().output_alignment);
+    meta.stride = stride;
+    Ok((data, width, height, fps, meta))
+}
+
+/// A frame `t` of the way from the previous frame to the current one, for
+/// playing a low-rate capture back at the display rate. Returns the current
+/// frame while there is no previous one of the same size to blend with.
+#[tauri::command]
+fn get_interpolated_frame(state: State<'_, CaptureState>, t: f32) -> Result<FrameData, String> {
+    if !(0.0..=1.0).contains(&t) {
+        return Err("t must be between 0 and 1".to_string());
+    }
+    let buffer = state.frame_buffer.read();
+    let previous = state.previous_frame.lock();
+    let Some(previous) = previous
+        .as_ref()
+        .filter(|previous| (previous.width, previous.height) == (buffer.width, buffer.height))
+    else {
+        return frame_or_placeholder(&state, &buffer);
+    };
+    let (data, width, height, fps, mut meta) = frame_data(&buffer)?;
+    let data = interpolate::blend(&previous.data, &data, t);
+    let (data, stride) = align_rows(data, width, height, state.settings.read().output_alignment);
+    meta.stride = stride;
+    Ok((data, width, height, fps, meta))
+}
+
+/// Keeps the previous frame so `get_interpolated_frame` can blend towards
+/// the current one. Costs one frame of memory.
+#[tauri::command]
+fn set_frame_interpolation(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().frame_interpolation = enabled;
+    if !enabled {
+        state.previous_frame.lock().take();
+    }
+}
+
+#[tauri::command]
+fn get_thumbnail_data(state: State<'_, CaptureState>) -> Result<FrameData, String> {
+    frame_data(&state.thumbnail.read())
+}
+
+/// Returns the next frame at `1/divisor` of the crop size (full resolution by
+/// default) without changing the preview.
+#[tauri::command]
+async fn request_hires_frame(state: State<'_, CaptureState>, divisor: Option<u32>) -> Result<FrameData, String> {
+    next_hires_frame(&state, divisor, None).await
+}
+
+#[derive(Serialize)]
+pub struct WebpFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Encoded size in bytes, for adapting quality to bandwidth.
+    size: usize,
+    meta: FrameMeta,
+}
+
+/// Returns the current frame as WebP; see `webp::encode` for `quality`.
+/// Without `quality`, uses the one adapted to `set_stream_bitrate`.
+#[tauri::command]
+async fn get_frame_webp(state: State<'_, CaptureState>, quality: Option<u8>, lossless: bool) -> Result<WebpFrame, String> {
+    if quality.is_some_and(|quality| quality > 100) {
+        return Err("Quality must be between 0 and 100".to_string());
+    }
+    let adaptive = quality.is_none() && !lossless;
+    let quality = quality.unwrap_or_else(|| state.stream_quality.lock().quality());
+    let (data, width, height, _, meta) = frame_data(&state.frame_buffer.read())?;
+    let frame = tauri::async_runtime::spawn_blocking(move || {
+        let data = webp::encode(&data, width, height, quality, lossless)?;
+        Ok::<_, String>(WebpFrame { size: data.len(), data, width, height, meta })
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if adaptive {
+        state.stream_quality.lock().record(frame.size);
+    }
+    Ok(frame)
+}
+
+/// Adapts the quality of `get_frame_webp` calls without one to keep the
+/// stream near `kbps`; `None` goes back to a fixed default quality.
+#[tauri::command]
+fn set_stream_bitrate(state: State<'_, CaptureState>, kbps: Option<u32>) -> Result<(), String> {
+    if kbps == Some(0) {
+        return Err("Bitrate must be greater than zero".to_string());
+    }
+    state.stream_quality.lock().set_target(kbps);
+    Ok(())
+}
+
+/// A frame reduced to at most 256 colours.
+#[derive(Serialize)]
+pub struct IndexedFrame {
+    /// RGBA entries that `indices` refer to.
+    palette: Vec<[u8; 4]>,
+    /// One byte per pixel, rows tightly packed.
+    indices: Vec<u8>,
+    width: u32,
+    height: u32,
+    meta: FrameMeta,
+}
+
+/// Returns the current frame quantized to the palette set with
+/// `set_output_palette`.
+#[tauri::command]
+async fn get_frame_indexed(state: State<'_, CaptureState>) -> Result<IndexedFrame, String> {
+    let mode = state.settings.read().output_palette.ok_or("No output palette set")?;
+    let (data, width, height, _, meta) = frame_data(&state.frame_buffer.read())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let indexed = palette::quantize(&data, mode);
+        IndexedFrame { palette: indexed.palette, indices: indexed.indices, width, height, meta }
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// The frame as `f32` RGB values; see `get_frame_tensor`.
+#[derive(Serialize)]
+pub struct FrameTensor {
+    data: Vec<f32>,
+    /// Dimension sizes in `layout` order.
+    shape: [u32; 3],
+    layout: TensorLayout,
+    meta: FrameMeta,
+}
+
+/// Returns the current frame as `f32` RGB in `layout`, scaled to 0..1 and
+/// then normalized per channel if `normalize` is given. Alpha is dropped;
+/// with `AlphaMode::Premultiplied` the colour values are premultiplied.
+#[tauri::command]
+async fn get_frame_tensor(
+    state: State<'_, CaptureState>,
+    layout: TensorLayout,
+    normalize: Option<Normalization>,
+) -> Result<FrameTensor, String> {
+    if let Some(normalize) = &normalize {
+        normalize.validate()?;
+    }
+    let (data, width, height, _, meta) = frame_data(&state.frame_buffer.read())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let shape = match layout {
+            TensorLayout::Hwc => [height, width, 3],
+            TensorLayout::Chw => [3, height, width],
+        };
+        let data = tensor::to_tensor(&data, width, height, layout, normalize);
+        FrameTensor { data, shape, layout, meta }
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Enables `get_frame_indexed` with the given palette; `None` disables it.
+/// Median cut costs noticeably more CPU per frame than the fixed palette.
+#[tauri::command]
+fn set_output_palette(state: State<'_, CaptureState>, mode: Option<PaletteMode>) -> Result<(), String> {
+    if let Some(mode) = mode {
+        mode.validate()?;
+    }
+    state.settings.write().output_palette = mode;
+    Ok(())
+}
+
+/// Returns the current crop as contrast-stretched grayscale, 1 byte per pixel,
+/// optionally binarized. Independent of the preview's processing settings.
+#[tauri::command]
+async fn get_frame_for_ocr(
+    state: State<'_, CaptureState>,
+    divisor: Option<u32>,
+    threshold: Option<Threshold>
+) -> Result<OcrFrame, String> {
+    let (data, width, height, ..) = next_hires_frame(&state, divisor, Some(ProcessOptions::default())).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        ocr::preprocess(&data, width, height, threshold.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+async fn next_hires_frame(
+    state: &CaptureState,
+    divisor: Option<u32>,
+    options: Option<ProcessOptions>
+) -> Result<FrameData, String> {
+    let divisor = divisor.unwrap_or(1);
+    if divisor == 0 {
+        return Err("Divisor must be non-zero".to_string());
+    }
+    let (sender, receiver) = oneshot::channel();
+    *state.hires_request.lock() = Some((divisor, options, sender));
+
+    match tokio::time::timeout(FRAME_REQUEST_TIMEOUT, receiver).await {
+        Ok(Ok(frame)) => Ok(frame),
+        Ok(Err(_)) => Err("Superseded by another high-resolution request".to_string()),
+        Err(_) => Err("Timed out waiting for a high-resolution frame".to_string()),
+    }
+}
+
+/// Waits for and returns the first frame acquired after this call, rather
+/// than the possibly older buffered one.
+#[tauri::command]
+async fn grab_now(state: State<'_, CaptureState>) -> Result<FrameData, String> {
+    let (sender, receiver) = oneshot::channel();
+    *state.grab_request.lock() = Some(GrabRequest { requested_at: Instant::now(), frames: 0, sender });
+
+    match tokio::time::timeout(FRAME_REQUEST_TIMEOUT, receiver).await {
+        Ok(Ok(frame)) => frame,
+        Ok(Err(_)) => Err("Superseded by another grab".to_string()),
+        Err(_) => Err("Timed out waiting for a fresh frame; is capture stalled?".to_string()),
+    }
+}
+
+/// Arms a trigger and waits for it to fire, returning the first frame
+/// acquired after `fire_trigger` with the delay between them. Arming again
+/// replaces a trigger that hasn't completed. Rejects at once while capture
+/// isn't running, and with `Timeout`, disarming the trigger, if it hasn't
+/// completed within `timeout_ms`; without one it waits indefinitely.
+#[tauri::command]
+async fn arm_trigger(state: State<'_, CaptureState>, timeout_ms: Option<u64>) -> Result<TriggeredFrame, String> {
+    if !state.is_capturing() {
+        return Err("Capture isn't running".to_string());
+    }
+    let (sender, receiver) = oneshot::channel();
+    *state.trigger.lock() = Some(TriggerRequest { fired_at: None, captured_at: None, frames: 0, sender });
+
+    let completed = match timeout_ms {
+        Some(ms) => match tokio::time::timeout(Duration::from_millis(ms), receiver).await {
+            Ok(completed) => completed,
+            Err(_) => {
+                // Our receiver is gone, so a closed sender is ours rather than a newer trigger's
+                state.trigger.lock().take_if(|request| request.sender.is_closed());
+                return Err("Timeout".to_string());
+            }
+        },
+        None => receiver.await,
+    };
+    completed.map_err(|_| "Trigger was replaced or capture stopped".to_string())?
+}
+
+/// Fires the armed trigger, e.g. from an external event relayed by the frontend.
+#[tauri::command]
+fn fire_trigger(state: State<'_, CaptureState>) -> Result<(), String> {
+    let fired_at = Instant::now();
+    match state.trigger.lock().as_mut() {
+        Some(request) if request.fired_at.is_none() => {
+            request.fired_at = Some(fired_at);
+            Ok(())
+        }
+        Some(_) => Err("Trigger already fired".to_string()),
+        None => Err("No trigger armed".to_string()),
+    }
+}
+
+/// Captures `count` consecutive frames at `1/scale_factor` of the crop,
+/// pausing the preview until they are all in.
+#[tauri::command]
+async fn capture_burst(state: State<'_, CaptureState>, count: u32, scale_factor: u32) -> Result<Burst, String> {
+    if !(1..=MAX_BURST_FRAMES).contains(&count) {
+        return Err(format!("Burst count must be between 1 and {}", MAX_BURST_FRAMES));
+    }
+    if scale_factor == 0 {
+        return Err("Scale factor must be non-zero".to_string());
+    }
+    let (sender, receiver) = oneshot::channel();
+    *state.burst_request.lock() = Some(BurstRequest::new(count, scale_factor, sender));
+
+    match tokio::time::timeout(BURST_TIMEOUT, receiver).await {
+        Ok(Ok(burst)) => burst,
+        Ok(Err(_)) => Err("Superseded by another burst".to_string()),
+        Err(_) => {
+            // Give the preview back
+            state.burst_request.lock().take();
+            Err("Timed out waiting for burst frames".to_string())
+        }
+    }
+}
+
+/// Discards frames buffered before this call and restarts sequences at 1,
+/// returning the first fresh frame.
+#[tauri::command]
+async fn flush(state: State<'_, CaptureState>) -> Result<FrameData, String> {
+    state.flush_requested.store(true, Ordering::Release);
+    grab_now(state).await
+}
+
+/// Measures the time from DWM composing a frame to it being published in
+/// the frame buffer over the next `samples` frames (60 by default).
+#[tauri::command]
+async fn measure_latency(state: State<'_, CaptureState>, samples: Option<u32>) -> Result<LatencyReport, String> {
+    let samples = samples.unwrap_or(60);
+    if !(1..=MAX_LATENCY_SAMPLES).contains(&samples) {
+        return Err(format!("Samples must be between 1 and {}", MAX_LATENCY_SAMPLES));
+    }
+    let (sender, receiver) = oneshot::channel();
+    *state.latency_probe.lock() = Some(LatencyProbe::new(samples, sender));
+
+    // Generous enough for every sample at 1fps pacing plus startup
+    let timeout = FRAME_REQUEST_TIMEOUT + Duration::from_secs(samples as u64);
+    match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(report)) => Ok(report),
+        Ok(Err(_)) => Err("Superseded by another latency measurement".to_string()),
+        Err(_) => Err("Timed out waiting for frames to measure".to_string()),
+    }
+}
+
+/// Returns the next desktop frame exactly as read back: full resolution,
+/// uncropped and still BGRA.
+///
+/// Expensive: a 4K desktop is over 33 MB before IPC serialization, so this
+/// is meant for occasional custom processing, not per-frame use.
+#[tauri::command]
+async fn get_raw_texture_frame(state: State<'_, CaptureState>) -> Result<RawFrame, String> {
+    let (sender, receiver) = oneshot::channel();
+    *state.raw_request.lock() = Some(sender);
+
+    match tokio::time::timeout(FRAME_REQUEST_TIMEOUT, receiver).await {
+        Ok(Ok(frame)) => Ok(frame),
+        Ok(Err(_)) => Err("Superseded by another raw frame request".to_string()),
+        Err(_) => Err("Timed out waiting for a raw frame".to_string()),
+    }
+}
+
+/// Like `get_frame_data`, but returns a `Busy` error instead of blocking on the lock.
+#[tauri::command]
+fn try_get_frame_data(state: State<'_, CaptureState>) -> Result<FrameData, String> {
+    let frame_buffer = state.frame_buffer
+        .try_read_for(FRAME_READ_TIMEOUT)
+        .ok_or("Busy")?;
+
+    frame_or_placeholder(&state, &frame_buffer)
+}
+
+/// Keeps the current frame as the reference for `capture_diff` and returns its sequence.
+#[tauri::command]
+fn set_baseline(state: State<'_, CaptureState>) -> Result<u32, String> {
+    let frame_buffer = state.frame_buffer.read();
+    if frame_buffer.data.is_empty() {
+        return Err("No valid frame data".to_string());
+    }
+    let sequence = frame_buffer.meta.sequence;
+    *state.baseline.lock() = Some(Baseline {
+        sequence,
+        width: frame_buffer.width,
+        height: frame_buffer.height,
+        data: frame_buffer.data.clone(),
+    });
+    Ok(sequence)
+}
+
+/// Returns the area of the current frame that changed after `since_sequence`,
+/// or the whole frame, flagged `full`, when that frame is too old to compare.
+#[tauri::command]
+fn get_frame_delta(state: State<'_, CaptureState>, since_sequence: u32) -> Result<FrameDelta, String> {
+    let frame_buffer = state.frame_buffer.read();
+    if frame_buffer.data.is_empty() {
+        return Err("No valid frame data".to_string());
+    }
+    Ok(frame_buffer.dirty.delta(since_sequence, frame_buffer.meta.sequence, &frame_buffer.data))
+}
+
+/// Diffs the current frame against the baseline set as `baseline_sequence`.
+#[tauri::command]
+fn capture_diff(state: State<'_, CaptureState>, baseline_sequence: u32) -> Result<FrameDiff, String> {
+    let baseline = state.baseline.lock();
+    let baseline = baseline.as_ref().ok_or("No baseline set")?;
+    if baseline.sequence != baseline_sequence {
+        return Err(format!(
+            "Baseline {} has been replaced by {}",
+            baseline_sequence, baseline.sequence
+        ));
+    }
+
+    let frame_buffer = state.frame_buffer.read();
+    diff::diff(baseline, frame_buffer.meta.sequence, frame_buffer.width, frame_buffer.height, &frame_buffer.data)
+}
+
+#[tauri::command]
+async fn stop_capture(state: State<'_, CaptureState>) -> Result<(), String> {
+    state.shutdown().await
+}
+
+/// Returned by `safe_mode`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SafeModeReport {
+    status: CaptureStatus,
+    /// Whether the capture loop exited within the shutdown timeout.
+    capture_stopped: bool,
+    /// Whether the window can now be seen in screen captures and shares.
+    protection_disabled: bool,
+    /// The recording that was in progress, finalized; `None` if there was
+    /// none or it couldn't be finalized.
+    recording: Option<RecordingSummary>,
+}
+
+/// Gets the app into a known-good state for troubleshooting: stops capture
+/// and any recording, lifts capture protection so the window shows up in a
+/// screen share, closes the frame channel and every stream, drops pending
+/// frame requests, clears the last frame, and resets every setting, the
+/// per-display settings, the watermark, the streamed region, the replay
+/// buffer, the baseline, the error interval, the stream bitrate target and
+/// the log level to their defaults. Capture stays stopped until the app restarts.
+#[tauri::command]
+async fn safe_mode(window: tauri::Window, state: State<'_, CaptureState>) -> Result<SafeModeReport, String> {
+    let capture_stopped = match state.shutdown().await {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Safe mode: {}", e);
+            false
+        }
+    };
+    let recording = state.video.lock().take();
+    let recording = match recording {
+        Some(recording) => {
+            let stopped = tauri::async_runtime::spawn_blocking(move || recording.stop()).await;
+            // Finish the reset whether or not the file could be finalized
+            match stopped.map_err(|e| e.to_string()).and_then(|stopped| stopped) {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    warn!("Safe mode couldn't finalize the recording: {}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    state.consumers.lock().unregister(ConsumerId::Recording);
+
+    state.frame_channel.lock().take();
+    state.consumers.lock().unregister(ConsumerId::FrameChannel);
+    state.streams.lock().close_all(&state.consumers);
+    // Dropping the senders fails whatever is waiting on them
+    state.grab_request.lock().take();
+    state.trigger.lock().take();
+    state.burst_request.lock().take();
+    state.hires_request.lock().take();
+    state.raw_request.lock().take();
+    state.latency_probe.lock().take();
+
+    let protection_disabled = match disable_capture_protection(&window) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Safe mode couldn't lift capture protection: {}", e);
+            false
+        }
+    };
+    state.self_excluded.store(false, Ordering::Release);
+
+    *state.settings.write() = CaptureSettings::default();
+    state.display_profiles.lock().clear();
+    state.watermark.lock().take();
+    state.dynamic_region.store(None)?;
+    // Consumers get "No valid frame data" rather than a frozen frame
+    *state.frame_buffer.write() = FrameBuffer::default();
+    state.previous_frame.lock().take();
+    *state.thumbnail.write() = FrameBuffer::default();
+    *state.replay.lock() = ReplayBuffer::default();
+    state.baseline.lock().take();
+    state.errors.lock().set_interval(ErrorLimiter::default().interval());
+    state.stream_quality.lock().set_target(None);
+    log::set_max_level(DEFAULT_LOG_LEVEL);
+
+    set_status(&window, &state.status, CaptureStatus::SafeMode);
+    info!("Entered safe mode");
+    Ok(SafeModeReport { status: CaptureStatus::SafeMode, capture_stopped, protection_disabled, recording })
+}
+
+/// Which resize path produced a `capture_scaled` frame.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub enum ScalePath {
+    Gpu,
+    Cpu,
+}
+
+#[derive(Serialize)]
+pub struct ScaledFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    path: ScalePath,
+}
+
+/// Captures one frame of a display resized to exactly `out_w`×`out_h` RGBA.
+///
+/// Scales on the GPU with the D3D11 video processor, falling back to
+/// `process_image` when that isn't available.
+#[tauri::command]
+async fn capture_scaled(
+    state: State<'_, CaptureState>,
+    display_index: u32,
+    out_w: u32,
+    out_h: u32
+) -> Result<ScaledFrame, String> {
+    if out_w == 0 || out_h == 0 {
+        return Err("Output size must be non-zero".to_string());
+    }
+    frame_len(out_w, out_h)?;
+    let options = state.settings.read().process_options();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        dpi::ensure_aware();
+        co_init();
+
+        let (adapter, output) = find_display(display_index)?;
+        let rotation = displays::rotation(&output)?;
+        let mut dupl = DesktopDuplicationApi::new(adapter, output)
+            .map_err(|e| format!("Failed to initialize desktop duplication: {:?}", e))?;
+        dupl.configure(DuplicationApiOptions { skip_cursor: true });
+
+        let tex = acquire_first_frame(&mut dupl)?;
+        let desc = tex.desc();
+        let full = Rect { x: 0, y: 0, width: desc.width, height: desc.height };
+        let (device, ctx) = dupl.get_device_and_ctx();
+        // The GPU scales the texture as stored; turn the result upright afterwards
+        let (texture_w, texture_h) = if rotation.is_quarter_turn() { (out_h, out_w) } else { (out_w, out_h) };
+
+        let scaled = unsafe {
+            gpu::scale_texture(
+                &gpu::rewrap(&device),
+                &gpu::rewrap(&ctx),
+                &gpu::rewrap(tex.as_raw_ref()),
+                full,
+                texture_w,
+                texture_h,
+            )
+        };
+        match scaled {
+            Ok(data) => {
+                let (mut data, ..) = rotation.upright(data, texture_w, texture_h);
+                for pixel in data.chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&options.apply([pixel[0], pixel[1], pixel[2], pixel[3]]));
+                }
+                Ok(ScaledFrame { data, width: out_w, height: out_h, path: ScalePath::Gpu })
+            }
+            Err(e) => {
+                info!("GPU scaling unavailable, using CPU: {}", e);
+                let mut frame_data = Vec::new();
+                TextureReader::new(device, ctx)
+                    .get_data(&mut frame_data, &tex)
+                    .map_err(|e| format!("Failed to get texture data: {:?}", e))?;
+                let (frame_data, frame_width, frame_height) = rotation.upright(frame_data, desc.width, desc.height);
+                let full = Rect { x: 0, y: 0, width: frame_width, height: frame_height };
+                let data = process_image(&frame_data, frame_width, full, out_w, out_h, &options);
+                Ok(ScaledFrame { data, width: out_w, height: out_h, path: ScalePath::Cpu })
+            }
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[derive(Serialize)]
+pub struct VirtualDesktopFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Top-left of the virtual desktop, which is negative when a display
+    /// sits left of or above the primary one.
+    x: i32,
+    y: i32,
+    /// Size of the virtual desktop in desktop pixels, or in pixels at
+    /// `normalized_dpi` when set.
+    desktop_width: u32,
+    desktop_height: u32,
+    /// The DPI every display was scaled to, with `set_dpi_normalize` on.
+    normalized_dpi: Option<u32>,
+}
+
+/// Captures every display into one frame laid out as on the virtual
+/// desktop, at `1/divisor` scale (full resolution by default). Areas no
+/// display covers are filled with the fill colour.
+#[tauri::command]
+async fn capture_virtual_desktop(state: State<'_, CaptureState>, divisor: Option<u32>) -> Result<VirtualDesktopFrame, String> {
+    let divisor = divisor.unwrap_or(1);
+    if divisor == 0 {
+        return Err("Divisor must be non-zero".to_string());
+    }
+    let (options, normalize_dpi) = {
+        let settings = state.settings.read();
+        (settings.process_options(), settings.dpi_normalize)
+    };
+
+    tauri::async_runtime::spawn_blocking(move || capture_all(divisor, &options, normalize_dpi))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Captures the rectangle between two corners in physical virtual desktop
+/// coordinates, e.g. from a drag selection, at full resolution. A selection
+/// spanning displays is composited from each, with gaps between them filled.
+#[tauri::command]
+async fn capture_region_screen(
+    state: State<'_, CaptureState>,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32
+) -> Result<VirtualDesktopFrame, String> {
+    let area = [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)];
+    if area[0] == area[2] || area[1] == area[3] {
+        return Err("Selection has zero area".to_string());
+    }
+    let options = state.settings.read().process_options();
+
+    tauri::async_runtime::spawn_blocking(move || capture_area(Some(area), 1, &options, false))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Reply to `capture_behind_window`.
+#[derive(Serialize)]
+pub struct BehindWindow {
+    /// What the window covers; `x` and `y` are its top-left on the desktop.
+    frame: VirtualDesktopFrame,
+    /// Whether the window had to be hidden for the capture because it isn't
+    /// excluded from capture.
+    hidden: bool,
+}
+
+/// Captures the desktop under our window as if it weren't there, e.g. to
+/// drive a blur-behind effect with real content. Relies on the window being
+/// excluded from capture; where the OS can't exclude it, the window is
+/// briefly hidden instead, which is visible as a flicker.
+#[tauri::command]
+async fn capture_behind_window(window: tauri::Window, state: State<'_, CaptureState>) -> Result<BehindWindow, String> {
+    let hwnd = HWND(window.hwnd().map_err(|e| e.to_string())?.0);
+    let bounds = process::window_bounds(hwnd).ok_or("Window is minimized")?;
+    let area = [bounds.left, bounds.top, bounds.right, bounds.bottom];
+    let options = state.settings.read().process_options();
+
+    // Re-apply protection in case it failed at startup, unless `safe_mode` lifted it on purpose
+    let safe_mode = *state.status.read() == CaptureStatus::SafeMode;
+    let excluded = state.self_excluded.load(Ordering::Acquire)
+        || (!safe_mode && enable_capture_protection(&window).unwrap_or(false));
+    state.self_excluded.store(excluded, Ordering::Release);
+    if !excluded {
+        window.hide().map_err(|e| e.to_string())?;
+        tokio::time::sleep(HIDE_SETTLE_TIME).await;
+    }
+    let frame = tauri::async_runtime::spawn_blocking(move || capture_area(Some(area), 1, &options, false))
+        .await
+        .map_err(|e| e.to_string());
+    if !excluded {
+        window.show().map_err(|e| e.to_string())?;
+    }
+    Ok(BehindWindow { frame: frame??, hidden: !excluded })
+}
+
+/// Reply to `capture_around_cursor`.
+#[derive(Serialize)]
+pub struct Magnified {
+    /// RGBA, `zoom` times the captured square.
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Top-left of the captured square on the virtual desktop.
+    x: i32,
+    y: i32,
+    /// Side of the captured square before zooming; smaller than
+    /// `2 * radius` only on a display too small to hold it.
+    source_width: u32,
+    source_height: u32,
+}
+
+/// Captures a `2 * radius` square centred on the pointer and scales it up
+/// `zoom` times with bilinear filtering, e.g. for a magnifier. Near a display
+/// edge the square shifts to stay on the display rather than shrinking.
+#[tauri::command]
+async fn capture_around_cursor(state: State<'_, CaptureState>, radius: u32, zoom: f32) -> Result<Magnified, String> {
+    validate_magnifier(radius, zoom)?;
+    let pointer = cursor::pointer_position().ok_or("Pointer position unavailable")?;
+    let bounds = displays::monitor_bounds(pointer).ok_or("No display under the pointer")?;
+    let area = magnifier_area(pointer, radius, bounds);
+    let options = state.settings.read().process_options();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let frame = capture_area(Some(area), 1, &options, false)?;
+        let (width, height) = zoomed_size(frame.width, frame.height, zoom);
+        let crop = Rect { x: 0, y: 0, width: frame.width, height: frame.height };
+        let downscale = options.downscale_filter.resolve(1.0 / zoom);
+        let data = resample::resample(&frame.data, frame.width, crop, width, height, Interpolation::Bilinear, downscale);
+        Ok(Magnified {
+            data,
+            width,
+            height,
+            x: area[0],
+            y: area[1],
+            source_width: frame.width,
+            source_height: frame.height,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn validate_magnifier(radius: u32, zoom: f32) -> Result<(), String> {
+    if radius == 0 {
+        return Err("Radius must be non-zero".to_string());
+    }
+    if !(1.0..=MAX_MAGNIFIER_ZOOM).contains(&zoom) {
+        return Err(format!("Zoom must be between 1 and {}", MAX_MAGNIFIER_ZOOM));
+    }
+    Ok(())
+}
+
+/// The `2 * radius` square centred on `pointer`, as left, top, right and
+/// bottom, moved inside `bounds` and shrunk only if `bounds` is smaller.
+fn magnifier_area(pointer: (i32, i32), radius: u32, bounds: RECT) -> [i32; 4] {
+    let side = (radius as i64 * 2).min(i32::MAX as i64) as i32;
+    let place = |center: i32, low: i32, high: i32| {
+        let side = side.min(high - low);
+        let start = center.saturating_sub(side / 2).clamp(low, high - side);
+        (start, start + side)
+    };
+    let (left, right) = place(pointer.0, bounds.left, bounds.right);
+    let (top, bottom) = place(pointer.1, bounds.top, bounds.bottom);
+    [left, top, right, bottom]
+}
+
+fn zoomed_size(width: u32, height: u32, zoom: f32) -> (u32, u32) {
+    let zoom = |len: u32| ((len as f32 * zoom).round() as u32).max(1);
+    (zoom(width), zoom(height))
+}
+
+#[derive(Serialize)]
+pub struct Montage {
+    /// PNG of every display in its desktop position.
+    png: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Top-left of the virtual desktop.
+    x: i32,
+    y: i32,
+}
+
+/// Snapshots every display into a single PNG matching their desktop
+/// layout, at `1/divisor` scale (full resolution by default).
+#[tauri::command]
+async fn capture_all_displays(state: State<'_, CaptureState>, divisor: Option<u32>) -> Result<Montage, String> {
+    let divisor = divisor.unwrap_or(1);
+    if divisor == 0 {
+        return Err("Divisor must be non-zero".to_string());
+    }
+    let (options, normalize_dpi) = {
+        let settings = state.settings.read();
+        (settings.process_options(), settings.dpi_normalize)
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let frame = capture_all(divisor, &options, normalize_dpi)?;
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(&frame.data, frame.width, frame.height, ExtendedColorType::Rgba8)
+            .map_err(|e| e.to_string())?;
+        Ok(Montage { png, width: frame.width, height: frame.height, x: frame.x, y: frame.y })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// One frame from each display, composited by desktop position. Blocking.
+fn capture_all(divisor: u32, options: &ProcessOptions, normalize_dpi: bool) -> Result<VirtualDesktopFrame, String> {
+    capture_area(None, divisor, options, normalize_dpi)
+}
+
+/// A display's part of a virtual desktop capture.
+struct AreaCapture {
+    /// The display on the desktop.
+    bounds: RECT,
+    /// What of it was captured, relative to the display.
+    crop: Rect,
+    /// Upright width of `data`.
+    width: u32,
+    data: Vec<u8>,
+    dpi: u32,
+}
+
+/// Captures `area` of the virtual desktop as left, top, right and bottom, or
+/// the whole of it, compositing the displays it covers as they are laid out.
+/// With `normalize_dpi`, a whole-desktop capture scales each display to the
+/// lowest DPI among them, so content is the same size on every display.
+fn capture_area(
+    area: Option<[i32; 4]>,
+    divisor: u32,
+    options: &ProcessOptions,
+    normalize_dpi: bool
+) -> Result<VirtualDesktopFrame, String> {
+    dpi::ensure_aware();
+    co_init();
+
+    let mut captures = Vec::new();
+    for (adapter, display) in displays::distinct() {
+        let bounds = displays::desktop_bounds(&display)?;
+        let [left, top, right, bottom] = match area {
+            Some([left, top, right, bottom]) => {
+                [left.max(bounds.left), top.max(bounds.top), right.min(bounds.right), bottom.min(bounds.bottom)]
+            }
+            None => [bounds.left, bounds.top, bounds.right, bounds.bottom],
+        };
+        // Only displays the area overlaps are worth duplicating
+        if left >= right || top >= bottom {
+            continue;
+        }
+        let crop = Rect {
+            x: (left - bounds.left) as u32,
+            y: (top - bounds.top) as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        };
+        let rotation = displays::rotation(&display)?;
+        let mut dupl = DesktopDuplicationApi::new(adapter, display)
+            .map_err(|e| format!("Failed to initialize desktop duplication: {:?}", e))?;
+        dupl.configure(DuplicationApiOptions { skip_cursor: true });
+        let tex = acquire_first_frame(&mut dupl)?;
+        let desc = tex.desc();
+        let (device, ctx) = dupl.get_device_and_ctx();
+        let mut frame_data = Vec::new();
+        TextureReader::new(device, ctx)
+            .get_data(&mut frame_data, &tex)
+            .map_err(|e| format!("Failed to get texture data: {:?}", e))?;
+        let (data, width, _) = rotation.upright(frame_data, desc.width, desc.height);
+        let dpi = displays::effective_dpi((bounds.left, bounds.top));
+        captures.push(AreaCapture { bounds, crop, width, data, dpi });
+    }
+    if captures.is_empty() {
+        return Err(match area {
+            Some(_) => "Selection doesn't cover any display".to_string(),
+            None => "No displays to capture".to_string(),
+        });
+    }
+
+    // Where each capture goes, as left, top, width and height before the divisor
+    let normalized_dpi = captures.iter().map(|capture| capture.dpi).min().filter(|_| normalize_dpi && area.is_none());
+    let placements: Vec<[f64; 4]> = match normalized_dpi {
+        Some(reference) => {
+            let scaled: Vec<(RECT, f64)> = captures
+                .iter()
+                .map(|capture| (capture.bounds, reference as f64 / capture.dpi as f64))
+                .collect();
+            displays::scaled_layout(&scaled)
+                .into_iter()
+                .zip(&captures)
+                .zip(&scaled)
+                .map(|(((x, y), capture), (_, scale))| {
+                    [x, y, capture.crop.width as f64 * scale, capture.crop.height as f64 * scale]
+                })
+                .collect()
+        }
+        None => captures
+            .iter()
+            .map(|capture| {
+                let x = capture.bounds.left + capture.crop.x as i32;
+                let y = capture.bounds.top + capture.crop.y as i32;
+                [x as f64, y as f64, capture.crop.width as f64, capture.crop.height as f64]
+            })
+            .collect(),
+    };
+
+    let extent = |pick: fn(&[f64; 4]) -> f64, min: bool| {
+        let values = placements.iter().map(pick);
+        if min { values.fold(f64::MAX, f64::min) } else { values.fold(f64::MIN, f64::max) }
+    };
+    let [left, top, right, bottom] = match area {
+        Some(area) => area.map(|edge| edge as f64),
+        None => [
+            extent(|p| p[0], true),
+            extent(|p| p[1], true),
+            extent(|p| p[0] + p[2], false),
+            extent(|p| p[1] + p[3], false),
+        ],
+    };
+    let (desktop_width, desktop_height) = ((right - left).round() as u32, (bottom - top).round() as u32);
+    let (width, height) = (desktop_width / divisor, desktop_height / divisor);
+    let mut data = options.fill.repeat(frame_len(width, height)? / 4);
+
+    for (capture, [x, y, placed_width, placed_height]) in captures.iter().zip(placements) {
+        let scale = |value: f64| (value / divisor as f64) as u32;
+        let (out_x, out_y) = (scale(x - left), scale(y - top));
+        let (out_w, out_h) = (scale(placed_width), scale(placed_height));
+        if out_w == 0 || out_h == 0 {
+            continue;
+        }
+        let scaled = process_image(&capture.data, capture.width, capture.crop, out_w, out_h, options);
+        for (row, line) in scaled.chunks_exact(out_w as usize * 4).enumerate() {
+            // Rounding can push the last column past the edge; don't wrap into the next row
+            let line = &line[..line.len().min(width.saturating_sub(out_x) as usize * 4)];
+            let start = pixel_offset(out_x, out_y + row as u32, width);
+            if let Some(dst) = data.get_mut(start..start + line.len()) {
+                dst.copy_from_slice(line);
+            }
+        }
+    }
+
+    // Still the desktop's physical top-left, even when laid out at another DPI
+    let (x, y) = match area {
+        Some([x, y, ..]) => (x, y),
+        None => (
+            captures.iter().map(|capture| capture.bounds.left).min().unwrap_or(0),
+            captures.iter().map(|capture| capture.bounds.top).min().unwrap_or(0),
+        ),
+    };
+    Ok(VirtualDesktopFrame { data, width, height, x, y, desktop_width, desktop_height, normalized_dpi })
+}
+
+/// Scales each display to the lowest DPI among them in whole-desktop
+/// captures, so a 4K display at 200% and a 1080p one at 100% show content
+/// at the same size. Selections with `capture_region_screen` aren't affected.
+#[tauri::command]
+fn set_dpi_normalize(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().dpi_normalize = enabled;
+}
+
+/// Lists every display, with indices as used by `capture_scaled`. Mirrored
+/// displays name the display they mirror in `mirror_of`.
+#[tauri::command]
+async fn enumerate_displays() -> Result<Vec<DisplayInfo>, String> {
+    tauri::async_runtime::spawn_blocking(|| {
+        dpi::ensure_aware();
+        co_init();
+        displays::describe_all()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Colour space, bit depth, SDR white level and primaries of the display at
+/// `index`, e.g. to warn that an HDR display won't capture faithfully.
+#[tauri::command]
+async fn get_display_color_info(index: u32) -> Result<ColorInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        dpi::ensure_aware();
+        co_init();
+        displays::color_info(index)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Captures the display with this device name (`\\.\DISPLAY2`) or monitor name,
+/// or for a mirror, the display it mirrors.
+///
+/// Scale, region and output settings are remembered for the display being
+/// left and restored for the one being selected, if it was used before.
+#[tauri::command]
+async fn set_display_by_name(state: State<'_, CaptureState>, name: String) -> Result<DisplayInfo, String> {
+    let settings = state.settings.clone();
+    let profiles = state.display_profiles.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        dpi::ensure_aware();
+        co_init();
+        let (_, display) = displays::find_by_name(&name)?;
+        let index = displays::all()
+            .iter()
+            .position(|(_, other)| other.name() == display.name())
+            .unwrap_or_default();
+        let info = displays::describe_all()?.into_iter().nth(index).ok_or("Display disappeared")?;
+
+        let selected = Some(display.name());
+        let mut settings = settings.write();
+        if settings.display != selected {
+            let mut profiles = profiles.lock();
+            profiles.insert(settings.display.clone(), settings.profile());
+            if let Some(profile) = profiles.get(&selected) {
+                settings.apply_profile(profile.clone());
+            }
+            settings.display = selected;
+        }
+        Ok(info)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Divides the crop by `factor` to get the output size.
+#[tauri::command]
+fn set_scale_factor(state: State<'_, CaptureState>, factor: u32) -> Result<(), String> {
+    validate_scale_factor(factor)?;
+    state.settings.write().scale_factor = factor;
+    Ok(())
+}
+
+fn validate_scale_factor(factor: u32) -> Result<(), String> {
+    if !(1..=64).contains(&factor) {
+        return Err("Scale factor must be between 1 and 64".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn set_alpha_mode(state: State<'_, CaptureState>, mode: AlphaMode) {
+    state.settings.write().alpha_mode = mode;
+}
+
+/// The current cursor bitmap, hotspot and position, or `None` while it is
+/// hidden. The bitmap is only re-read when `version` changes.
+#[tauri::command]
+fn get_cursor_shape(state: State<'_, CaptureState>) -> Option<CursorShape> {
+    state.cursor.lock().shape()
+}
+
+/// Shows the pointer blended at `opacity`, or hides it.
+#[tauri::command]
+fn set_cursor_mode(state: State<'_, CaptureState>, mode: CursorMode) -> Result<(), String> {
+    validate_cursor_mode(mode)?;
+    state.settings.write().cursor = mode;
+    Ok(())
+}
+
+fn validate_cursor_mode(mode: CursorMode) -> Result<(), String> {
+    if let CursorMode::Shown { opacity } = mode {
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err("Cursor opacity must be between 0 and 1".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Sets the RGBA colour of padding and masked pixels; opaque black by default.
+#[tauri::command]
+fn set_fill_color(state: State<'_, CaptureState>, rgba: [u8; 4]) {
+    state.settings.write().fill_color = rgba;
+}
+
+/// Fades the preview's alpha to zero over `pixels` at each edge, for blending
+/// overlays; zero disables it.
+#[tauri::command]
+fn set_edge_fade(state: State<'_, CaptureState>, pixels: u32) {
+    state.settings.write().edge_fade = pixels;
+}
+
+/// Blends each frame with the previous one, weighted by `factor`, wherever
+/// channels change by less than the noise threshold, so static content stays
+/// static. Zero disables it.
+#[tauri::command]
+fn set_temporal_smoothing(state: State<'_, CaptureState>, factor: f32) -> Result<(), String> {
+    validate_smoothing(factor)?;
+    state.settings.write().temporal_smoothing = factor;
+    Ok(())
+}
+
+fn validate_smoothing(factor: f32) -> Result<(), String> {
+    if !(0.0..1.0).contains(&factor) {
+        return Err("Smoothing factor must be at least 0 and below 1".to_string());
+    }
+    Ok(())
+}
+
+/// Shows Sobel edges or changes since the previous frame instead of the
+/// capture; `None` turns it off. `opacity` below 1 blends the visualization
+/// over the capture.
+#[tauri::command]
+fn set_visualization(
+    state: State<'_, CaptureState>,
+    mode: Option<Visualization>,
+    opacity: Option<f32>
+) -> Result<(), String> {
+    let opacity = opacity.unwrap_or(1.0);
+    validate_opacity(opacity)?;
+    let mut settings = state.settings.write();
+    settings.visualization = mode;
+    settings.visualization_opacity = opacity;
+    Ok(())
+}
+
+fn validate_opacity(opacity: f32) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err("Opacity must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Burns fps, resolution, dropped frames and latency into the top-left
+/// corner of every frame, so screenshots of the preview carry them.
+#[tauri::command]
+fn set_debug_overlay(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().debug_overlay = enabled;
+}
+
+/// Fills these rectangles, in pixels relative to the captured display, with
+/// the fill colour in every output; an empty list removes them.
+#[tauri::command]
+fn set_exclusion_rects(state: State<'_, CaptureState>, rects: Vec<Rect>) {
+    state.settings.write().exclusion_rects = rects;
+}
+
+/// Fills the taskbar and any other area the display reserves outside its
+/// work area.
+#[tauri::command]
+fn set_exclude_taskbar(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().exclude_taskbar = enabled;
+}
+
+/// Keeps only the pixels of window `hwnd` in every output, at its real
+/// position, making the rest transparent so it can be composited onto other
+/// backgrounds. Everything is transparent while it is minimized or off the
+/// display. `None` turns it off.
+#[tauri::command]
+fn set_mask_to_window(state: State<'_, CaptureState>, hwnd: Option<usize>) -> Result<(), String> {
+    if let Some(hwnd) = hwnd {
+        if !unsafe { IsWindow(Some(HWND(hwnd as *mut _))) }.as_bool() {
+            return Err(format!("No window with handle {:#x}", hwnd));
+        }
+    }
+    state.settings.write().mask_to_window = hwnd;
+    Ok(())
+}
+
+/// Takes the byte order of captured pixels from the duplicated texture's
+/// format, for drivers that hand over RGBA where BGRA is assumed and come
+/// out blue-tinted. The detected order is in `get_capture_info`.
+#[tauri::command]
+fn set_autodetect_channels(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().autodetect_channels = enabled;
+}
+
+/// Blends a PNG or WebP logo into `corner` of every output frame at
+/// `opacity`, respecting its own alpha; it is scaled down to at most a
+/// quarter of the frame. `None` removes it.
+#[tauri::command]
+async fn set_watermark(
+    state: State<'_, CaptureState>,
+    image: Option<Vec<u8>>,
+    corner: Corner,
+    opacity: f32
+) -> Result<(), String> {
+    let decoded = match image {
+        Some(bytes) => Some(
+            tauri::async_runtime::spawn_blocking(move || Watermark::decode(&bytes, corner, opacity))
+                .await
+                .map_err(|e| e.to_string())??,
+        ),
+        None => None,
+    };
+    *state.watermark.lock() = decoded;
+    Ok(())
+}
+
+/// Outputs only `channel` as grayscale; `None` restores full colour.
+#[tauri::command]
+fn set_channel_extract(state: State<'_, CaptureState>, channel: Option<Channel>) {
+    state.settings.write().channel = channel;
+}
+
+/// Restricts capture to a window-local region, anchored for the current window size.
+#[tauri::command]
+fn set_region(
+    window: tauri::Window,
+    state: State<'_, CaptureState>,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32
+) -> Result<(), String> {
+    validate_region(Rect { x, y, width, height })?;
+    let window_size = window.outer_size().map_err(|e| e.to_string())?;
+
+    state.settings.write().region = Some(CaptureRegion {
+        rect: Rect { x, y, width, height },
+        reference_width: window_size.width,
+        reference_height: window_size.height,
+    });
+    Ok(())
+}
+
+/// Crops to this display-relative rectangle from the next frame on, ignoring
+/// the window. Cheap enough to call on every pointer move.
+#[tauri::command]
+fn set_dynamic_region(state: State<'_, CaptureState>, x: u32, y: u32, width: u32, height: u32) -> Result<(), String> {
+    let rect = Rect { x, y, width, height };
+    validate_region(rect)?;
+    state.dynamic_region.store(Some(rect))
+}
+
+fn validate_region(rect: Rect) -> Result<(), String> {
+    if rect.width == 0 || rect.height == 0 {
+        return Err("Region must have a non-zero size".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn clear_dynamic_region(state: State<'_, CaptureState>) {
+    let _ = state.dynamic_region.store(None);
+}
+
+#[tauri::command]
+fn clear_region(state: State<'_, CaptureState>) {
+    state.settings.write().region = None;
+}
+
+/// Serves a `width`×`height` frame of `rgba` from `get_frame_data` until
+/// capture produces its first frame.
+#[tauri::command]
+fn set_placeholder(state: State<'_, CaptureState>, width: u32, height: u32, rgba: [u8; 4]) -> Result<(), String> {
+    validate_size(width, height, "Placeholder")?;
+    state.settings.write().placeholder = Some((width, height, rgba));
+    Ok(())
+}
+
+#[tauri::command]
+fn clear_placeholder(state: State<'_, CaptureState>) {
+    state.settings.write().placeholder = None;
+}
+
+#[tauri::command]
+fn set_source(state: State<'_, CaptureState>, source: FrameSource) -> Result<(), String> {
+    validate_source(source)?;
+    state.settings.write().source = source;
+    Ok(())
+}
+
+fn validate_source(source: FrameSource) -> Result<(), String> {
+    match source {
+        FrameSource::Mock(mock) => mock.validate(),
+        FrameSource::CursorMagnifier { radius, zoom } => validate_magnifier(radius, zoom),
+        _ => Ok(()),
+    }
+}
+
+/// Processes each frame's pixels across all cores. Worth it for large
+/// crops; for small outputs the thread overhead outweighs the gain.
+#[tauri::command]
+fn set_parallel_processing(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().parallel_processing = enabled;
+}
+
+/// Turns output frames to suit a viewer held in `orientation`, whatever the
+/// source's shape; `None` restores the source orientation. `get_frame_data`
+/// reports the turned dimensions.
+#[tauri::command]
+fn set_viewer_orientation(state: State<'_, CaptureState>, orientation: Option<ViewerOrientation>) {
+    state.settings.write().viewer_orientation = orientation;
+}
+
+/// Turns output frames clockwise by `degrees`, resampled bilinearly onto a
+/// canvas grown to hold the turned crop, with the corners in the fill
+/// colour; zero turns it off. Returns the size frames will have at this
+/// angle, once a frame has been captured.
+#[tauri::command]
+fn set_rotation_angle(state: State<'_, CaptureState>, degrees: f32) -> Result<Option<FrameSize>, String> {
+    if !degrees.is_finite() {
+        return Err("Angle must be a finite number of degrees".to_string());
+    }
+    let degrees = degrees.rem_euclid(360.0);
+    state.settings.write().rotation_angle = degrees;
+    let (width, height) = state.frame_buffer.read().unrotated;
+    Ok((width > 0 && height > 0).then(|| {
+        let (width, height) = rotation::rotated_size(width, height, degrees);
+        FrameSize { width, height }
+    }))
+}
+
+/// Over-crops each edge by `pixels` while the window is being moved, hiding
+/// desktop that shows through when its reported position lags; zero disables.
+#[tauri::command]
+fn set_drag_compensation(state: State<'_, CaptureState>, pixels: u32) {
+    state.settings.write().drag_compensation = pixels;
+}
+
+/// Crops out uniform black bars around letterboxed or pillarboxed content,
+/// treating pixels with no channel above `threshold` as black.
+#[tauri::command]
+fn set_autocrop_bars(state: State<'_, CaptureState>, enabled: bool, threshold: u8) {
+    state.settings.write().autocrop_bars = enabled.then_some(threshold);
+}
+
+#[tauri::command]
+fn set_region_anchor(state: State<'_, CaptureState>, anchor: RegionAnchor) {
+    state.settings.write().region_anchor = anchor;
+}
+
+/// Produces frames of exactly `width`×`height`, regardless of the crop size.
+#[tauri::command]
+fn set_output_size(state: State<'_, CaptureState>, width: u32, height: u32) -> Result<(), String> {
+    validate_size(width, height, "Output")?;
+    state.settings.write().output_size = Some((width, height));
+    Ok(())
+}
+
+#[tauri::command]
+fn clear_output_size(state: State<'_, CaptureState>) {
+    state.settings.write().output_size = None;
+}
+
+/// Fixes frames at `width`×`height` for consumers that can't follow size
+/// changes: the crop is fitted inside, centred on the fill colour, and the
+/// memory policy and viewer orientation no longer change the size.
+#[tauri::command]
+fn lock_output_size(state: State<'_, CaptureState>, width: u32, height: u32) -> Result<(), String> {
+    validate_size(width, height, "Output")?;
+    state.settings.write().locked_size = Some((width, height));
+    Ok(())
+}
+
+#[tauri::command]
+fn unlock_output_size(state: State<'_, CaptureState>) {
+    state.settings.write().locked_size = None;
+}
+
+/// Also produces a `width`×`height` thumbnail of every frame, fetched with `get_thumbnail_data`.
+#[tauri::command]
+fn set_thumbnail_size(state: State<'_, CaptureState>, width: u32, height: u32) -> Result<(), String> {
+    validate_size(width, height, "Thumbnail")?;
+    state.settings.write().thumbnail_size = Some((width, height));
+    Ok(())
+}
+
+/// Checks a frame size is non-zero and fits in memory; `what` names it in the error.
+fn validate_size(width: u32, height: u32, what: &str) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("{} size must be non-zero", what));
+    }
+    frame_len(width, height).map(|_| ())
+}
+
+#[tauri::command]
+fn disable_thumbnail(state: State<'_, CaptureState>) {
+    state.settings.write().thumbnail_size = None;
+    *state.thumbnail.write() = FrameBuffer::default();
+}
+
+#[tauri::command]
+fn set_upscale_filter(state: State<'_, CaptureState>, filter: Interpolation) {
+    state.settings.write().upscale_filter = filter;
+}
+
+#[tauri::command]
+fn set_downscale_filter(state: State<'_, CaptureState>, filter: Downscale) {
+    state.settings.write().downscale_filter = filter;
+}
+
+/// Chooses the process DPI awareness; only possible before it's been established.
+#[tauri::command]
+fn set_dpi_awareness(mode: DpiAwareness) -> Result<(), String> {
+    dpi::set(mode)
+}
+
+#[tauri::command]
+fn get_capture_status(state: State<'_, CaptureState>) -> CaptureStatus {
+    *state.status.read()
+}
+
+#[tauri::command]
+fn get_dpi_awareness() -> Option<DpiAwareness> {
+    dpi::current()
+}
+
+/// Caps the bytes held by the live frame, thumbnail and replay buffer,
+/// resolving overruns with `policy`; zero removes the cap.
+#[tauri::command]
+fn set_frame_memory_budget(state: State<'_, CaptureState>, bytes: usize, policy: MemoryPolicy) {
+    let mut settings = state.settings.write();
+    settings.frame_memory_budget = (bytes > 0).then_some(bytes);
+    settings.memory_policy = policy;
+}
+
+/// Keeps the last `seconds` of output frames in memory; zero disables replay.
+#[tauri::command]
+fn set_replay_seconds(state: State<'_, CaptureState>, seconds: u32) {
+    state.replay.lock().set_window(Duration::from_secs(seconds as u64));
+}
+
+#[tauri::command]
+fn set_replay_budget(state: State<'_, CaptureState>, bytes: usize) {
+    state.replay.lock().set_budget(bytes);
+}
+
+/// Saves the replay buffer as a PNG sequence in `path`, returning the frame count.
+#[tauri::command]
+async fn save_replay(state: State<'_, CaptureState>, path: String) -> Result<usize, String> {
+    let frames = state.replay.lock().snapshot();
+    tauri::async_runtime::spawn_blocking(move || replay::save(&frames, std::path::Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Enables tile delta events, with a full keyframe every `keyframe_interval` patches.
+#[tauri::command]
+fn set_delta_mode(state: State<'_, CaptureState>, enabled: bool, keyframe_interval: u32) {
+    state.settings.write().delta = enabled.then_some(DeltaConfig { keyframe_interval });
+}
+
+/// Makes the next `frame-delta` a full keyframe, e.g. after a consumer reconnects.
+#[tauri::command]
+fn request_keyframe(state: State<'_, CaptureState>) {
+    state.keyframe_requested.store(true, Ordering::Release);
+}
+
+/// Uses `buffers` staging textures for GPU readback, trading latency for throughput.
+#[tauri::command]
+fn set_readback_buffers(state: State<'_, CaptureState>, buffers: usize) -> Result<(), String> {
+    validate_readback_buffers(buffers)?;
+    state.settings.write().readback_buffers = buffers;
+    Ok(())
+}
+
+fn validate_readback_buffers(buffers: usize) -> Result<(), String> {
+    if !(1..=4).contains(&buffers) {
+        return Err("Readback buffers must be between 1 and 4".to_string());
+    }
+    Ok(())
+}
+
+/// Copies only the crop off the GPU instead of the whole display. Applies
+/// with a single readback buffer on an unrotated display; otherwise, and
+/// while a raw frame is requested, the whole display is still read.
+#[tauri::command]
+fn set_crop_readback(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().crop_readback = enabled;
+}
+
+#[tauri::command]
+fn set_pacing(state: State<'_, CaptureState>, pacing: Pacing) -> Result<(), String> {
+    validate_pacing(pacing)?;
+    state.settings.write().pacing = pacing;
+    Ok(())
+}
+
+fn validate_pacing(pacing: Pacing) -> Result<(), String> {
+    if pacing == Pacing::FixedFps(0) {
+        return Err("Frame rate must be non-zero".to_string());
+    }
+    Ok(())
+}
+
+/// Sets the OS priority of the capture thread and optionally pins it to
+/// `cores`; `None` lets it run on any core.
+#[tauri::command]
+fn set_capture_thread_priority(
+    state: State<'_, CaptureState>,
+    priority: ThreadPriority,
+    cores: Option<Vec<u32>>,
+) -> Result<(), String> {
+    let affinity = cores.map(|cores| thread::affinity_mask(&cores)).transpose()?;
+    state.settings.write().capture_thread = ThreadTuning { priority, affinity };
+    Ok(())
+}
+
+/// Sets the GPU scheduling priority of the capture device; `Low` lets a
+/// game on the same adapter keep its frame rate. `get_capture_info` reports
+/// the priority actually in effect.
+#[tauri::command]
+fn set_gpu_capture_priority(state: State<'_, CaptureState>, priority: GpuPriority) {
+    state.settings.write().gpu_priority = priority;
+}
+
+/// Lets the capture loop raise the scale factor up to `max_scale` while
+/// frames take longer to produce than the pacing allows, and lower it back
+/// towards `min_scale` once there is headroom. Each change is announced
+/// with `quality-adapted`. Overrides `set_scale_factor` until cleared.
+#[tauri::command]
+fn set_adaptive_quality(state: State<'_, CaptureState>, min_scale: u32, max_scale: u32) -> Result<(), String> {
+    let bounds = AdaptiveBounds { min_scale, max_scale };
+    bounds.validate()?;
+    state.settings.write().adaptive_quality = Some(bounds);
+    Ok(())
+}
+
+#[tauri::command]
+fn clear_adaptive_quality(state: State<'_, CaptureState>) {
+    state.settings.write().adaptive_quality = None;
+}
+
+/// Has the GPU convert frames to RGBA as it copies them for readback,
+/// sparing the CPU the channel swap. Only applies with
+/// `AlphaMode::ForceOpaque`, as the conversion drops alpha; frames are
+/// converted on the CPU as before where the GPU can't do it.
+#[tauri::command]
+fn set_gpu_format_convert(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().gpu_format_convert = enabled;
+}
+
+fn profiles_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    Ok(app.path().app_config_dir().map_err(|e| e.to_string())?.join("profiles"))
+}
+
+/// Saves every current capture setting under `name`, replacing any profile
+/// of that name, along with the replay window and budget, the
+/// `capture-error` interval and the stream target bitrate. The display and
+/// `set_mask_to_window` handle are left out, and a `ProcessWindow` source is
+/// saved as the default one, as they only identify something for as long
+/// as it exists.
+#[tauri::command]
+fn save_profile(app: tauri::AppHandle, state: State<'_, CaptureState>, name: String) -> Result<(), String> {
+    let (replay_window, replay_budget) = {
+        let replay = state.replay.lock();
+        (replay.window(), replay.budget())
+    };
+    let profile = Profile {
+        settings: state.settings.read().clone(),
+        replay_seconds: replay_window.as_secs() as u32,
+        replay_budget,
+        error_interval_ms: state.errors.lock().interval().as_millis() as u64,
+        stream_bitrate_kbps: state.stream_quality.lock().target_kbps(),
+    };
+    profiles::save(&profiles_dir(&app)?, &name, profile)
+}
+
+/// Replaces all capture settings, and the state saved alongside them, with
+/// the profile `name` in one step. A profile failing any setter's checks is
+/// rejected without applying any of it. The display and masked window are
+/// kept.
+#[tauri::command]
+fn load_profile(app: tauri::AppHandle, state: State<'_, CaptureState>, name: String) -> Result<(), String> {
+    let profile = profiles::load(&profiles_dir(&app)?, &name)?;
+    {
+        let mut settings = state.settings.write();
+        let mut loaded = profile.settings;
+        loaded.display = settings.display.take();
+        loaded.mask_to_window = settings.mask_to_window;
+        *settings = loaded;
+    }
+    {
+        let mut replay = state.replay.lock();
+        replay.set_budget(profile.replay_budget);
+        replay.set_window(Duration::from_secs(profile.replay_seconds as u64));
+    }
+    state.errors.lock().set_interval(Duration::from_millis(profile.error_interval_ms));
+    state.stream_quality.lock().set_target(profile.stream_bitrate_kbps);
+    Ok(())
+}
+
+#[tauri::command]
+fn list_profiles(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    profiles::list(&profiles_dir(&app)?)
+}
+
+#[tauri::command]
+fn get_capture_info(state: State<'_, CaptureState>) -> CaptureInfo {
+    state.info.read().clone()
+}
+
+/// A rectangle whose origin can be negative, as left of or above the
+/// primary display on the virtual desktop.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SignedRect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+/// The current frame's crop in each coordinate space involved.
+#[derive(Clone, Debug, Serialize)]
+pub struct CaptureGeometry {
+    /// Relative to the captured window: ours, or a process's window.
+    window: SignedRect,
+    /// On the virtual desktop.
+    desktop: SignedRect,
+    /// Relative to the captured display, as its upright texture is indexed.
+    /// `None` when the frame came from `PrintWindow` rather than the display.
+    display: Option<Rect>,
+    display_origin: (i32, i32),
+    rotation: Rotation,
+    /// DPI scale of our window.
+    scale_factor: f64,
+}
+
+/// The latest frame's crop in window, virtual desktop and display
+/// coordinates, with the display origin and DPI scale used. Window
+/// coordinates use where the window is now.
+#[tauri::command]
+fn get_capture_geometry(window: tauri::Window, state: State<'_, CaptureState>) -> Result<CaptureGeometry, String> {
+    let meta = state.frame_buffer.read().meta.clone();
+    if meta.sequence == 0 {
+        return Err("No frame captured yet".to_string());
+    }
+    let info = state.info.read().clone();
+    let window_pos = match state.settings.read().source {
+        FrameSource::ProcessWindow { pid } => {
+            process::window_geometry(pid).ok_or_else(|| format!("No visible window for process {}", pid))?.0
+        }
+        _ => window.outer_position().map_err(|e| e.to_string())?,
+    };
+
+    let Rect { x, y, width, height } = meta.crop;
+    let desktop = SignedRect {
+        x: meta.display_origin.0 + x as i32,
+        y: meta.display_origin.1 + y as i32,
+        width,
+        height,
+    };
+    // Frames rendered by the window itself are relative to it, not the display
+    let display = (meta.display_origin == info.origin).then_some(meta.crop);
+    Ok(CaptureGeometry {
+        window: SignedRect { x: desktop.x - window_pos.x, y: desktop.y - window_pos.y, width, height },
+        desktop,
+        display,
+        display_origin: info.origin,
+        rotation: info.rotation,
+        scale_factor: window.scale_factor().map_err(|e| e.to_string())?,
+    })
+}
+
+/// Everything useful in a bug report, as returned by `export_diagnostics`.
+#[derive(Serialize)]
+struct Diagnostics {
+    version: &'static str,
+    status: CaptureStatus,
+    /// Backend frames are taken from.
+    source: FrameSource,
+    info: CaptureInfo,
+    settings: CaptureSettings,
+    stats: CaptureStats,
+    capabilities: Capabilities,
+    /// Occurrences of each error kind since startup.
+    error_counts: HashMap<ErrorKind, u64>,
+    last_error: Option<LastError>,
+}
+
+/// Settings, status, recent stats and errors, and system capabilities as one
+/// pretty-printed JSON document, for pasting into bug reports.
+#[tauri::command]
+async fn export_diagnostics(state: State<'_, CaptureState>) -> Result<String, String> {
+    let capabilities = get_capabilities().await?;
+    let settings = state.settings.read().clone();
+    let (error_counts, last_error) = {
+        let errors = state.errors.lock();
+        (errors.totals().clone(), errors.last())
+    };
+    let diagnostics = Diagnostics {
+        version: env!("CARGO_PKG_VERSION"),
+        status: *state.status.read(),
+        source: settings.source,
+        info: state.info.read().clone(),
+        stats: state.frame_buffer.read().stats.clone(),
+        settings,
+        capabilities,
+        error_counts,
+        last_error,
+    };
+    serde_json::to_string_pretty(&diagnostics).map_err(|e| e.to_string())
+}
+
+/// Adds a CRC32 of each frame to its metadata; off by default for speed.
+#[tauri::command]
+fn set_checksum(state: State<'_, CaptureState>, enabled: bool) {
+    state.settings.write().checksum = enabled;
+}
+
+#[tauri::command]
+fn set_sync_mode(state: State<'_, CaptureState>, mode: SyncMode) {
+    state.settings.write().sync_mode = mode;
+}
+
+/// Limits successful `get_frame_data` calls to one per `ms` milliseconds so a
+/// tight polling loop can't starve the capture loop of the write lock.
+#[tauri::command]
+fn set_min_fetch_interval(state: State<'_, CaptureState>, ms: u64) {
+    state.settings.write().min_fetch_interval = Duration::from_millis(ms);
+}
+
+/// Streams a `Meta` message for every frame through `channel` instead of the
+/// event bus, followed with `with_data` by a `Data` message of RGBA bytes.
+/// With `skip_unchanged` (the default) pixels identical to the last ones
+/// sent are left out, so a static screen costs only metadata. Replaces any
+/// previous channel.
+#[tauri::command]
+fn start_frame_channel(
+    state: State<'_, CaptureState>,
+    channel: ipc::Channel<FrameMessage>,
+    with_data: Option<bool>,
+    skip_unchanged: Option<bool>,
+) {
+    let with_data = with_data.unwrap_or(false);
+    let skip_unchanged = skip_unchanged.unwrap_or(true);
+    let format = match (with_data, skip_unchanged) {
+        (true, true) => "Metadata, RGBA when changed",
+        (true, false) => "RGBA with metadata",
+        (false, _) => "Metadata",
+    };
+    state.consumers.lock().register(ConsumerId::FrameChannel, format.to_string());
+    *state.frame_channel.lock() = Some(FrameChannel { channel, with_data, skip_unchanged, last_data: None });
+}
+
+#[tauri::command]
+fn stop_frame_channel(state: State<'_, CaptureState>) {
+    state.frame_channel.lock().take();
+    state.consumers.lock().unregister(ConsumerId::FrameChannel);
+}
+
+/// Starts encoding output frames into an MP4 at `path`, sized to the current
+/// frame. Frames are timed by when they were captured, so a varying capture
+/// rate plays back at its real pace.
+#[tauri::command]
+async fn start_video_recording(
+    state: State<'_, CaptureState>,
+    path: PathBuf,
+    codec: VideoCodec,
+    bitrate_kbps: u32
+) -> Result<(), String> {
+    if bitrate_kbps == 0 {
+        return Err("Bitrate must be greater than zero".to_string());
+    }
+    if state.video.lock().is_some() {
+        return Err("Already recording".to_string());
+    }
+    let (width, height) = {
+        let buffer = state.frame_buffer.read();
+        (buffer.width, buffer.height)
+    };
+    if width == 0 || height == 0 {
+        return Err("No frame captured yet".to_string());
+    }
+    let fill = state.settings.read().fill_color;
+    let recording = tauri::async_runtime::spawn_blocking(move || {
+        VideoRecording::start(path, codec, bitrate_kbps, width, height, fill)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    *state.video.lock() = Some(recording);
+    let format = format!("{:?} MP4, {}x{} at {} kbps", codec, width & !1, height & !1, bitrate_kbps);
+    state.consumers.lock().register(ConsumerId::Recording, format);
+    Ok(())
+}
+
+/// Finishes the recording, returning what was written.
+#[tauri::command]
+async fn stop_video_recording(state: State<'_, CaptureState>) -> Result<RecordingSummary, String> {
+    let recording = state.video.lock().take().ok_or("Not recording")?;
+    state.consumers.lock().unregister(ConsumerId::Recording);
+    tauri::async_runtime::spawn_blocking(move || recording.stop())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Opens a stream of frames through `channel` in the format `config` asks
+/// for, alongside any other streams. Replies with the stream's id and the
+/// config it was granted.
+#[tauri::command]
+fn open_stream(state: State<'_, CaptureState>, channel: ipc::Channel<StreamFrame>, config: StreamConfig) -> StreamHandshake {
+    state.streams.lock().open(channel, config, &state.consumers)
+}
+
+#[tauri::command]
+fn close_stream(state: State<'_, CaptureState>, id: u32) -> Result<(), String> {
+    state.streams.lock().close(id, &state.consumers)
+}
+
+/// Everything currently receiving frames, with its format, delivery rate
+/// over the last second and frames dropped, to find which one is holding
+/// things up. Polling and `frame-ready` listeners show while frames are
+/// going to them.
+#[tauri::command]
+fn list_consumers(state: State<'_, CaptureState>) -> Vec<ConsumerInfo> {
+    state.consumers.lock().list()
+}
+
+/// Limits `capture-error` to one event per error kind per `ms` milliseconds;
+/// suppressed occurrences are counted in the next event's `count_since_last`.
+#[tauri::command]
+fn set_error_interval(state: State<'_, CaptureState>, ms: u64) {
+    state.errors.lock().set_interval(Duration::from_millis(ms));
+}
+
+/// Limits `frame-ready` to at most one event per `ms` milliseconds.
+#[tauri::command]
+fn set_emit_interval(state: State<'_, CaptureState>, ms: u64) {
+    state.settings.write().emit_interval = Duration::from_millis(ms);
+}
+
+
+/// Sets the most verbose level that is logged; `Off` silences logging.
+#[tauri::command]
+fn set_log_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .manage(CaptureState::new())
+        // Everything reaches the plugin; `set_log_level` does the filtering
+        .plugin(tauri_plugin_log::Builder::new().level(LevelFilter::Trace).build())
+        .setup(|app| {
+            log::set_max_level(DEFAULT_LOG_LEVEL);
+            let capture_state = app.state::<CaptureState>();
+
+            if let Some(window) = app.get_window("main") {
+                match configure_window(&window) {
+                    Ok(excluded) => {
+                        if !excluded {
+                            warn!("Window isn't excluded from capture, masking it out of frames");
+                        }
+                        capture_state.self_excluded.store(excluded, Ordering::Release);
+                    }
+                    Err(e) => error!("Failed to configure window: {}", e),
+                }
+
+                spawn_capture(window, capture_state.inner().clone());
             }
             Ok(())
         })
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![get_frame_data])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(tauri::generate_handler![
+            get_frame_data,
+            next_frame,
+            warm_up,
+            set_output_alignment,
+            try_get_frame_data,
+            set_placeholder,
+            clear_placeholder,
+            get_thumbnail_data,
+            get_fresh_frame,
+            get_interpolated_frame,
+            set_frame_interpolation,
+            get_frame_webp,
+            get_frame_indexed,
+            get_frame_tensor,
+            set_output_palette,
+            request_hires_frame,
+            get_frame_for_ocr,
+            get_raw_texture_frame,
+            grab_now,
+            arm_trigger,
+            fire_trigger,
+            flush,
+            capture_burst,
+            stop_capture,
+            safe_mode,
+            set_baseline,
+            capture_diff,
+            get_frame_delta,
+            get_capture_status,
+            verify_protection,
+            get_capabilities,
+            capture_scaled,
+            capture_virtual_desktop,
+            capture_region_screen,
+            capture_behind_window,
+            capture_around_cursor,
+            capture_all_displays,
+            set_dpi_normalize,
+            enumerate_displays,
+            get_display_color_info,
+            set_display_by_name,
+            set_scale_factor,
+            set_alpha_mode,
+            set_channel_extract,
+            set_exclusion_rects,
+            set_exclude_taskbar,
+            set_mask_to_window,
+            set_autodetect_channels,
+            set_watermark,
+            set_cursor_mode,
+            get_cursor_shape,
+            set_fill_color,
+            set_edge_fade,
+            set_temporal_smoothing,
+            set_visualization,
+            set_debug_overlay,
+            set_region,
+            clear_region,
+            set_dynamic_region,
+            clear_dynamic_region,
+            set_region_anchor,
+            set_autocrop_bars,
+            set_drag_compensation,
+            set_source,
+            set_viewer_orientation,
+            set_rotation_angle,
+            set_parallel_processing,
+            set_output_size,
+            clear_output_size,
+            lock_output_size,
+            unlock_output_size,
+            set_thumbnail_size,
+            disable_thumbnail,
+            set_upscale_filter,
+            set_downscale_filter,
+            set_checksum,
+            set_sync_mode,
+            set_pacing,
+            set_capture_thread_priority,
+            set_gpu_capture_priority,
+            set_gpu_format_convert,
+            set_adaptive_quality,
+            clear_adaptive_quality,
+            get_capture_info,
+            get_capture_geometry,
+            export_diagnostics,
+            measure_latency,
+            save_profile,
+            load_profile,
+            list_profiles,
+            set_emit_interval,
+            set_min_fetch_interval,
+            set_error_interval,
+            set_stream_bitrate,
+            start_frame_channel,
+            stop_frame_channel,
+            open_stream,
+            start_video_recording,
+            stop_video_recording,
+            close_stream,
+            list_consumers,
+            set_readback_buffers,
+            set_crop_readback,
+            set_delta_mode,
+            request_keyframe,
+            set_dpi_awareness,
+            get_dpi_awareness,
+            set_replay_seconds,
+            set_replay_budget,
+            set_frame_memory_budget,
+            save_replay,
+            set_log_level
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                let state = app.state::<CaptureState>();
+                if let Err(e) = tauri::async_runtime::block_on(state.shutdown()) {
+                    error!("Capture shutdown error: {}", e);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_len_counts_bytes_past_32_bits() {
+        assert_eq!(frame_len(3 * 3840, 2160), Ok(3 * 3840 * 2160 * 4));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(frame_len(65536, 32768), Ok(8 << 30));
+        #[cfg(target_pointer_width = "32")]
+        assert!(frame_len(65536, 16384).is_err());
+    }
+
+    #[test]
+    fn frame_len_rejects_overflow_instead_of_wrapping() {
+        assert_eq!(
+            frame_len(u32::MAX, u32::MAX),
+            Err(format!("A {}x{} frame is too large to address", u32::MAX, u32::MAX))
+        );
+    }
+
+    /// An opaque BGRA image whose red channel is `reds`, one per pixel.
+    fn bgra_reds(reds: &[u8]) -> Vec<u8> {
+        reds.iter().flat_map(|&red| [0, 0, red, 255]).collect()
+    }
+
+    fn reds(rgba: &[u8]) -> Vec<u8> {
+        rgba.chunks_exact(4).map(|pixel| pixel[0]).collect()
+    }
+
+    /// A `width`×`height` BGRA frame with no two neighbouring bytes alike.
+    fn noise_frame(width: u32, height: u32) -> Vec<u8> {
+        (0..frame_len(width, height).unwrap()).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn nearest(parallel: bool) -> ProcessOptions {
+        ProcessOptions { downscale_filter: Downscale::Nearest, alpha_mode: AlphaMode::Premultiplied, parallel, ..Default::default() }
+    }
+
+    #[test]
+    fn parallel_processing_matches_serial() {
+        let (width, height) = (3840, 2160);
+        let frame = noise_frame(width, height);
+        // Full size, scaled down, and running off the bottom-right edge
+        let cases = [
+            (Rect { x: 0, y: 0, width, height }, width, height),
+            (Rect { x: 0, y: 0, width, height }, width / 3, height / 3),
+            (Rect { x: 3000, y: 1500, width: 1280, height: 720 }, 1280, 720),
+        ];
+        for (crop, out_width, out_height) in cases {
+            let serial = process_image(&frame, width, crop, out_width, out_height, &nearest(false));
+            let parallel = process_image(&frame, width, crop, out_width, out_height, &nearest(true));
+            assert_eq!(serial.len(), frame_len(out_width, out_height).unwrap());
+            assert!(serial == parallel, "outputs differ for {:?} at {}x{}", crop, out_width, out_height);
+        }
+    }
+
+    /// Times a 4K frame processed on one thread and across all of them; run
+    /// with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn parallel_processing_timing_4k() {
+        let (width, height) = (3840, 2160);
+        let frame = noise_frame(width, height);
+        let crop = Rect { x: 0, y: 0, width, height };
+        for parallel in [false, true] {
+            let options = nearest(parallel);
+            let started = Instant::now();
+            for _ in 0..20 {
+                std::hint::black_box(process_image(&frame, width, crop, width, height, &options));
+            }
+            println!(
+                "{}: {:.2} ms per frame",
+                if parallel { "rayon" } else { "single" },
+                started.elapsed().as_secs_f64() * 1000.0 / 20.0
+            );
+        }
+    }
+
+    /// Which pixels of `data` are all zero, row by row.
+    fn zeroed(data: &[u8], width: u32) -> Vec<Vec<bool>> {
+        data.chunks_exact(width as usize * 4)
+            .map(|row| row.chunks_exact(4).map(|pixel| pixel == [0; 4]).collect())
+            .collect()
+    }
+
+    #[test]
+    fn masks_own_window_out_of_crop() {
+        // An 8x4 crop of a display at (1920, 0), window fully inside the crop
+        let crop = Rect { x: 2, y: 1, width: 8, height: 4 };
+        let mut data = vec![200; frame_len(8, 4).unwrap()];
+        let window = on_display((1920 + 4, 2), (3, 2), (1920, 0));
+        assert_eq!(window, Rect { x: 4, y: 2, width: 3, height: 2 });
+        mask_regions(&mut data, 8, 4, crop, &[window], [0; 4]);
+
+        let expected: Vec<Vec<bool>> = (0..4)
+            .map(|y| (0..8).map(|x| (1..3).contains(&y) && (2..5).contains(&x)).collect())
+            .collect();
+        assert_eq!(zeroed(&data, 8), expected);
+    }
+
+    #[test]
+    fn masks_only_onscreen_part_of_partially_offscreen_window() {
+        // Hanging 3 pixels off the left of the display and past the crop's bottom
+        let crop = Rect { x: 2, y: 1, width: 8, height: 4 };
+        let mut data = vec![200; frame_len(8, 4).unwrap()];
+        let window = on_display((-3, 2), (6, 10), (0, 0));
+        assert_eq!(window, Rect { x: 0, y: 2, width: 3, height: 10 });
+        mask_regions(&mut data, 8, 4, crop, &[window], [0; 4]);
+
+        let expected: Vec<Vec<bool>> = (0..4).map(|y| (0..8).map(|x| y >= 1 && x == 0).collect()).collect();
+        assert_eq!(zeroed(&data, 8), expected);
+    }
+
+    #[test]
+    fn masks_nothing_for_window_entirely_off_display() {
+        assert_eq!(on_display((-500, 100), (400, 300), (0, 0)).width, 0);
+        let crop = Rect { x: 0, y: 0, width: 4, height: 4 };
+        let mut data = vec![200; frame_len(4, 4).unwrap()];
+        mask_regions(&mut data, 4, 4, crop, &[on_display((-500, 100), (400, 300), (0, 0))], [0; 4]);
+        assert!(data.iter().all(|&byte| byte == 200));
+    }
+
+    #[test]
+    fn process_image_upscales_filtered_to_rgba() {
+        let source = bgra_reds(&[0, 100]);
+        let crop = Rect { x: 0, y: 0, width: 2, height: 1 };
+        let output = process_image(&source, 2, crop, 4, 1, &ProcessOptions::default());
+        assert_eq!(output.len(), 4 * 4);
+        assert_eq!(reds(&output), [0, 25, 75, 100]);
+        assert_eq!(output[4..8], [25, 0, 0, 255]);
+    }
 }
@@ -0,0 +1,293 @@
+//! Synthetic frames for running the output pipeline without a capture-capable
+//! GPU, e.g. in CI or while developing the frontend.
+
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::gpu::GpuPriority;
+use crate::rotation::Rotation;
+use crate::source::{CaptureSource, Failure, FrameDesc, SourceDisplay, SourceOptions};
+use crate::{frame_len, pixel_offset, ChannelOrder, FrameSource, Rect, SyncMode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestPattern {
+    /// Red rising left to right, green top to bottom, blue cycling over time.
+    Gradient,
+    /// Vertical bars scrolling right, a column per frame.
+    MovingBars,
+    /// SMPTE-style colour bars, static.
+    ColorBars,
+}
+
+/// Generated `width`×`height` frames at `fps`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MockConfig {
+    pub pattern: TestPattern,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+}
+
+/// White, yellow, cyan, green, magenta, red, blue, as BGRA.
+const BARS: [[u8; 4]; 7] = [
+    [235, 235, 235, 255],
+    [16, 235, 235, 255],
+    [235, 235, 16, 255],
+    [16, 235, 16, 255],
+    [235, 16, 235, 255],
+    [16, 16, 235, 255],
+    [235, 16, 16, 255],
+];
+/// Width of each bar in `TestPattern::MovingBars`.
+const MOVING_BAR_WIDTH: u32 = 32;
+
+impl MockConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("Mock frames must have a non-zero size".to_string());
+        }
+        frame_len(self.width, self.height)?;
+        if !(self.fps > 0.0 && self.fps.is_finite()) {
+            return Err("Mock frame rate must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.fps)
+    }
+
+    /// Frame number `tick`, as BGRA like a duplicated desktop texture.
+    pub fn render(&self, tick: u32) -> Vec<u8> {
+        let (width, height) = (self.width, self.height);
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = match self.pattern {
+                    TestPattern::Gradient => [
+                        (tick % 256) as u8,
+                        (y * 255 / (height - 1).max(1)) as u8,
+                        (x * 255 / (width - 1).max(1)) as u8,
+                        255,
+                    ],
+                    TestPattern::MovingBars => {
+                        let cycle = MOVING_BAR_WIDTH * BARS.len() as u32;
+                        let shifted = x % cycle + cycle - tick % cycle;
+                        BARS[(shifted / MOVING_BAR_WIDTH) as usize % BARS.len()]
+                    }
+                    TestPattern::ColorBars => BARS[(x as usize * BARS.len()) / width as usize],
+                };
+                data.extend_from_slice(&pixel);
+            }
+        }
+        data
+    }
+}
+
+/// A frame of the pattern, rendered when it is read.
+pub(crate) struct MockFrame {
+    config: MockConfig,
+    tick: u32,
+}
+
+/// Serves generated frames to the capture loop in place of a display, paced
+/// at the configured rate.
+pub(crate) struct MockSource {
+    config: MockConfig,
+    tick: u32,
+    next_frame: Instant,
+    display: SourceDisplay,
+}
+
+impl MockSource {
+    fn set_config(&mut self, config: MockConfig) {
+        self.config = config;
+        self.display.refresh_rate = Some(config.fps as f64);
+    }
+}
+
+impl CaptureSource for MockSource {
+    type Frame = MockFrame;
+    type Error = Infallible;
+
+    /// Opens on the pattern `options.source` selects; there is no display to pick.
+    fn init(_display: Option<&str>, options: &SourceOptions) -> Result<Self, String> {
+        let FrameSource::Mock(config) = options.source else {
+            return Err("Mock frames need a mock source selected".to_string());
+        };
+        let display = SourceDisplay {
+            name: "Mock".to_string(),
+            origin: (0, 0),
+            refresh_rate: None,
+            rotation: Rotation::Identity,
+            variable_refresh: false,
+        };
+        let mut source = MockSource { config, tick: 0, next_frame: Instant::now(), display };
+        source.set_config(config);
+        Ok(source)
+    }
+
+    fn serves(source: &FrameSource) -> bool {
+        matches!(source, FrameSource::Mock(_))
+    }
+
+    fn configure(&mut self, options: &SourceOptions) {
+        if let FrameSource::Mock(config) = options.source {
+            if config != self.config {
+                self.set_config(config);
+            }
+        }
+    }
+
+    fn display(&self) -> &SourceDisplay {
+        &self.display
+    }
+
+    /// Waits out the rest of the frame period; `sync` has no vertical blank to wait for.
+    async fn acquire_frame(&mut self, _sync: SyncMode) -> Result<MockFrame, Infallible> {
+        let now = Instant::now();
+        if self.next_frame > now {
+            tokio::time::sleep(self.next_frame - now).await;
+        }
+        // Tick on a fixed grid, unless a whole period has been missed
+        let period = self.config.period();
+        self.next_frame = if now.saturating_duration_since(self.next_frame) > period {
+            now + period
+        } else {
+            self.next_frame + period
+        };
+        let frame = MockFrame { config: self.config, tick: self.tick };
+        self.tick = self.tick.wrapping_add(1);
+        Ok(frame)
+    }
+
+    fn describe(&self, frame: &MockFrame) -> FrameDesc {
+        FrameDesc {
+            width: frame.config.width,
+            height: frame.config.height,
+            format: "Mock".to_string(),
+            order: Some(ChannelOrder::Bgra),
+        }
+    }
+
+    fn read_into(&mut self, frame: &MockFrame, region: Option<Rect>, data: &mut Vec<u8>) -> Result<bool, String> {
+        let MockConfig { width, height, .. } = frame.config;
+        let rendered = frame.config.render(frame.tick);
+        let region = region.unwrap_or(Rect { x: 0, y: 0, width, height });
+        if region.x + region.width > width || region.y + region.height > height {
+            return Err("Region lies outside the mock frame".to_string());
+        }
+        data.clear();
+        for y in region.y..region.y + region.height {
+            let start = pixel_offset(region.x, y, width);
+            data.extend_from_slice(&rendered[start..start + region.width as usize * 4]);
+        }
+        Ok(true)
+    }
+
+    fn read_rgba_into(&mut self, _frame: &MockFrame, _region: Option<Rect>, _data: &mut Vec<u8>) -> Result<bool, String> {
+        Err("Mock frames aren't on the GPU".to_string())
+    }
+
+    fn flush(&mut self) {}
+
+    /// Frames are rendered as they are read, like a single readback buffer.
+    fn pipeline_depth(&self) -> usize {
+        1
+    }
+
+    fn set_gpu_priority(&mut self, _priority: GpuPriority) -> Result<GpuPriority, String> {
+        Err("Mock frames don't use the GPU".to_string())
+    }
+
+    fn failure(&self, error: &Infallible) -> Failure {
+        match *error {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pattern: TestPattern, width: u32, height: u32) -> MockConfig {
+        MockConfig { pattern, width, height, fps: 1000.0 }
+    }
+
+    fn pixel(data: &[u8], x: u32, y: u32, width: u32) -> [u8; 4] {
+        let offset = pixel_offset(x, y, width);
+        data[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn gradient_ramps_across_the_frame() {
+        let data = config(TestPattern::Gradient, 4, 3).render(5);
+        assert_eq!(data.len(), 4 * 3 * 4);
+        assert_eq!(pixel(&data, 0, 0, 4), [5, 0, 0, 255]);
+        assert_eq!(pixel(&data, 1, 1, 4), [5, 127, 85, 255]);
+        assert_eq!(pixel(&data, 3, 2, 4), [5, 255, 255, 255]);
+    }
+
+    #[test]
+    fn color_bars_split_the_width_evenly() {
+        let data = config(TestPattern::ColorBars, 14, 2).render(0);
+        assert_eq!(data.len(), 14 * 2 * 4);
+        for x in 0..14 {
+            assert_eq!(pixel(&data, x, 1, 14), BARS[x as usize / 2]);
+        }
+    }
+
+    #[test]
+    fn moving_bars_scroll_a_column_per_frame() {
+        let mock = config(TestPattern::MovingBars, 300, 1);
+        let (first, second) = (mock.render(0), mock.render(1));
+        assert_eq!(first.len(), 300 * 4);
+        assert_eq!(pixel(&first, 0, 0, 300), BARS[0]);
+        assert_eq!(pixel(&first, MOVING_BAR_WIDTH, 0, 300), BARS[1]);
+        // Wraps around once every bar has gone by
+        assert_eq!(pixel(&first, MOVING_BAR_WIDTH * 7, 0, 300), BARS[0]);
+        assert_eq!(pixel(&second, 0, 0, 300), BARS[6]);
+        for x in 0..299 {
+            assert_eq!(pixel(&second, x + 1, 0, 300), pixel(&first, x, 0, 300));
+        }
+    }
+
+    #[test]
+    fn read_into_crops_the_region() {
+        let mock = config(TestPattern::Gradient, 4, 3);
+        let rendered = mock.render(0);
+        let mut source = MockSource::init(None, &SourceOptions { readback_buffers: 1, source: FrameSource::Mock(mock) }).unwrap();
+        let frame = MockFrame { config: mock, tick: 0 };
+        let mut data = Vec::new();
+
+        assert!(source.read_into(&frame, None, &mut data).unwrap());
+        assert_eq!(data, rendered);
+
+        let region = Rect { x: 1, y: 1, width: 2, height: 2 };
+        assert!(source.read_into(&frame, Some(region), &mut data).unwrap());
+        let expected: Vec<u8> = [(1, 1), (2, 1), (1, 2), (2, 2)]
+            .into_iter()
+            .flat_map(|(x, y)| pixel(&rendered, x, y, 4))
+            .collect();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn read_into_rejects_region_outside_frame() {
+        let mock = config(TestPattern::Gradient, 4, 3);
+        let mut source = MockSource::init(None, &SourceOptions { readback_buffers: 1, source: FrameSource::Mock(mock) }).unwrap();
+        let frame = MockFrame { config: mock, tick: 0 };
+        let mut data = Vec::new();
+        for region in [Rect { x: 3, y: 0, width: 2, height: 1 }, Rect { x: 0, y: 2, width: 1, height: 2 }] {
+            assert!(source.read_into(&frame, Some(region), &mut data).is_err());
+        }
+    }
+
+    #[test]
+    fn validate_rejects_empty_and_stopped_sources() {
+        assert!(config(TestPattern::Gradient, 4, 3).validate().is_ok());
+        assert!(config(TestPattern::Gradient, 0, 3).validate().is_err());
+        assert!(MockConfig { fps: 0.0, ..config(TestPattern::Gradient, 4, 3) }.validate().is_err());
+        assert!(MockConfig { fps: f32::NAN, ..config(TestPattern::Gradient, 4, 3) }.validate().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Preprocessing that turns RGBA frames into OCR-friendly grayscale.
+
+use serde::{Deserialize, Serialize};
+
+/// Half-width of the neighbourhood used by adaptive thresholding.
+const ADAPTIVE_RADIUS: usize = 7;
+/// How far below the local mean a pixel must be to count as ink.
+const ADAPTIVE_OFFSET: u32 = 5;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Threshold {
+    /// Contrast-stretched grayscale.
+    #[default]
+    None,
+    /// Binarize with a single global threshold chosen by Otsu's method.
+    Otsu,
+    /// Binarize against the mean of each pixel's neighbourhood, which copes
+    /// with uneven backgrounds.
+    Adaptive,
+}
+
+/// Single-channel, 1 byte per pixel image.
+#[derive(Clone, Debug, Serialize)]
+pub struct OcrFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+pub fn preprocess(rgba: &[u8], width: u32, height: u32, threshold: Threshold) -> OcrFrame {
+    let mut gray: Vec<u8> = rgba.chunks_exact(4).map(|p| luma(p[0], p[1], p[2])).collect();
+    stretch_contrast(&mut gray);
+
+    match threshold {
+        Threshold::None => {}
+        Threshold::Otsu => {
+            let level = otsu_level(&gray);
+            gray.iter_mut().for_each(|v| *v = if *v > level { 255 } else { 0 });
+        }
+        Threshold::Adaptive => gray = adaptive(&gray, width as usize, height as usize),
+    }
+
+    OcrFrame { data: gray, width, height }
+}
+
+/// Rec. 601 luma in integer arithmetic.
+#[inline]
+pub(crate) fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8
+}
+
+/// Spreads the used range of values over 0..=255.
+fn stretch_contrast(gray: &mut [u8]) {
+    let (Some(&min), Some(&max)) = (gray.iter().min(), gray.iter().max()) else {
+        return;
+    };
+    if max == min {
+        return;
+    }
+    let range = (max - min) as u32;
+    for v in gray.iter_mut() {
+        *v = ((*v - min) as u32 * 255 / range) as u8;
+    }
+}
+
+/// The level that maximizes between-class variance of the histogram.
+fn otsu_level(gray: &[u8]) -> u8 {
+    let mut histogram = [0u64; 256];
+    for &v in gray {
+        histogram[v as usize] += 1;
+    }
+    let total = gray.len() as f64;
+    let sum: f64 = histogram.iter().enumerate().map(|(i, &n)| i as f64 * n as f64).sum();
+
+    let (mut background, mut background_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0u8, 0.0);
+    for (level, &count) in histogram.iter().enumerate() {
+        background += count as f64;
+        if background == 0.0 {
+            continue;
+        }
+        let foreground = total - background;
+        if foreground == 0.0 {
+            break;
+        }
+        background_sum += level as f64 * count as f64;
+        let mean_background = background_sum / background;
+        let mean_foreground = (sum - background_sum) / foreground;
+        let variance = background * foreground * (mean_background - mean_foreground).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = level as u8;
+        }
+    }
+    best
+}
+
+/// Mean-based adaptive threshold over a summed-area table.
+fn adaptive(gray: &[u8], width: usize, height: usize) -> Vec<u8> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let stride = width + 1;
+    let mut integral = vec![0u64; stride * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0u64;
+        for x in 0..width {
+            row_sum += gray[y * width + x] as u64;
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row_sum;
+        }
+    }
+
+    let mut output = Vec::with_capacity(gray.len());
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(ADAPTIVE_RADIUS), (y + ADAPTIVE_RADIUS + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(ADAPTIVE_RADIUS), (x + ADAPTIVE_RADIUS + 1).min(width));
+            let sum = integral[y1 * stride + x1] + integral[y0 * stride + x0]
+                - integral[y0 * stride + x1]
+                - integral[y1 * stride + x0];
+            let mean = sum / ((x1 - x0) * (y1 - y0)) as u64;
+            let ink = (gray[y * width + x] as u32 + ADAPTIVE_OFFSET) <= mean as u32;
+            output.push(if ink { 0 } else { 255 });
+        }
+    }
+    output
+}
@@ -0,0 +1,113 @@
+//! Diagnostics burned into the frame with a built-in 5×7 bitmap font, so a
+//! screenshot of the preview carries them.
+
+use crate::pixel_offset;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Output pixels per font pixel.
+const SCALE: u32 = 2;
+/// Space around and between lines, in font pixels.
+const PADDING: u32 = 2;
+const TEXT: [u8; 4] = [255, 255, 255, 255];
+const BACKGROUND: [u8; 4] = [0, 0, 0, 192];
+
+/// Rows of a glyph, top first, the low five bits left to right.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'D' => [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        '.' => [0, 0, 0, 0, 0, 0b01100, 0b01100],
+        ':' => [0, 0b01100, 0b01100, 0, 0b01100, 0b01100, 0],
+        '-' => [0, 0, 0, 0b11111, 0, 0, 0],
+        // Space and anything the font lacks
+        _ => [0; 7],
+    }
+}
+
+/// Values shown by the overlay.
+#[derive(Default, Clone, Copy)]
+pub struct OverlayStats {
+    pub fps: f32,
+    pub width: u32,
+    pub height: u32,
+    /// Desktop presents over the last second that weren't captured.
+    pub dropped: u32,
+    /// Time from acquiring the frame to publishing it.
+    pub latency_ms: f32,
+}
+
+/// Draws `stats` over the top-left corner of a `width`×`height` RGBA frame,
+/// clipped to the frame.
+pub fn draw(data: &mut [u8], width: u32, height: u32, stats: &OverlayStats) {
+    let lines = [
+        format!("FPS {:.1}", stats.fps),
+        format!("RES {}X{}", stats.width, stats.height),
+        format!("DROP {}", stats.dropped),
+        format!("LAT {:.1}MS", stats.latency_ms),
+    ];
+    let columns = lines.iter().map(|line| line.len() as u32).max().unwrap_or(0);
+    let box_width = (PADDING + columns * (GLYPH_WIDTH + 1) + PADDING - 1) * SCALE;
+    let box_height = (PADDING + lines.len() as u32 * (GLYPH_HEIGHT + PADDING)) * SCALE;
+
+    for y in 0..box_height.min(height) {
+        for x in 0..box_width.min(width) {
+            blend(data, pixel_offset(x, y, width), BACKGROUND);
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = PADDING + row as u32 * (GLYPH_HEIGHT + PADDING);
+        for (column, c) in line.chars().enumerate() {
+            let left = PADDING + column as u32 * (GLYPH_WIDTH + 1);
+            for (gy, bits) in glyph(c).iter().enumerate() {
+                for gx in 0..GLYPH_WIDTH {
+                    if bits >> (GLYPH_WIDTH - 1 - gx) & 1 == 0 {
+                        continue;
+                    }
+                    // One font pixel as a SCALE×SCALE block
+                    for dy in 0..SCALE {
+                        for dx in 0..SCALE {
+                            let x = (left + gx) * SCALE + dx;
+                            let y = (top + gy as u32) * SCALE + dy;
+                            if x < width && y < height {
+                                blend(data, pixel_offset(x, y, width), TEXT);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn blend(data: &mut [u8], offset: usize, color: [u8; 4]) {
+    let Some(pixel) = data.get_mut(offset..offset + 4) else {
+        return;
+    };
+    let alpha = color[3] as u32;
+    for (channel, &value) in pixel[..3].iter_mut().zip(&color[..3]) {
+        *channel = ((value as u32 * alpha + *channel as u32 * (255 - alpha)) / 255) as u8;
+    }
+    pixel[3] = pixel[3].max(color[3]);
+}
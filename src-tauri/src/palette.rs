@@ -0,0 +1,137 @@
+//! Quantizing frames to an indexed palette of at most 256 colours.
+
+use serde::{Deserialize, Serialize};
+
+/// Bits kept per channel when histogramming colours for median cut.
+const HISTOGRAM_BITS: u32 = 5;
+const HISTOGRAM_SIZE: usize = 1 << (HISTOGRAM_BITS * 3);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteMode {
+    /// The fixed 216-colour web-safe palette; cheap, and stable across frames.
+    WebSafe,
+    /// Up to `colors` colours picked for each frame by median cut.
+    MedianCut { colors: u16 },
+}
+
+impl PaletteMode {
+    pub fn validate(self) -> Result<(), String> {
+        match self {
+            PaletteMode::MedianCut { colors } if !(2..=256).contains(&colors) => {
+                Err("Palette must have between 2 and 256 colours".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Pixels as one byte each indexing `palette`, whose entries are RGBA.
+pub struct Indexed {
+    pub palette: Vec<[u8; 4]>,
+    pub indices: Vec<u8>,
+}
+
+/// Quantizes an RGBA frame.
+pub fn quantize(data: &[u8], mode: PaletteMode) -> Indexed {
+    match mode {
+        PaletteMode::WebSafe => web_safe(data),
+        PaletteMode::MedianCut { colors } => median_cut(data, colors as usize),
+    }
+}
+
+fn web_safe(data: &[u8]) -> Indexed {
+    // Six levels per channel, 0x00 to 0xff in steps of 0x33
+    let palette = (0..216u32)
+        .map(|i| [(i / 36 * 51) as u8, (i / 6 % 6 * 51) as u8, (i % 6 * 51) as u8, 255])
+        .collect();
+    let level = |c: u8| (c as u32 + 25) / 51;
+    let indices = data
+        .chunks_exact(4)
+        .map(|p| (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as u8)
+        .collect();
+    Indexed { palette, indices }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Bucket {
+    count: u64,
+    /// Sum of each RGBA channel over the pixels in this bucket.
+    sum: [u64; 4],
+}
+
+fn histogram_key(p: &[u8]) -> usize {
+    let shift = 8 - HISTOGRAM_BITS;
+    ((p[0] as usize >> shift) << (HISTOGRAM_BITS * 2)) | ((p[1] as usize >> shift) << HISTOGRAM_BITS) | (p[2] as usize >> shift)
+}
+
+/// Channel `channel` (0 red, 1 green, 2 blue) of a histogram key.
+fn component(key: usize, channel: usize) -> usize {
+    (key >> (HISTOGRAM_BITS as usize * (2 - channel))) & ((1 << HISTOGRAM_BITS) - 1)
+}
+
+fn median_cut(data: &[u8], colors: usize) -> Indexed {
+    let mut buckets = vec![Bucket::default(); HISTOGRAM_SIZE];
+    for p in data.chunks_exact(4) {
+        let bucket = &mut buckets[histogram_key(p)];
+        bucket.count += 1;
+        for (sum, &c) in bucket.sum.iter_mut().zip(p) {
+            *sum += c as u64;
+        }
+    }
+
+    // Each box is a set of histogram keys; keep splitting the one with the
+    // widest channel at its pixel median until there are enough colours
+    let mut boxes: Vec<Vec<usize>> = vec![(0..HISTOGRAM_SIZE).filter(|&key| buckets[key].count > 0).collect()];
+    while boxes.len() < colors {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, keys)| keys.len() > 1)
+            .flat_map(|(index, keys)| {
+                (0..3).map(move |channel| {
+                    let values = keys.iter().map(|&key| component(key, channel));
+                    let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                    (index, channel, range)
+                })
+            })
+            .max_by_key(|&(.., range)| range);
+        let Some((index, channel, _)) = widest else {
+            break;
+        };
+
+        let mut keys = boxes.swap_remove(index);
+        keys.sort_unstable_by_key(|&key| component(key, channel));
+        let total: u64 = keys.iter().map(|&key| buckets[key].count).sum();
+        let mut seen = 0;
+        let median = keys
+            .iter()
+            .position(|&key| {
+                seen += buckets[key].count;
+                seen * 2 >= total
+            })
+            .map_or(1, |position| position + 1)
+            .clamp(1, keys.len() - 1);
+        let upper = keys.split_off(median);
+        boxes.push(keys);
+        boxes.push(upper);
+    }
+
+    let mut lookup = vec![0u8; HISTOGRAM_SIZE];
+    let palette = boxes
+        .iter()
+        .enumerate()
+        .map(|(index, keys)| {
+            let mut total = Bucket::default();
+            for &key in keys {
+                lookup[key] = index as u8;
+                total.count += buckets[key].count;
+                for (sum, part) in total.sum.iter_mut().zip(buckets[key].sum) {
+                    *sum += part;
+                }
+            }
+            total.sum.map(|sum| (sum / total.count.max(1)) as u8)
+        })
+        .collect();
+    let indices = data.chunks_exact(4).map(|p| lookup[histogram_key(p)]).collect();
+    Indexed { palette, indices }
+}
@@ -0,0 +1,62 @@
+//! Locating another process's window, for cropping the duplicated desktop to it.
+
+use tauri::{PhysicalPosition, PhysicalSize};
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM, RECT};
+use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS};
+use windows::Win32::UI::WindowsAndMessaging::{
+    EnumWindows, GetWindow, GetWindowThreadProcessId, IsIconic, IsWindow, IsWindowVisible, GW_OWNER,
+};
+
+/// Position and size on the virtual desktop of the main window of process
+/// `pid`: its first visible, unowned top-level window. `None` while it has
+/// none or it is minimized.
+pub fn window_geometry(pid: u32) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+    let rect = window_bounds(main_window(pid)?)?;
+    let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+    Some((PhysicalPosition::new(rect.left, rect.top), PhysicalSize::new(width as u32, height as u32)))
+}
+
+/// The visible frame of `hwnd` on the virtual desktop, without the invisible
+/// resize borders `GetWindowRect` includes. `None` if it isn't a window, is
+/// minimized or has no area.
+pub fn window_bounds(hwnd: HWND) -> Option<RECT> {
+    if !unsafe { IsWindow(Some(hwnd)) }.as_bool() || unsafe { IsIconic(hwnd) }.as_bool() {
+        return None;
+    }
+    let mut rect = RECT::default();
+    unsafe {
+        DwmGetWindowAttribute(
+            hwnd,
+            DWMWA_EXTENDED_FRAME_BOUNDS,
+            &mut rect as *mut _ as *mut _,
+            size_of::<RECT>() as u32,
+        )
+    }
+    .ok()?;
+    (rect.right > rect.left && rect.bottom > rect.top).then_some(rect)
+}
+
+fn main_window(pid: u32) -> Option<HWND> {
+    struct Search {
+        pid: u32,
+        found: Option<HWND>,
+    }
+
+    unsafe extern "system" fn visit(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let search = &mut *(lparam.0 as *mut Search);
+        let mut owner_pid = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut owner_pid));
+        let unowned = GetWindow(hwnd, GW_OWNER).is_err();
+        if owner_pid == search.pid && unowned && IsWindowVisible(hwnd).as_bool() {
+            search.found = Some(hwnd);
+            // Stop enumerating
+            return false.into();
+        }
+        true.into()
+    }
+
+    let mut search = Search { pid, found: None };
+    // Errors once the callback stops enumeration, so the result says nothing
+    let _ = unsafe { EnumWindows(Some(visit), LPARAM(&mut search as *mut _ as isize)) };
+    search.found
+}
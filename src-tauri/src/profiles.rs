@@ -0,0 +1,98 @@
+//! Named capture profiles, stored as JSON files in the app config directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ErrorLimiter;
+use crate::replay::DEFAULT_REPLAY_BUDGET;
+use crate::{CaptureSettings, FrameSource};
+
+const EXTENSION: &str = "json";
+
+/// What a profile holds: the capture settings plus the state kept outside
+/// them. Profiles saved before the extra state keep its defaults.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    #[serde(flatten)]
+    pub settings: CaptureSettings,
+    pub replay_seconds: u32,
+    pub replay_budget: usize,
+    pub error_interval_ms: u64,
+    pub stream_bitrate_kbps: Option<u32>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            settings: CaptureSettings::default(),
+            replay_seconds: 0,
+            replay_budget: DEFAULT_REPLAY_BUDGET,
+            error_interval_ms: ErrorLimiter::default().interval().as_millis() as u64,
+            stream_bitrate_kbps: None,
+        }
+    }
+}
+
+impl Profile {
+    fn validate(&self) -> Result<(), String> {
+        self.settings.validate()?;
+        if self.stream_bitrate_kbps == Some(0) {
+            return Err("Bitrate must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn save(dir: &Path, name: &str, mut profile: Profile) -> Result<(), String> {
+    let path = profile_path(dir, name)?;
+    forget_process(&mut profile.settings);
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(&profile).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Reads and checks profile `name`, failing rather than returning anything
+/// a setter would have refused.
+pub fn load(dir: &Path, name: &str) -> Result<Profile, String> {
+    let path = profile_path(dir, name)?;
+    let json = fs::read_to_string(&path).map_err(|e| format!("Failed to read profile {}: {}", name, e))?;
+    let mut profile: Profile =
+        serde_json::from_str(&json).map_err(|e| format!("Profile {} is invalid: {}", name, e))?;
+    forget_process(&mut profile.settings);
+    profile.validate().map_err(|e| format!("Profile {} is invalid: {}", name, e))?;
+    Ok(profile)
+}
+
+/// Process ids are reused once the process exits, so they aren't kept.
+fn forget_process(settings: &mut CaptureSettings) {
+    if let FrameSource::ProcessWindow { .. } = settings.source {
+        settings.source = FrameSource::default();
+    }
+}
+
+/// Names of all saved profiles, sorted.
+pub fn list(dir: &Path) -> Result<Vec<String>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.path().file_name()?.to_str().map(str::to_owned))
+        .filter_map(|file| file.strip_suffix(&format!(".{}", EXTENSION)).map(str::to_owned))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Names double as file names, so they're limited to characters safe in both.
+fn profile_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let valid = !name.trim().is_empty()
+        && name.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'));
+    if !valid {
+        return Err("Profile names may only contain letters, digits, spaces, '-' and '_'".to_string());
+    }
+    Ok(dir.join(format!("{}.{}", name, EXTENSION)))
+}
@@ -0,0 +1,87 @@
+//! Checking that capture protection really hides the window.
+//!
+//! The screen area under the window is captured with GDI, which honours
+//! display affinity the same way the duplication API does, and compared with
+//! what the window itself renders.
+
+use serde::Serialize;
+use windows::Win32::Foundation::{HWND, RECT};
+use windows::Win32::Graphics::Gdi::{BitBlt, GetDC, ReleaseDC, SRCCOPY};
+use windows::Win32::UI::WindowsAndMessaging::{GetWindowRect, IsIconic};
+
+use crate::gdi;
+
+/// Channel difference still counted as the same colour.
+const TOLERANCE: u8 = 8;
+/// Share of pixels that must agree for two captures to count as the same.
+const MATCH_RATIO: f32 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Verdict {
+    /// The window's area shows what is behind it.
+    Hidden,
+    /// The window's area is black: protected, but not excluded.
+    BlackedOut,
+    /// The window's content shows up in the capture.
+    Visible,
+    /// The window renders almost entirely black, so it can't be told apart.
+    Inconclusive,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProtectionCheck {
+    verdict: Verdict,
+    passed: bool,
+    method: String,
+    /// Share of the captured area matching the window's own rendering.
+    match_ratio: f32,
+}
+
+pub fn verify(hwnd: HWND) -> Result<ProtectionCheck, String> {
+    if unsafe { IsIconic(hwnd) }.as_bool() {
+        return Err("Restore the window before verifying protection".to_string());
+    }
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(hwnd, &mut rect) }.map_err(|e| e.to_string())?;
+    let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+    if width <= 0 || height <= 0 {
+        return Err("Window has no area to verify".to_string());
+    }
+
+    let screen = unsafe {
+        gdi::snapshot(width, height, |dc| {
+            let screen_dc = GetDC(None);
+            let copied = BitBlt(dc, 0, 0, width, height, Some(screen_dc), rect.left, rect.top, SRCCOPY).is_ok();
+            ReleaseDC(None, screen_dc);
+            copied
+        })
+    }
+    .ok_or("Failed to capture the screen")?;
+    let rendered = gdi::print_window(hwnd, width as u32, height as u32).ok_or("Failed to render the window")?;
+
+    let black = |p: &[u8]| p[..3].iter().all(|&c| c < TOLERANCE);
+    let ratio = |count: usize| count as f32 / (width * height) as f32;
+    let matching = screen
+        .chunks_exact(4)
+        .zip(rendered.chunks_exact(4))
+        .filter(|(a, b)| a[..3].iter().zip(&b[..3]).all(|(a, b)| a.abs_diff(*b) < TOLERANCE))
+        .count();
+    let match_ratio = ratio(matching);
+
+    let verdict = if ratio(rendered.chunks_exact(4).filter(|p| black(p)).count()) >= MATCH_RATIO {
+        Verdict::Inconclusive
+    } else if ratio(screen.chunks_exact(4).filter(|p| black(p)).count()) >= MATCH_RATIO {
+        Verdict::BlackedOut
+    } else if match_ratio >= MATCH_RATIO {
+        Verdict::Visible
+    } else {
+        Verdict::Hidden
+    };
+
+    Ok(ProtectionCheck {
+        verdict,
+        passed: matches!(verdict, Verdict::Hidden | Verdict::BlackedOut),
+        method: "GDI screen copy compared with PrintWindow".to_string(),
+        match_ratio,
+    })
+}
@@ -0,0 +1,149 @@
+//! Always-on instant replay of recent processed frames.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use image::ExtendedColorType;
+use serde::Serialize;
+
+/// Default memory ceiling for buffered replay frames.
+pub const DEFAULT_REPLAY_BUDGET: usize = 256 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct ReplayFrame {
+    captured_at: Instant,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Index entry written next to each saved frame.
+#[derive(Serialize)]
+struct SavedFrame {
+    file: String,
+    /// Milliseconds since the first frame of the replay.
+    offset_ms: u64,
+    width: u32,
+    height: u32,
+}
+
+pub struct ReplayBuffer {
+    frames: VecDeque<ReplayFrame>,
+    window: Duration,
+    budget: usize,
+    bytes: usize,
+}
+
+impl Default for ReplayBuffer {
+    fn default() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            window: Duration::ZERO,
+            budget: DEFAULT_REPLAY_BUDGET,
+            bytes: 0,
+        }
+    }
+}
+
+impl ReplayBuffer {
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Keeps the last `window` of frames; zero disables and clears the buffer.
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+        if window.is_zero() {
+            self.frames.clear();
+            self.bytes = 0;
+        } else {
+            self.trim(Instant::now());
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.trim(Instant::now());
+    }
+
+    pub fn push(&mut self, width: u32, height: u32, data: Vec<u8>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let now = Instant::now();
+        self.bytes += data.len();
+        self.frames.push_back(ReplayFrame { captured_at: now, width, height, data });
+        self.trim(now);
+    }
+
+    /// Bytes held by buffered frames.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Drops the oldest frames until at most `bytes` are held.
+    pub fn evict_to(&mut self, bytes: usize) {
+        while self.bytes > bytes {
+            let Some(front) = self.frames.pop_front() else {
+                break;
+            };
+            self.bytes -= front.data.len();
+        }
+    }
+
+    /// Copies out the buffered frames, oldest first.
+    pub fn snapshot(&self) -> Vec<ReplayFrame> {
+        self.frames.iter().cloned().collect()
+    }
+
+    /// Drops frames older than the window, then oldest frames until under budget.
+    fn trim(&mut self, now: Instant) {
+        while let Some(front) = self.frames.front() {
+            let expired = now.duration_since(front.captured_at) > self.window;
+            if !expired && self.bytes <= self.budget {
+                break;
+            }
+            self.bytes -= front.data.len();
+            self.frames.pop_front();
+        }
+    }
+}
+
+/// Writes `frames` as a numbered PNG sequence plus an `index.json` into `dir`.
+pub fn save(frames: &[ReplayFrame], dir: &Path) -> Result<usize, String> {
+    let first = frames.first().ok_or("Replay buffer is empty")?.captured_at;
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let mut index = Vec::with_capacity(frames.len());
+    for (i, frame) in frames.iter().enumerate() {
+        let file = format!("frame_{:05}.png", i);
+        image::save_buffer(
+            dir.join(&file),
+            &frame.data,
+            frame.width,
+            frame.height,
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| e.to_string())?;
+
+        index.push(SavedFrame {
+            file,
+            offset_ms: frame.captured_at.duration_since(first).as_millis() as u64,
+            width: frame.width,
+            height: frame.height,
+        });
+    }
+
+    let json = serde_json::to_vec_pretty(&index).map_err(|e| e.to_string())?;
+    fs::write(dir.join("index.json"), json).map_err(|e| e.to_string())?;
+    Ok(frames.len())
+}
@@ -0,0 +1,222 @@
+//! Filtered resampling of the crop to the output size.
+//!
+//! Each axis is handled independently: an axis that grows is interpolated
+//! with the upscale filter, an axis that shrinks uses the downscale filter.
+//! This covers the mixed case where only one dimension is upscaled.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{frame_len, pixel_offset, scale_coord, Rect};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Catmull-Rom bicubic.
+    Bicubic,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Downscale {
+    Nearest,
+    /// Averages every source pixel covered by an output pixel.
+    Box,
+    /// Repeated 2× box reductions until within 4×, then `Box`.
+    MultiStep,
+    /// Picks one of the above from the reduction ratio.
+    #[default]
+    Auto,
+}
+
+impl Downscale {
+    /// Resolves `Auto` for a reduction of `ratio` source pixels per output pixel.
+    pub fn resolve(self, ratio: f32) -> Downscale {
+        match self {
+            Downscale::Auto if ratio <= 1.5 => Downscale::Nearest,
+            Downscale::Auto if ratio <= 4.0 => Downscale::Box,
+            Downscale::Auto => Downscale::MultiStep,
+            other => other,
+        }
+    }
+}
+
+/// Source pixels and weights contributing to one output coordinate.
+type Taps = Vec<(u32, f32)>;
+
+/// Computes the taps for every output coordinate along one axis.
+fn axis_taps(start: u32, len: u32, out_len: u32, filter: Interpolation, downscale: Downscale) -> Vec<Taps> {
+    let last = start + len.saturating_sub(1);
+    let clamp = |i: i64| (start as i64 + i).clamp(start as i64, last as i64) as u32;
+
+    (0..out_len)
+        .map(|o| {
+            if out_len < len && downscale != Downscale::Nearest {
+                let from = scale_coord(o, len, out_len);
+                let to = scale_coord(o + 1, len, out_len).max(from + 1);
+                let weight = 1.0 / (to - from) as f32;
+                return (from..to).map(|i| (start + i, weight)).collect();
+            }
+            if out_len <= len || filter == Interpolation::Nearest {
+                return vec![(start + scale_coord(o, len, out_len), 1.0)];
+            }
+
+            // Centre-aligned position of this output sample in source space
+            let pos = (o as f32 + 0.5) * len as f32 / out_len as f32 - 0.5;
+            let base = pos.floor();
+            let t = pos - base;
+            let base = base as i64;
+
+            match filter {
+                Interpolation::Bilinear => vec![(clamp(base), 1.0 - t), (clamp(base + 1), t)],
+                _ => catmull_rom(t)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, w)| (clamp(base - 1 + k as i64), w))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+/// Resamples `crop` of a 4-byte-per-pixel image to `out_width`×`out_height`,
+/// keeping the source channel order. `downscale` must already be resolved.
+pub fn resample(
+    original: &[u8],
+    orig_width: u32,
+    crop: Rect,
+    out_width: u32,
+    out_height: u32,
+    filter: Interpolation,
+    downscale: Downscale,
+) -> Vec<u8> {
+    if downscale == Downscale::MultiStep {
+        let halve_x = crop.width / 4 > out_width;
+        let halve_y = crop.height / 4 > out_height;
+        if halve_x || halve_y {
+            let (halved, half_crop) = halve(original, orig_width, crop, halve_x, halve_y);
+            return resample(&halved, half_crop.width, half_crop, out_width, out_height, filter, downscale);
+        }
+    }
+
+    let xs = axis_taps(crop.x, crop.width, out_width, filter, downscale);
+    let ys = axis_taps(crop.y, crop.height, out_height, filter, downscale);
+
+    let mut output = Vec::with_capacity(frame_len(out_width, out_height).unwrap_or(0));
+    for y_taps in &ys {
+        for x_taps in &xs {
+            let mut acc = [0f32; 4];
+            for &(sy, wy) in y_taps {
+                for &(sx, wx) in x_taps {
+                    let idx = pixel_offset(sx, sy, orig_width);
+                    if let Some(pixel) = original.get(idx..idx + 4) {
+                        for (sum, &value) in acc.iter_mut().zip(pixel) {
+                            *sum += value as f32 * wx * wy;
+                        }
+                    }
+                }
+            }
+            output.extend(acc.map(|v| v.round().clamp(0.0, 255.0) as u8));
+        }
+    }
+    output
+}
+
+/// Box-reduces `crop` by 2× along the chosen axes into a new image covering
+/// just the reduced crop.
+fn halve(original: &[u8], orig_width: u32, crop: Rect, halve_x: bool, halve_y: bool) -> (Vec<u8>, Rect) {
+    let step_x = if halve_x { 2 } else { 1 };
+    let step_y = if halve_y { 2 } else { 1 };
+    let width = crop.width / step_x;
+    let height = crop.height / step_y;
+
+    let mut output = Vec::with_capacity(frame_len(width, height).unwrap_or(0));
+    for y in 0..height {
+        for x in 0..width {
+            let mut acc = [0u32; 4];
+            let mut count = 0;
+            for dy in 0..step_y {
+                for dx in 0..step_x {
+                    let sx = crop.x + x * step_x + dx;
+                    let sy = crop.y + y * step_y + dy;
+                    let idx = pixel_offset(sx, sy, orig_width);
+                    if let Some(pixel) = original.get(idx..idx + 4) {
+                        for (sum, &value) in acc.iter_mut().zip(pixel) {
+                            *sum += value as u32;
+                        }
+                        count += 1;
+                    }
+                }
+            }
+            output.extend(acc.map(|v| (v + count / 2).checked_div(count).unwrap_or(0) as u8));
+        }
+    }
+    (output, Rect { x: 0, y: 0, width, height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An opaque gray image with one pixel per value.
+    fn gray(values: &[u8]) -> Vec<u8> {
+        values.iter().flat_map(|&v| [v, v, v, 255]).collect()
+    }
+
+    /// The first channel of each pixel.
+    fn levels(data: &[u8]) -> Vec<u8> {
+        data.chunks_exact(4).map(|pixel| pixel[0]).collect()
+    }
+
+    fn whole(width: u32, height: u32) -> Rect {
+        Rect { x: 0, y: 0, width, height }
+    }
+
+    #[test]
+    fn bilinear_upscale_interpolates_between_pixels() {
+        let source = gray(&[0, 100, 0, 100]);
+        let output = resample(&source, 2, whole(2, 2), 4, 4, Interpolation::Bilinear, Downscale::Nearest);
+        assert_eq!(output.len(), 4 * 4 * 4);
+        for row in output.chunks_exact(4 * 4) {
+            assert_eq!(levels(row), [0, 25, 75, 100]);
+        }
+        assert!(output.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn bicubic_upscale_sharpens_edges() {
+        let source = gray(&[0, 0, 100, 100]);
+        let bicubic = resample(&source, 4, whole(4, 1), 8, 1, Interpolation::Bicubic, Downscale::Nearest);
+        let bilinear = resample(&source, 4, whole(4, 1), 8, 1, Interpolation::Bilinear, Downscale::Nearest);
+        assert_eq!(bicubic.len(), 8 * 4);
+        assert_eq!(levels(&bicubic)[3..5], [20, 80]);
+        assert_eq!(levels(&bilinear)[3..5], [25, 75]);
+    }
+
+    #[test]
+    fn mixed_scale_upscales_width_and_box_filters_height() {
+        // Each pixel is 100 per column plus 10 per row
+        let source = gray(&[0, 100, 10, 110, 20, 120, 30, 130]);
+        let output = resample(&source, 2, whole(2, 4), 4, 2, Interpolation::Bilinear, Downscale::Box);
+        assert_eq!(output.len(), 4 * 2 * 4);
+        assert_eq!(levels(&output), [5, 30, 80, 105, 25, 50, 100, 125]);
+    }
+
+    #[test]
+    fn resamples_only_the_crop() {
+        let source = gray(&[200, 0, 100, 200]);
+        let output = resample(&source, 4, Rect { x: 1, y: 0, width: 2, height: 1 }, 4, 1, Interpolation::Bilinear, Downscale::Nearest);
+        assert_eq!(levels(&output), [0, 25, 75, 100]);
+    }
+}
@@ -0,0 +1,137 @@
+//! Orientation of rotated (portrait or flipped) displays.
+//!
+//! Duplicated textures arrive in the panel's native orientation, so frames
+//! from a rotated display are turned upright before desktop coordinates
+//! such as window positions and regions are applied to them. Output frames
+//! can then be turned again to suit a viewer held the other way.
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_MODE_ROTATION, DXGI_MODE_ROTATION_ROTATE180, DXGI_MODE_ROTATION_ROTATE270, DXGI_MODE_ROTATION_ROTATE90,
+};
+
+use crate::pixel_offset;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Rotation {
+    #[default]
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// How the consumer of the frames is held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ViewerOrientation {
+    Portrait,
+    Landscape,
+}
+
+impl ViewerOrientation {
+    /// Turns a frame a quarter clockwise if its shape doesn't suit the
+    /// viewer, returning it with its final width and height and whether it
+    /// was turned. Square frames suit both.
+    pub fn orient(self, data: Vec<u8>, width: u32, height: u32) -> (Vec<u8>, u32, u32, bool) {
+        let turn = match self {
+            ViewerOrientation::Portrait => width > height,
+            ViewerOrientation::Landscape => height > width,
+        };
+        if !turn {
+            return (data, width, height, false);
+        }
+        // Upright for a 270° display is a clockwise quarter turn
+        let (data, width, height) = Rotation::Rotate270.upright(data, width, height);
+        (data, width, height, true)
+    }
+}
+
+impl From<DXGI_MODE_ROTATION> for Rotation {
+    fn from(rotation: DXGI_MODE_ROTATION) -> Self {
+        match rotation {
+            DXGI_MODE_ROTATION_ROTATE90 => Rotation::Rotate90,
+            DXGI_MODE_ROTATION_ROTATE180 => Rotation::Rotate180,
+            DXGI_MODE_ROTATION_ROTATE270 => Rotation::Rotate270,
+            // Identity and unspecified
+            _ => Rotation::Identity,
+        }
+    }
+}
+
+impl Rotation {
+    /// Whether width and height swap between the texture and the desktop.
+    pub fn is_quarter_turn(self) -> bool {
+        matches!(self, Rotation::Rotate90 | Rotation::Rotate270)
+    }
+
+    /// Turns a `width`×`height` 4-byte-per-pixel texture upright, returning
+    /// it with its desktop width and height.
+    pub fn upright(self, data: Vec<u8>, width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        if self == Rotation::Identity {
+            return (data, width, height);
+        }
+        let (out_width, out_height) = if self.is_quarter_turn() { (height, width) } else { (width, height) };
+
+        let mut output = Vec::with_capacity(data.len());
+        for y in 0..out_height {
+            for x in 0..out_width {
+                // Texture pixel shown at desktop (x, y)
+                let (sx, sy) = match self {
+                    Rotation::Rotate90 => (width - 1 - y, x),
+                    Rotation::Rotate180 => (width - 1 - x, height - 1 - y),
+                    _ => (y, height - 1 - x),
+                };
+                let idx = pixel_offset(sx, sy, width);
+                output.extend_from_slice(data.get(idx..idx + 4).unwrap_or(&[0; 4]));
+            }
+        }
+        (output, out_width, out_height)
+    }
+}
+
+/// Size of the canvas that holds a `width`×`height` frame turned by `degrees`.
+pub fn rotated_size(width: u32, height: u32, degrees: f32) -> (u32, u32) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let fit = |along: f32, across: f32| ((along * cos + across * sin).round() as u32).max(1);
+    (fit(width as f32, height as f32), fit(height as f32, width as f32))
+}
+
+/// Turns a `width`×`height` RGBA frame clockwise by `degrees` with bilinear
+/// sampling, on a canvas grown to hold all of it. Corners the frame doesn't
+/// cover are `fill`, blended into its edges.
+pub fn rotate_by(data: &[u8], width: u32, height: u32, degrees: f32, fill: [u8; 4]) -> (Vec<u8>, u32, u32) {
+    let (out_width, out_height) = rotated_size(width, height, degrees);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let sample = |x: i64, y: i64| -> [f32; 4] {
+        let pixel = if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+            let idx = pixel_offset(x as u32, y as u32, width);
+            data.get(idx..idx + 4).map_or(fill, |p| [p[0], p[1], p[2], p[3]])
+        } else {
+            fill
+        };
+        pixel.map(|c| c as f32)
+    };
+
+    let mut output = Vec::with_capacity(out_width as usize * out_height as usize * 4);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            // Undo the turn about the centres to find the source position
+            let dx = x as f32 + 0.5 - out_width as f32 / 2.0;
+            let dy = y as f32 + 0.5 - out_height as f32 / 2.0;
+            let sx = cos * dx + sin * dy + width as f32 / 2.0 - 0.5;
+            let sy = -sin * dx + cos * dy + height as f32 / 2.0 - 0.5;
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let (top_left, top_right) = (sample(x0, y0), sample(x0 + 1, y0));
+            let (bottom_left, bottom_right) = (sample(x0, y0 + 1), sample(x0 + 1, y0 + 1));
+            for c in 0..4 {
+                let top = top_left[c] + (top_right[c] - top_left[c]) * fx;
+                let bottom = bottom_left[c] + (bottom_right[c] - bottom_left[c]) * fx;
+                output.push((top + (bottom - top) * fy).round().clamp(0.0, 255.0) as u8);
+            }
+        }
+    }
+    (output, out_width, out_height)
+}
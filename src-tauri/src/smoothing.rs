@@ -0,0 +1,40 @@
+//! Temporal smoothing of output frames, so capture noise on static content
+//! doesn't make every frame differ.
+
+/// Channel change treated as real content rather than noise; larger changes
+/// pass through unsmoothed so motion doesn't ghost.
+const NOISE_THRESHOLD: u8 = 16;
+
+/// Blends each frame with the previous smoothed output.
+#[derive(Default)]
+pub struct TemporalFilter {
+    previous: Vec<u8>,
+    size: (u32, u32),
+}
+
+impl TemporalFilter {
+    /// Smooths a `width`×`height` RGBA frame in place, weighting the previous
+    /// output by `factor` in `0.0..1.0`. Alpha is left as captured.
+    pub fn apply(&mut self, data: &mut [u8], width: u32, height: u32, factor: f32) {
+        if self.size != (width, height) || self.previous.len() != data.len() {
+            // Nothing comparable to blend with yet
+            self.size = (width, height);
+            self.previous = data.to_vec();
+            return;
+        }
+
+        let weight = (factor.clamp(0.0, 1.0) * 256.0) as u32;
+        for (pixel, previous) in data.chunks_exact_mut(4).zip(self.previous.chunks_exact(4)) {
+            for (channel, &old) in pixel[..3].iter_mut().zip(&previous[..3]) {
+                if channel.abs_diff(old) < NOISE_THRESHOLD {
+                    *channel = ((old as u32 * weight + *channel as u32 * (256 - weight) + 128) / 256) as u8;
+                }
+            }
+        }
+        self.previous.copy_from_slice(data);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
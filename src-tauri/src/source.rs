@@ -0,0 +1,285 @@
+//! Backends the capture loop takes frames from.
+//!
+//! The loop is written against `CaptureSource`; desktop duplication and
+//! generated mock frames implement it, and a per-process swapchain hook or
+//! GDI fallback could be added as others without touching the loop.
+
+use std::fmt::Debug;
+use parking_lot::RwLock;
+use win_desktop_duplication::devices::AdapterFactory;
+use win_desktop_duplication::errors::DDApiError;
+use win_desktop_duplication::texture::{ColorFormat, Texture};
+use win_desktop_duplication::{DesktopDuplicationApi, DuplicationApiOptions};
+
+use crate::rotation::Rotation;
+use crate::gpu::GpuPriority;
+use crate::{displays, gpu, CaptureInfo, ChannelOrder, FrameSource, Rect, SyncMode};
+
+/// Size and pixel format of an acquired frame.
+pub(crate) struct FrameDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    /// Byte order the format implies; `None` when it isn't 8-bit RGB.
+    pub order: Option<ChannelOrder>,
+}
+
+/// The display a source's frames cover.
+pub(crate) struct SourceDisplay {
+    /// Device name of the display.
+    pub name: String,
+    /// Top-left of the display on the virtual desktop.
+    pub origin: (i32, i32),
+    /// Refresh rate in Hz, when the display mode reports one.
+    pub refresh_rate: Option<f64>,
+    /// How the source's frames are turned relative to the desktop.
+    pub rotation: Rotation,
+    /// Whether the display may refresh at a variable rate.
+    pub variable_refresh: bool,
+}
+
+impl SourceDisplay {
+    /// Describes this display in `info`, keeping the thread details.
+    pub fn publish(&self, info: &RwLock<CaptureInfo>) {
+        let mut info = info.write();
+        info.display = Some(self.name.clone());
+        info.origin = self.origin;
+        info.refresh_rate = self.refresh_rate;
+        info.rotation = self.rotation;
+        info.variable_refresh = self.variable_refresh;
+    }
+}
+
+/// Settings a source picks up between frames.
+pub(crate) struct SourceOptions {
+    /// Frames of latency the read pipeline may add to avoid stalls.
+    pub readback_buffers: usize,
+    /// The selected source, for sources configured through it.
+    pub source: FrameSource,
+}
+
+/// What a failed acquire means for the source.
+pub(crate) enum Failure {
+    /// Worth retrying on the next frame.
+    Transient,
+    /// The device is gone; the source has to be initialised again.
+    DeviceLost,
+    /// Capture is no longer allowed, e.g. a secure desktop took over.
+    AccessLost,
+}
+
+pub(crate) trait CaptureSource: Sized {
+    /// A frame held by the source until it is read.
+    type Frame;
+    type Error: Debug;
+
+    /// Opens the source on the named display, or the primary one.
+    fn init(display: Option<&str>, options: &SourceOptions) -> Result<Self, String>;
+
+    /// Whether this source produces the frames `source` selects; capture
+    /// hands over to another source once it doesn't.
+    fn serves(source: &FrameSource) -> bool;
+
+    fn configure(&mut self, options: &SourceOptions);
+
+    fn display(&self) -> &SourceDisplay;
+
+    /// The next frame, waiting for the vertical blank with `SyncMode::Vsync`.
+    async fn acquire_frame(&mut self, sync: SyncMode) -> Result<Self::Frame, Self::Error>;
+
+    fn describe(&self, frame: &Self::Frame) -> FrameDesc;
+
+    /// Reads `frame`, or only `region` of it, into `data` as BGRA. Sources
+    /// that pipeline reads return `false` while no frame has completed yet.
+    fn read_into(&mut self, frame: &Self::Frame, region: Option<Rect>, data: &mut Vec<u8>) -> Result<bool, String>;
+
+    /// Like `read_into`, but has the GPU convert the pixels to RGBA on the
+    /// way, so they come back in output byte order. Alpha is lost.
+    fn read_rgba_into(&mut self, frame: &Self::Frame, region: Option<Rect>, data: &mut Vec<u8>) -> Result<bool, String>;
+
+    /// Drops frames still in the read pipeline.
+    fn flush(&mut self);
+
+    /// Frames currently in the read pipeline.
+    fn pipeline_depth(&self) -> usize;
+
+    /// Sets the GPU priority of the source's device, returning the one in
+    /// effect afterwards.
+    fn set_gpu_priority(&mut self, priority: GpuPriority) -> Result<GpuPriority, String>;
+
+    fn failure(&self, error: &Self::Error) -> Failure;
+}
+
+/// An open duplication of one display, with the reader for its frames.
+pub(crate) struct DuplicationSource {
+    api: DesktopDuplicationApi,
+    readback: gpu::Readback,
+    /// Kept between frames for `read_rgba_into`.
+    converter: Option<gpu::Converter>,
+    display: SourceDisplay,
+}
+
+impl CaptureSource for DuplicationSource {
+    type Frame = Texture;
+    type Error = DDApiError;
+
+    /// Creates a duplication on a freshly enumerated adapter, along with a
+    /// reader for its frames.
+    fn init(display_name: Option<&str>, options: &SourceOptions) -> Result<Self, String> {
+        let (adapter, output) = match display_name {
+            Some(name) => displays::find_by_name(name)?,
+            None => {
+                let mut adapters = AdapterFactory::new();
+                let adapter = adapters.find(|adapter| {
+                    adapter.get_display_by_idx(0).is_some()
+                })
+                    .ok_or("No suitable display adapters found")?;
+
+                let output = adapter.get_display_by_idx(0)
+                    .ok_or("No displays found for the selected adapter")?;
+                (adapter, output)
+            }
+        };
+        let display = SourceDisplay {
+            name: output.name(),
+            origin: displays::desktop_origin(&output)?,
+            refresh_rate: output
+                .get_current_display_mode()
+                .ok()
+                .filter(|mode| mode.refresh_num > 0 && mode.refresh_den > 0)
+                .map(|mode| mode.refresh_num as f64 / mode.refresh_den as f64),
+            rotation: displays::rotation(&output)?,
+            variable_refresh: displays::variable_refresh(&adapter),
+        };
+
+        // Configure for fastest frame acquisition
+        let mut api = DesktopDuplicationApi::new(adapter, output)
+            .map_err(|e| format!("Failed to initialize desktop duplication: {:?}", e))?;
+
+        api.configure(DuplicationApiOptions { skip_cursor: true });
+
+        let (device, ctx) = api.get_device_and_ctx();
+        let readback = unsafe { gpu::Readback::new(gpu::rewrap(&device), gpu::rewrap(&ctx), options.readback_buffers) };
+        Ok(DuplicationSource { api, readback, converter: None, display })
+    }
+
+    fn serves(source: &FrameSource) -> bool {
+        !matches!(source, FrameSource::Mock(_))
+    }
+
+    fn configure(&mut self, options: &SourceOptions) {
+        self.readback.set_buffers(options.readback_buffers);
+    }
+
+    fn display(&self) -> &SourceDisplay {
+        &self.display
+    }
+
+    async fn acquire_frame(&mut self, sync: SyncMode) -> Result<Texture, DDApiError> {
+        match sync {
+            SyncMode::Immediate => self.api.acquire_next_frame_now(),
+            SyncMode::Vsync => self.api.acquire_next_vsync_frame().await,
+        }
+    }
+
+    fn describe(&self, frame: &Texture) -> FrameDesc {
+        let desc = frame.desc();
+        let order = match desc.format {
+            // Named as packed words, so the bytes in memory run the other way
+            ColorFormat::ARGB8UNorm => Some(ChannelOrder::Bgra),
+            ColorFormat::ABGR8UNorm => Some(ChannelOrder::Rgba),
+            _ => None,
+        };
+        FrameDesc { width: desc.width, height: desc.height, format: format!("{:?}", desc.format), order }
+    }
+
+    fn read_into(&mut self, frame: &Texture, region: Option<Rect>, data: &mut Vec<u8>) -> Result<bool, String> {
+        self.readback.read(unsafe { &gpu::rewrap(frame.as_raw_ref()) }, region, data)
+    }
+
+    fn read_rgba_into(&mut self, frame: &Texture, region: Option<Rect>, data: &mut Vec<u8>) -> Result<bool, String> {
+        let desc = frame.desc();
+        let crop = region.unwrap_or(Rect { x: 0, y: 0, width: desc.width, height: desc.height });
+        let (input, output) = ((desc.width, desc.height), (crop.width, crop.height));
+        let (device, ctx) = self.api.get_device_and_ctx();
+        let (device, ctx) = unsafe { (gpu::rewrap(&device), gpu::rewrap(&ctx)) };
+        let converter = match self.converter.take().filter(|converter| converter.fits(input, output)) {
+            Some(converter) => converter,
+            None => gpu::Converter::new(&device, input, output)?,
+        };
+        let texture = unsafe { gpu::rewrap(frame.as_raw_ref()) };
+        let read = converter
+            .convert(&device, &ctx, &texture, crop)
+            .and_then(|target| self.readback.read(target, None, data));
+        self.converter = Some(converter);
+        read
+    }
+
+    fn flush(&mut self) {
+        self.readback.flush();
+    }
+
+    fn pipeline_depth(&self) -> usize {
+        self.readback.buffers()
+    }
+
+    fn set_gpu_priority(&mut self, priority: GpuPriority) -> Result<GpuPriority, String> {
+        gpu::set_gpu_priority(&unsafe { gpu::rewrap(&self.api.get_device_and_ctx().0) }, priority)
+    }
+
+    fn failure(&self, error: &DDApiError) -> Failure {
+        // A removed device (driver timeout, GPU reset) can't be reused; start from a new adapter
+        if unsafe { gpu::device_removed(&gpu::rewrap(&self.api.get_device_and_ctx().0)) } {
+            Failure::DeviceLost
+        } else if matches!(error, DDApiError::AccessLost | DDApiError::AccessDenied) {
+            Failure::AccessLost
+        } else {
+            Failure::Transient
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockConfig, MockSource, TestPattern};
+
+    /// Opens `S` and reads its next two frames whole, as the capture loop would.
+    fn read_two<S: CaptureSource>(options: &SourceOptions) -> Vec<(FrameDesc, Vec<u8>)> {
+        let mut source = S::init(None, options).unwrap();
+        source.configure(options);
+        tauri::async_runtime::block_on(async {
+            let mut frames = Vec::new();
+            for _ in 0..2 {
+                let frame = source.acquire_frame(SyncMode::Immediate).await.unwrap();
+                let desc = source.describe(&frame);
+                let mut data = Vec::new();
+                assert!(source.read_into(&frame, None, &mut data).unwrap());
+                frames.push((desc, data));
+            }
+            frames
+        })
+    }
+
+    #[test]
+    fn mock_source_serves_frames_through_the_trait() {
+        let config = MockConfig { pattern: TestPattern::Gradient, width: 8, height: 4, fps: 1000.0 };
+        let options = SourceOptions { readback_buffers: 1, source: FrameSource::Mock(config) };
+        assert!(MockSource::serves(&options.source));
+        assert!(!DuplicationSource::serves(&options.source));
+
+        let frames = read_two::<MockSource>(&options);
+        for (tick, (desc, data)) in frames.iter().enumerate() {
+            assert_eq!((desc.width, desc.height), (8, 4));
+            assert_eq!(desc.order, Some(ChannelOrder::Bgra));
+            assert_eq!(*data, config.render(tick as u32));
+        }
+    }
+
+    #[test]
+    fn mock_source_needs_a_mock_selected() {
+        let options = SourceOptions { readback_buffers: 1, source: FrameSource::DesktopDuplication };
+        assert!(MockSource::init(None, &options).is_err());
+        assert!(!MockSource::serves(&options.source));
+    }
+}
@@ -0,0 +1,260 @@
+//! Frame streams negotiated per consumer.
+//!
+//! A consumer opens a stream with the `StreamConfig` it would like and gets
+//! back the one it will actually receive. Each stream is then fed from the
+//! shared capture, scaled, reduced and encoded for that consumer alone, so a
+//! small client and a full-resolution one can watch the same capture.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::debug;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::ipc;
+
+use crate::consumers::{ConsumerId, Consumers};
+use crate::resample::{self, Downscale, Interpolation};
+use crate::{webp, FrameBuffer, FrameMeta, Rect};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamCodec {
+    /// Uncompressed pixels.
+    Raw,
+    /// WebP at `quality`; see `webp::encode`.
+    Webp { quality: u8 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamChannels {
+    Rgba,
+    /// Luma only; 1 byte per pixel when raw.
+    Gray,
+}
+
+/// What a consumer asks for when opening a stream, and what it is granted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    codec: StreamCodec,
+    /// Frames larger than this are scaled down to fit, keeping aspect ratio.
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    /// Frames beyond this rate are skipped for this stream.
+    max_fps: Option<f32>,
+    channels: StreamChannels,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            codec: StreamCodec::Raw,
+            max_width: None,
+            max_height: None,
+            max_fps: None,
+            channels: StreamChannels::Rgba,
+        }
+    }
+}
+
+impl StreamConfig {
+    /// The config the server will deliver for this request.
+    fn negotiate(self) -> StreamConfig {
+        StreamConfig {
+            codec: match self.codec {
+                StreamCodec::Webp { quality } => StreamCodec::Webp { quality: quality.min(100) },
+                codec => codec,
+            },
+            max_width: self.max_width.filter(|&width| width > 0),
+            max_height: self.max_height.filter(|&height| height > 0),
+            max_fps: self.max_fps.filter(|fps| fps.is_finite() && *fps > 0.0),
+            channels: self.channels,
+        }
+    }
+
+    fn period(&self) -> Duration {
+        self.max_fps.map_or(Duration::ZERO, |fps| Duration::from_secs_f32(1.0 / fps))
+    }
+
+    /// Summary for `list_consumers`.
+    fn describe(&self) -> String {
+        let limit = |value: Option<String>| value.unwrap_or_else(|| "any".to_string());
+        format!(
+            "{:?} {:?}, up to {}x{} at {} fps",
+            self.codec,
+            self.channels,
+            limit(self.max_width.map(|width| width.to_string())),
+            limit(self.max_height.map(|height| height.to_string())),
+            limit(self.max_fps.map(|fps| fps.to_string())),
+        )
+    }
+}
+
+/// Reply to `open_stream`.
+#[derive(Clone, Debug, Serialize)]
+pub struct StreamHandshake {
+    id: u32,
+    /// The request as granted.
+    config: StreamConfig,
+}
+
+/// One frame as sent through a stream.
+#[derive(Clone, Serialize)]
+pub struct StreamFrame {
+    sequence: u32,
+    width: u32,
+    height: u32,
+    codec: StreamCodec,
+    channels: StreamChannels,
+    data: Vec<u8>,
+    meta: FrameMeta,
+}
+
+struct Stream {
+    config: StreamConfig,
+    channel: ipc::Channel<StreamFrame>,
+    last_sent: Option<Instant>,
+    /// Set while a frame is being prepared, so a slow consumer skips frames
+    /// rather than queueing them.
+    busy: Arc<AtomicBool>,
+    /// Set once the consumer has gone away.
+    closed: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+pub struct Streams {
+    next_id: u32,
+    open: HashMap<u32, Stream>,
+}
+
+impl Streams {
+    pub fn open(
+        &mut self,
+        channel: ipc::Channel<StreamFrame>,
+        requested: StreamConfig,
+        consumers: &Mutex<Consumers>,
+    ) -> StreamHandshake {
+        let config = requested.negotiate();
+        self.next_id = self.next_id.wrapping_add(1);
+        consumers.lock().register(ConsumerId::Stream(self.next_id), config.describe());
+        let stream = Stream {
+            config,
+            channel,
+            last_sent: None,
+            busy: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
+        };
+        self.open.insert(self.next_id, stream);
+        StreamHandshake { id: self.next_id, config }
+    }
+
+    pub fn close(&mut self, id: u32, consumers: &Mutex<Consumers>) -> Result<(), String> {
+        consumers.lock().unregister(ConsumerId::Stream(id));
+        self.open.remove(&id).map(drop).ok_or_else(|| format!("No stream {}", id))
+    }
+
+    pub fn close_all(&mut self, consumers: &Mutex<Consumers>) {
+        let mut consumers = consumers.lock();
+        for (id, _) in self.open.drain() {
+            consumers.unregister(ConsumerId::Stream(id));
+        }
+    }
+
+    /// Sends the frame in `frame_buffer` to every stream due one, preparing
+    /// each off the capture thread.
+    pub fn feed(&mut self, frame_buffer: &FrameBuffer, consumers: &Arc<Mutex<Consumers>>) {
+        self.open.retain(|&id, stream| {
+            let closed = stream.closed.load(Ordering::Acquire);
+            if closed {
+                consumers.lock().unregister(ConsumerId::Stream(id));
+            }
+            !closed
+        });
+        if frame_buffer.data.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        for (&id, stream) in self.open.iter_mut() {
+            let due = stream.last_sent.is_none_or(|last| now.duration_since(last) >= stream.config.period());
+            if !due {
+                continue;
+            }
+            if stream.busy.swap(true, Ordering::AcqRel) {
+                consumers.lock().dropped(ConsumerId::Stream(id));
+                continue;
+            }
+            stream.last_sent = Some(now);
+
+            let config = stream.config;
+            let channel = stream.channel.clone();
+            let (busy, closed) = (stream.busy.clone(), stream.closed.clone());
+            let (data, width, height) = (frame_buffer.data.clone(), frame_buffer.width, frame_buffer.height);
+            let meta = frame_buffer.meta.clone();
+            let consumers = consumers.clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                match prepare(&config, data, width, height) {
+                    Ok((data, width, height)) => {
+                        let frame = StreamFrame {
+                            sequence: meta.sequence,
+                            width,
+                            height,
+                            codec: config.codec,
+                            channels: config.channels,
+                            data,
+                            meta,
+                        };
+                        match channel.send(frame) {
+                            Ok(()) => consumers.lock().delivered(ConsumerId::Stream(id)),
+                            Err(e) => {
+                                debug!("Stream closed: {:?}", e);
+                                closed.store(true, Ordering::Release);
+                            }
+                        }
+                    }
+                    Err(e) => debug!("Failed to prepare stream frame: {}", e),
+                }
+                busy.store(false, Ordering::Release);
+            });
+        }
+    }
+}
+
+/// Scales, reduces and encodes an RGBA frame as `config` asks.
+fn prepare(config: &StreamConfig, data: Vec<u8>, width: u32, height: u32) -> Result<(Vec<u8>, u32, u32), String> {
+    let bounds = (config.max_width.unwrap_or(width), config.max_height.unwrap_or(height));
+    let (data, width, height) = if width > bounds.0 || height > bounds.1 {
+        let (out_width, out_height) = crate::fit_size(width, height, bounds);
+        let ratio = width as f32 / out_width as f32;
+        let crop = Rect { x: 0, y: 0, width, height };
+        let downscale = Downscale::Auto.resolve(ratio);
+        let scaled = resample::resample(&data, width, crop, out_width, out_height, Interpolation::Bilinear, downscale);
+        (scaled, out_width, out_height)
+    } else {
+        (data, width, height)
+    };
+
+    let data = match config.channels {
+        StreamChannels::Rgba => data,
+        StreamChannels::Gray => {
+            let luma = |p: &[u8]| ((p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114 + 500) / 1000) as u8;
+            match config.codec {
+                StreamCodec::Raw => data.chunks_exact(4).map(luma).collect(),
+                // The encoder takes RGBA; equal channels still compress well
+                StreamCodec::Webp { .. } => data
+                    .chunks_exact(4)
+                    .flat_map(|p| {
+                        let y = luma(p);
+                        [y, y, y, p[3]]
+                    })
+                    .collect(),
+            }
+        }
+    };
+
+    let data = match config.codec {
+        StreamCodec::Raw => data,
+        StreamCodec::Webp { quality } => webp::encode(&data, width, height, quality, false)?,
+    };
+    Ok((data, width, height))
+}
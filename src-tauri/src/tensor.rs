@@ -0,0 +1,59 @@
+//! The frame as `f32` RGB values, laid out for ML pipelines.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TensorLayout {
+    /// Height, width, channels: each pixel's RGB together.
+    Hwc,
+    /// Channels, height, width: one plane per channel.
+    Chw,
+}
+
+/// Per-channel constants applied as `(value - mean) / std` after scaling
+/// to 0..1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Normalization {
+    mean: [f32; 3],
+    std: [f32; 3],
+}
+
+impl Normalization {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mean.iter().chain(&self.std).any(|v| !v.is_finite()) || self.std.contains(&0.0) {
+            return Err("Normalization constants must be finite with non-zero std".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Converts an RGBA frame to `f32` RGB in `layout`, dropping alpha. Values
+/// are in 0..1 unless `normalize` is given.
+pub fn to_tensor(data: &[u8], width: u32, height: u32, layout: TensorLayout, normalize: Option<Normalization>) -> Vec<f32> {
+    let Normalization { mean, std } = normalize.unwrap_or(Normalization { mean: [0.0; 3], std: [1.0; 3] });
+    let scale = std.map(|s| 1.0 / (255.0 * s));
+    let offset: [f32; 3] = std::array::from_fn(|c| mean[c] / std[c]);
+    let value = |p: &[u8], c: usize| p[c] as f32 * scale[c] - offset[c];
+
+    let pixels = (width * height) as usize;
+    let mut tensor = vec![0.0; pixels * 3];
+    match layout {
+        TensorLayout::Hwc => {
+            for (out, p) in tensor.chunks_exact_mut(3).zip(data.chunks_exact(4)) {
+                for (c, out) in out.iter_mut().enumerate() {
+                    *out = value(p, c);
+                }
+            }
+        }
+        TensorLayout::Chw => {
+            let (r, rest) = tensor.split_at_mut(pixels);
+            let (g, b) = rest.split_at_mut(pixels);
+            for (i, p) in data.chunks_exact(4).take(pixels).enumerate() {
+                r[i] = value(p, 0);
+                g[i] = value(p, 1);
+                b[i] = value(p, 2);
+            }
+        }
+    }
+    tensor
+}
@@ -0,0 +1,110 @@
+//! Scheduling of the capture thread.
+//!
+//! The capture loop runs on a dedicated thread so its OS priority and
+//! affinity can be tuned without affecting the async runtime's workers; the
+//! tuning ends with the thread.
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::System::Threading::{
+    GetCurrentProcess, GetCurrentThread, GetProcessAffinityMask, GetThreadPriority, SetThreadAffinityMask,
+    SetThreadPriority, THREAD_PRIORITY, THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL,
+    THREAD_PRIORITY_HIGHEST, THREAD_PRIORITY_IDLE, THREAD_PRIORITY_LOWEST, THREAD_PRIORITY_NORMAL,
+    THREAD_PRIORITY_TIME_CRITICAL,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThreadPriority {
+    Idle,
+    Lowest,
+    BelowNormal,
+    #[default]
+    Normal,
+    AboveNormal,
+    Highest,
+    TimeCritical,
+}
+
+impl ThreadPriority {
+    const ALL: [ThreadPriority; 7] = [
+        ThreadPriority::Idle,
+        ThreadPriority::Lowest,
+        ThreadPriority::BelowNormal,
+        ThreadPriority::Normal,
+        ThreadPriority::AboveNormal,
+        ThreadPriority::Highest,
+        ThreadPriority::TimeCritical,
+    ];
+
+    fn level(self) -> THREAD_PRIORITY {
+        match self {
+            ThreadPriority::Idle => THREAD_PRIORITY_IDLE,
+            ThreadPriority::Lowest => THREAD_PRIORITY_LOWEST,
+            ThreadPriority::BelowNormal => THREAD_PRIORITY_BELOW_NORMAL,
+            ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+            ThreadPriority::AboveNormal => THREAD_PRIORITY_ABOVE_NORMAL,
+            ThreadPriority::Highest => THREAD_PRIORITY_HIGHEST,
+            ThreadPriority::TimeCritical => THREAD_PRIORITY_TIME_CRITICAL,
+        }
+    }
+}
+
+/// Priority and core pinning for the capture thread.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThreadTuning {
+    pub priority: ThreadPriority,
+    /// Bit mask of logical cores to run on; `None` allows every core the process may use.
+    pub affinity: Option<usize>,
+}
+
+impl ThreadTuning {
+    /// Checks the affinity mask only names cores this process may use, as
+    /// one saved on another machine might not.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(mask) = self.affinity {
+            if mask == 0 || mask & !process_affinity()? != 0 {
+                return Err("Affinity mask includes cores unavailable to this process".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds an affinity mask from core indices, checking the process may run on each.
+pub fn affinity_mask(cores: &[u32]) -> Result<usize, String> {
+    let allowed = process_affinity()?;
+    let mut mask = 0usize;
+    for &core in cores {
+        let bit = 1usize.checked_shl(core).filter(|bit| allowed & bit != 0);
+        mask |= bit.ok_or_else(|| format!("Core {} isn't available to this process", core))?;
+    }
+    if mask == 0 {
+        return Err("At least one core is required".to_string());
+    }
+    Ok(mask)
+}
+
+/// Applies `tuning` to the calling thread.
+pub fn apply(tuning: &ThreadTuning) -> Result<(), String> {
+    let thread = unsafe { GetCurrentThread() };
+    unsafe { SetThreadPriority(thread, tuning.priority.level()) }.map_err(|e| e.to_string())?;
+    let mask = match tuning.affinity {
+        Some(mask) => mask,
+        None => process_affinity()?,
+    };
+    if unsafe { SetThreadAffinityMask(thread, mask) } == 0 {
+        return Err(windows::core::Error::from_win32().to_string());
+    }
+    Ok(())
+}
+
+/// The calling thread's priority, or `None` if it isn't one of the named levels.
+pub fn current_priority() -> Option<ThreadPriority> {
+    let level = unsafe { GetThreadPriority(GetCurrentThread()) };
+    ThreadPriority::ALL.into_iter().find(|priority| priority.level().0 == level)
+}
+
+fn process_affinity() -> Result<usize, String> {
+    let (mut process, mut system) = (0usize, 0usize);
+    unsafe { GetProcessAffinityMask(GetCurrentProcess(), &mut process, &mut system) }.map_err(|e| e.to_string())?;
+    Ok(process)
+}
@@ -0,0 +1,265 @@
+//! Recording output frames to an MP4 file with Media Foundation.
+//!
+//! Encoding runs on its own thread behind a short queue; frames arriving
+//! while it is full are dropped rather than stalling capture. Each frame is
+//! stamped with its capture time, so the file plays back at the pace the
+//! frames were captured even when the rate varies.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::Instant;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use win_desktop_duplication::co_init;
+use windows::core::{GUID, HSTRING};
+use windows::Win32::Media::MediaFoundation::{
+    IMFSinkWriter, MFCreateAttributes, MFCreateMediaType, MFCreateMemoryBuffer, MFCreateSample,
+    MFCreateSinkWriterFromURL, MFMediaType_Video, MFShutdown, MFStartup, MFVideoFormat_H264, MFVideoFormat_HEVC,
+    MFVideoFormat_RGB32, MFVideoInterlace_Progressive, MFSTARTUP_FULL, MF_API_VERSION, MF_MT_AVG_BITRATE,
+    MF_MT_DEFAULT_STRIDE, MF_MT_FRAME_RATE, MF_MT_FRAME_SIZE, MF_MT_INTERLACE_MODE, MF_MT_MAJOR_TYPE,
+    MF_MT_PIXEL_ASPECT_RATIO, MF_MT_SUBTYPE, MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, MF_SDK_VERSION,
+};
+
+use crate::resample::{self, Downscale, Interpolation};
+use crate::Rect;
+
+/// Frames waiting for the encoder before new ones are dropped.
+const QUEUE_FRAMES: usize = 4;
+/// Nominal rate written to the file; actual timing comes from timestamps.
+const NOMINAL_FPS: u32 = 60;
+/// Media Foundation times are in 100 ns units.
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoCodec {
+    H264,
+    /// Needs an HEVC encoder, which not every system has.
+    Hevc,
+}
+
+impl VideoCodec {
+    fn subtype(self) -> GUID {
+        match self {
+            VideoCodec::H264 => MFVideoFormat_H264,
+            VideoCodec::Hevc => MFVideoFormat_HEVC,
+        }
+    }
+}
+
+/// Returned by `stop_video_recording`.
+#[derive(Clone, Debug, Serialize)]
+pub struct RecordingSummary {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    frames: u64,
+    /// Frames dropped because the encoder fell behind.
+    dropped: u64,
+    duration_ms: u64,
+}
+
+struct QueuedFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    captured_at: Instant,
+}
+
+/// A recording in progress.
+pub struct VideoRecording {
+    sender: SyncSender<QueuedFrame>,
+    dropped: u64,
+    thread: JoinHandle<Result<RecordingSummary, String>>,
+}
+
+impl VideoRecording {
+    /// Starts writing `width`×`height` video to `path`. Frames of any other
+    /// size are scaled to fit and centred on `fill`.
+    pub fn start(
+        path: PathBuf,
+        codec: VideoCodec,
+        bitrate_kbps: u32,
+        width: u32,
+        height: u32,
+        fill: [u8; 4],
+    ) -> Result<Self, String> {
+        // Encoders only take even dimensions
+        let (width, height) = (width & !1, height & !1);
+        if width == 0 || height == 0 {
+            return Err("Frame too small to record".to_string());
+        }
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_FRAMES);
+        let (ready_sender, ready) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("video-encoder".to_string())
+            .spawn(move || {
+                co_init();
+                if let Err(e) = unsafe { MFStartup((MF_SDK_VERSION << 16) | MF_API_VERSION, MFSTARTUP_FULL) } {
+                    let message = format!("Media Foundation unavailable: {}", e);
+                    let _ = ready_sender.send(Err(message.clone()));
+                    return Err(message);
+                }
+                let result = match open_writer(&path, codec, bitrate_kbps, width, height) {
+                    Ok((writer, stream)) => {
+                        let _ = ready_sender.send(Ok(()));
+                        let encoder = Encoder { writer, stream, width, height, fill };
+                        encoder.run(receiver).map(|(frames, duration_ms)| RecordingSummary {
+                            path,
+                            width,
+                            height,
+                            frames,
+                            dropped: 0,
+                            duration_ms,
+                        })
+                    }
+                    Err(e) => {
+                        let _ = ready_sender.send(Err(e.clone()));
+                        Err(e)
+                    }
+                };
+                let _ = unsafe { MFShutdown() };
+                result
+            })
+            .map_err(|e| e.to_string())?;
+
+        ready.recv().map_err(|_| "Encoder thread exited".to_string())??;
+        Ok(VideoRecording { sender, dropped: 0, thread })
+    }
+
+    /// Queues an RGBA frame, dropping it if the encoder is behind. Returns
+    /// whether it was queued.
+    pub fn push(&mut self, data: Vec<u8>, width: u32, height: u32, captured_at: Instant) -> bool {
+        match self.sender.try_send(QueuedFrame { data, width, height, captured_at }) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                false
+            }
+            // The encoder failed; `stop` reports why
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    /// Writes out queued frames and finalizes the file.
+    pub fn stop(self) -> Result<RecordingSummary, String> {
+        drop(self.sender);
+        let summary = self.thread.join().map_err(|_| "Encoder thread panicked".to_string())??;
+        Ok(RecordingSummary { dropped: self.dropped, ..summary })
+    }
+}
+
+/// Opens a writer encoding BGRX frames to `path`, with the index of its stream.
+fn open_writer(
+    path: &Path,
+    codec: VideoCodec,
+    bitrate_kbps: u32,
+    width: u32,
+    height: u32,
+) -> Result<(IMFSinkWriter, u32), String> {
+    let frame_size = ((width as u64) << 32) | height as u64;
+    unsafe {
+        let mut attributes = None;
+        MFCreateAttributes(&mut attributes, 1).map_err(|e| e.to_string())?;
+        let attributes = attributes.ok_or("Failed to create writer attributes")?;
+        attributes.SetUINT32(&MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, 1).map_err(|e| e.to_string())?;
+        let writer = MFCreateSinkWriterFromURL(&HSTRING::from(path.as_os_str()), None, &attributes)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+
+        let output = MFCreateMediaType().map_err(|e| e.to_string())?;
+        output.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video).map_err(|e| e.to_string())?;
+        output.SetGUID(&MF_MT_SUBTYPE, &codec.subtype()).map_err(|e| e.to_string())?;
+        output.SetUINT32(&MF_MT_AVG_BITRATE, bitrate_kbps.saturating_mul(1000)).map_err(|e| e.to_string())?;
+        output.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32).map_err(|e| e.to_string())?;
+        output.SetUINT64(&MF_MT_FRAME_SIZE, frame_size).map_err(|e| e.to_string())?;
+        output.SetUINT64(&MF_MT_FRAME_RATE, ((NOMINAL_FPS as u64) << 32) | 1).map_err(|e| e.to_string())?;
+        output.SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, (1 << 32) | 1).map_err(|e| e.to_string())?;
+        let stream = writer
+            .AddStream(&output)
+            .map_err(|e| format!("No {:?} encoder for {}x{}: {}", codec, width, height, e))?;
+
+        // Top-down BGRX; the writer inserts the conversion the encoder needs
+        let input = MFCreateMediaType().map_err(|e| e.to_string())?;
+        input.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video).map_err(|e| e.to_string())?;
+        input.SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_RGB32).map_err(|e| e.to_string())?;
+        input.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32).map_err(|e| e.to_string())?;
+        input.SetUINT32(&MF_MT_DEFAULT_STRIDE, width * 4).map_err(|e| e.to_string())?;
+        input.SetUINT64(&MF_MT_FRAME_SIZE, frame_size).map_err(|e| e.to_string())?;
+        input.SetUINT64(&MF_MT_FRAME_RATE, ((NOMINAL_FPS as u64) << 32) | 1).map_err(|e| e.to_string())?;
+        input.SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, (1 << 32) | 1).map_err(|e| e.to_string())?;
+        writer.SetInputMediaType(stream, &input, None).map_err(|e| e.to_string())?;
+        writer.BeginWriting().map_err(|e| e.to_string())?;
+        Ok((writer, stream))
+    }
+}
+
+struct Encoder {
+    writer: IMFSinkWriter,
+    stream: u32,
+    width: u32,
+    height: u32,
+    fill: [u8; 4],
+}
+
+impl Encoder {
+    /// Encodes frames until the sender is dropped, returning how many were
+    /// written and the time they span.
+    fn run(self, receiver: Receiver<QueuedFrame>) -> Result<(u64, u64), String> {
+        let mut first: Option<Instant> = None;
+        let mut last_time = 0u64;
+        let mut frames = 0u64;
+        for frame in receiver {
+            let started = *first.get_or_insert(frame.captured_at);
+            let time = (frame.captured_at.saturating_duration_since(started).as_nanos() / 100) as u64;
+            // Samples must advance even if two frames share a timestamp
+            let time = if frames > 0 { time.max(last_time + 1) } else { time };
+            if let Err(e) = self.write(frame, time) {
+                warn!("Failed to encode video frame: {}", e);
+                continue;
+            }
+            last_time = time;
+            frames += 1;
+        }
+        unsafe { self.writer.Finalize() }.map_err(|e| format!("Failed to finalize video: {}", e))?;
+        Ok((frames, last_time / (TICKS_PER_SECOND / 1000)))
+    }
+
+    fn write(&self, frame: QueuedFrame, time: u64) -> Result<(), String> {
+        let data = if (frame.width, frame.height) == (self.width, self.height) {
+            frame.data
+        } else {
+            let (fit_width, fit_height) = crate::fit_size(frame.width, frame.height, (self.width, self.height));
+            let crop = Rect { x: 0, y: 0, width: frame.width, height: frame.height };
+            let ratio = frame.width as f32 / fit_width as f32;
+            let scaled = resample::resample(
+                &frame.data,
+                frame.width,
+                crop,
+                fit_width,
+                fit_height,
+                Interpolation::Bilinear,
+                Downscale::Auto.resolve(ratio),
+            );
+            crate::center_in(scaled, fit_width, fit_height, self.width, self.height, self.fill).0
+        };
+
+        let len = data.len() as u32;
+        unsafe {
+            let buffer = MFCreateMemoryBuffer(len).map_err(|e| e.to_string())?;
+            let mut target = std::ptr::null_mut();
+            buffer.Lock(&mut target, None, None).map_err(|e| e.to_string())?;
+            let target = std::slice::from_raw_parts_mut(target, data.len());
+            for (dst, src) in target.chunks_exact_mut(4).zip(data.chunks_exact(4)) {
+                dst.copy_from_slice(&[src[2], src[1], src[0], 255]);
+            }
+            buffer.Unlock().map_err(|e| e.to_string())?;
+            buffer.SetCurrentLength(len).map_err(|e| e.to_string())?;
+
+            let sample = MFCreateSample().map_err(|e| e.to_string())?;
+            sample.AddBuffer(&buffer).map_err(|e| e.to_string())?;
+            sample.SetSampleTime(time as i64).map_err(|e| e.to_string())?;
+            sample.SetSampleDuration((TICKS_PER_SECOND / NOMINAL_FPS as u64) as i64).map_err(|e| e.to_string())?;
+            self.writer.WriteSample(self.stream, &sample).map_err(|e| e.to_string())
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Visualizations rendered in place of the captured image, for spotting
+//! edges, flicker and unexpected redraws.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ocr::luma;
+
+/// Luma change below which a pixel isn't highlighted as motion.
+const MOTION_THRESHOLD: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Visualization {
+    /// Sobel edge magnitude as grayscale.
+    Edges,
+    /// Pixels that changed since the previous frame in red, over a dimmed
+    /// grayscale of the frame.
+    Motion,
+}
+
+/// Renders visualizations, keeping the previous frame's luma for `Motion`.
+#[derive(Default)]
+pub struct Visualizer {
+    previous: Vec<u8>,
+    size: (u32, u32),
+}
+
+impl Visualizer {
+    /// Replaces a `width`×`height` RGBA frame with `mode`'s rendering,
+    /// blended over the original by `opacity` in `0.0..=1.0`. Alpha is left
+    /// as captured.
+    pub fn apply(&mut self, data: &mut [u8], width: u32, height: u32, mode: Visualization, opacity: f32) {
+        let gray: Vec<u8> = data.chunks_exact(4).map(|p| luma(p[0], p[1], p[2])).collect();
+        let rendered = match mode {
+            Visualization::Edges => sobel(&gray, width as usize, height as usize)
+                .into_iter()
+                .map(|m| [m, m, m])
+                .collect(),
+            Visualization::Motion => {
+                // Nothing to compare against on the first frame or after a resize
+                let previous = if self.size == (width, height) { &self.previous[..] } else { &gray[..] };
+                gray.iter()
+                    .zip(previous)
+                    .map(|(&now, &before)| {
+                        let dim = now / 3;
+                        match now.abs_diff(before) {
+                            change if change >= MOTION_THRESHOLD => [dim.saturating_add(change.max(128)), dim, dim],
+                            _ => [dim, dim, dim],
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+        self.previous = gray;
+        self.size = (width, height);
+
+        let weight = (opacity.clamp(0.0, 1.0) * 256.0) as u32;
+        for (pixel, shown) in data.chunks_exact_mut(4).zip(rendered) {
+            for (channel, value) in pixel[..3].iter_mut().zip(shown) {
+                *channel = ((value as u32 * weight + *channel as u32 * (256 - weight) + 128) >> 8) as u8;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Sobel gradient magnitude of a grayscale image; border pixels are 0.
+fn sobel(gray: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut out = vec![0u8; gray.len()];
+    if width < 3 || height < 3 {
+        return out;
+    }
+    let at = |x: usize, y: usize| gray[y * width + x] as i32;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let gx = at(x + 1, y - 1) + 2 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1) - 2 * at(x - 1, y) - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1) - 2 * at(x, y - 1) - at(x + 1, y - 1);
+            out[y * width + x] = ((gx * gx + gy * gy) as f32).sqrt().min(255.0) as u8;
+        }
+    }
+    out
+}
@@ -0,0 +1,149 @@
+//! A logo blended into a corner of every output frame, marking where
+//! exported and streamed frames came from.
+
+use serde::{Deserialize, Serialize};
+
+use crate::resample::{self, Downscale, Interpolation};
+use crate::{pixel_offset, Rect};
+
+/// Largest share of the frame's width and height the watermark may cover;
+/// larger images are scaled down to fit.
+const MAX_SHARE: u32 = 4;
+/// Gap between the watermark and the frame edges, in output pixels.
+const MARGIN: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+pub struct Watermark {
+    /// Decoded image, RGBA with straight alpha.
+    image: Vec<u8>,
+    width: u32,
+    height: u32,
+    corner: Corner,
+    /// Out of 256.
+    opacity: u32,
+    /// The image scaled for the output size it was last drawn on.
+    scaled: Option<Scaled>,
+}
+
+struct Scaled {
+    output: (u32, u32),
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl Watermark {
+    /// Decodes a PNG or WebP image.
+    pub fn decode(bytes: &[u8], corner: Corner, opacity: f32) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err("Opacity must be between 0 and 1".to_string());
+        }
+        let image = image::load_from_memory(bytes).map_err(|e| format!("Failed to decode watermark: {}", e))?;
+        let image = image.into_rgba8();
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Err("Watermark image is empty".to_string());
+        }
+        Ok(Watermark {
+            image: image.into_raw(),
+            width,
+            height,
+            corner,
+            opacity: (opacity * 256.0) as u32,
+            scaled: None,
+        })
+    }
+
+    /// Blends the watermark over a `width`×`height` RGBA frame, keeping the
+    /// frame premultiplied if it is.
+    pub fn draw(&mut self, data: &mut [u8], width: u32, height: u32, premultiplied: bool) {
+        self.rescale(width, height);
+        let Some(scaled) = self.scaled.as_ref().filter(|scaled| scaled.width > 0 && scaled.height > 0) else {
+            return;
+        };
+        let x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => MARGIN,
+            Corner::TopRight | Corner::BottomRight => width.saturating_sub(scaled.width + MARGIN),
+        };
+        let y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => height.saturating_sub(scaled.height + MARGIN),
+        };
+
+        for row in 0..scaled.height.min(height.saturating_sub(y)) {
+            for column in 0..scaled.width.min(width.saturating_sub(x)) {
+                let src = &scaled.data[pixel_offset(column, row, scaled.width)..][..4];
+                let alpha = src[3] as u32 * self.opacity / 256;
+                if alpha == 0 {
+                    continue;
+                }
+                let dst = pixel_offset(x + column, y + row, width);
+                let Some(dst) = data.get_mut(dst..dst + 4) else {
+                    continue;
+                };
+                over(dst, [src[0], src[1], src[2]], alpha, premultiplied);
+            }
+        }
+    }
+
+    /// Caches the image scaled for a `width`×`height` output.
+    fn rescale(&mut self, width: u32, height: u32) {
+        if self.scaled.as_ref().is_none_or(|scaled| scaled.output != (width, height)) {
+            let bounds = ((width / MAX_SHARE).max(1), (height / MAX_SHARE).max(1));
+            let (scaled_width, scaled_height) = if self.width > bounds.0 || self.height > bounds.1 {
+                crate::fit_size(self.width, self.height, bounds)
+            } else {
+                (self.width, self.height)
+            };
+            let data = if (scaled_width, scaled_height) == (self.width, self.height) {
+                self.image.clone()
+            } else {
+                // Straight alpha bleeds dark fringes when filtered; premultiply around it
+                let premultiplied: Vec<u8> = self
+                    .image
+                    .chunks_exact(4)
+                    .flat_map(|p| {
+                        let a = p[3] as u32;
+                        [(p[0] as u32 * a / 255) as u8, (p[1] as u32 * a / 255) as u8, (p[2] as u32 * a / 255) as u8, p[3]]
+                    })
+                    .collect();
+                let crop = Rect { x: 0, y: 0, width: self.width, height: self.height };
+                let ratio = self.width as f32 / scaled_width as f32;
+                let downscale = Downscale::Auto.resolve(ratio);
+                resample::resample(&premultiplied, self.width, crop, scaled_width, scaled_height, Interpolation::Bilinear, downscale)
+                    .chunks_exact(4)
+                    .flat_map(|p| {
+                        let a = p[3] as u32;
+                        let unpremultiply = |c: u8| (c as u32 * 255).checked_div(a).unwrap_or(0).min(255) as u8;
+                        [unpremultiply(p[0]), unpremultiply(p[1]), unpremultiply(p[2]), p[3]]
+                    })
+                    .collect()
+            };
+            self.scaled = Some(Scaled { output: (width, height), data, width: scaled_width, height: scaled_height });
+        }
+    }
+}
+
+/// Composites `rgb` at `alpha` out of 255 over one output pixel.
+fn over(dst: &mut [u8], rgb: [u8; 3], alpha: u32, premultiplied: bool) {
+    let keep = 255 - alpha;
+    let dst_alpha = dst[3] as u32;
+    let out_alpha = alpha + dst_alpha * keep / 255;
+    for (channel, &src) in dst[..3].iter_mut().zip(&rgb) {
+        let value = if premultiplied {
+            (src as u32 * alpha + *channel as u32 * keep) / 255
+        } else {
+            // Straight alpha weights the frame's colour by its own coverage
+            (src as u32 * alpha + *channel as u32 * dst_alpha * keep / 255).checked_div(out_alpha).unwrap_or(0)
+        };
+        *channel = value.min(255) as u8;
+    }
+    dst[3] = out_alpha.min(255) as u8;
+}
@@ -0,0 +1,36 @@
+//! WebP encoding of processed frames.
+//!
+//! The pure-Rust encoder only writes lossless WebP, so lossy output is made
+//! by quantizing colours first: fewer distinct values compress far better
+//! while alpha and the overall image survive intact.
+
+use image::codecs::webp::WebPEncoder;
+use image::ExtendedColorType;
+
+/// Encodes a `width`×`height` RGBA frame. Unless `lossless`, `quality` in
+/// `0..=100` controls how many low bits of each colour channel are dropped.
+pub fn encode(data: &[u8], width: u32, height: u32, quality: u8, lossless: bool) -> Result<Vec<u8>, String> {
+    let quantized;
+    let pixels = if lossless || quality >= 100 {
+        data
+    } else {
+        // 100 keeps every bit, 0 keeps the top two
+        let dropped = 6 - (quality.min(99) as u32 * 7 / 100).min(6);
+        let mask = 0xffu8 << dropped;
+        let round = (1u8 << dropped) >> 1;
+        quantized = data
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let q = |c: u8| c.saturating_add(round) & mask;
+                [q(p[0]), q(p[1]), q(p[2]), p[3]]
+            })
+            .collect::<Vec<u8>>();
+        &quantized
+    };
+
+    let mut encoded = Vec::new();
+    WebPEncoder::new_lossless(&mut encoded)
+        .encode(pixels, width, height, ExtendedColorType::Rgba8)
+        .map_err(|e| e.to_string())?;
+    Ok(encoded)
+}